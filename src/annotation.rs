@@ -0,0 +1,130 @@
+// Traditional move annotations, assigned from how much a move lost compared to the engine's best
+// move. Losses are from the point of view of the player who moved, negative when the move turned
+// out better than the engine expected.
+
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Annotation {
+    Good,
+    Interesting,
+    Dubious,
+    Mistake,
+    Blunder,
+}
+
+impl Annotation {
+    pub fn glyph(self) -> &'static str {
+        match self {
+            Annotation::Good => "!",
+            Annotation::Interesting => "!?",
+            Annotation::Dubious => "?!",
+            Annotation::Mistake => "?",
+            Annotation::Blunder => "??",
+        }
+    }
+
+    // The SGF move annotation property and its value, e.g. `BM[2]`
+    pub fn sgf_property(self) -> (&'static str, &'static str) {
+        match self {
+            Annotation::Good => ("TE", "1"),
+            Annotation::Interesting => ("IT", ""),
+            Annotation::Dubious => ("DO", ""),
+            Annotation::Mistake => ("BM", "1"),
+            Annotation::Blunder => ("BM", "2"),
+        }
+    }
+
+    // Most extreme bad marks first, then most extreme good ones
+    fn rank(self) -> u8 {
+        match self {
+            Annotation::Blunder => 0,
+            Annotation::Mistake => 1,
+            Annotation::Dubious => 2,
+            Annotation::Good => 3,
+            Annotation::Interesting => 4,
+        }
+    }
+
+    fn is_good(self) -> bool {
+        matches!(self, Annotation::Good | Annotation::Interesting)
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.glyph())
+    }
+}
+
+impl Serialize for Annotation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.glyph())
+    }
+}
+
+// Either being reached is enough. Win rates are from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loss {
+    pub points: f32,
+    pub winrate: f32,
+}
+
+impl Loss {
+    pub fn new(points: f32, winrate: f32) -> Self {
+        Self { points, winrate }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotator {
+    // Most extreme first, the first one reached wins
+    thresholds: Vec<(Annotation, Loss)>,
+}
+
+impl Default for Annotator {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![
+                (Annotation::Blunder, Loss::new(6.0, 0.2)),
+                (Annotation::Mistake, Loss::new(3.0, 0.1)),
+                (Annotation::Dubious, Loss::new(1.5, 0.05)),
+                (Annotation::Good, Loss::new(-3.0, -0.1)),
+                (Annotation::Interesting, Loss::new(-1.0, -0.03)),
+            ],
+        }
+    }
+}
+
+impl Annotator {
+    // Thresholds for good moves are negative losses, i.e. gains
+    pub fn threshold(mut self, annotation: Annotation, loss: Loss) -> Self {
+        match self.thresholds.iter_mut().find(|(a, _)| *a == annotation) {
+            Some((_, threshold)) => *threshold = loss,
+            None => self.thresholds.push((annotation, loss)),
+        }
+        self.thresholds
+            .sort_by_key(|(annotation, _)| annotation.rank());
+        self
+    }
+
+    pub fn without(mut self, annotation: Annotation) -> Self {
+        self.thresholds.retain(|(a, _)| *a != annotation);
+        self
+    }
+
+    pub fn annotate(&self, points_lost: f32, winrate_dropped: f32) -> Option<Annotation> {
+        self.thresholds
+            .iter()
+            .find(|(annotation, threshold)| {
+                if annotation.is_good() {
+                    points_lost <= threshold.points || winrate_dropped <= threshold.winrate
+                } else {
+                    points_lost >= threshold.points || winrate_dropped >= threshold.winrate
+                }
+            })
+            .map(|(annotation, _)| *annotation)
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{MuxClient, MuxConnection, MuxError, MuxQuota};
+
+// Who an API key belongs to. The name becomes the mux connection's prefix, so it must be a valid
+// prefix and each principal can hold one connection at a time.
+#[derive(Clone, Debug)]
+pub struct Principal {
+    pub name: String,
+    pub quota: MuxQuota,
+}
+
+// Verifies credentials before an engine is shared with whoever presents them
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, api_key: &str) -> Option<Principal>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StaticApiKeys {
+    keys: Vec<(String, Principal)>,
+}
+
+impl StaticApiKeys {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn key(
+        mut self,
+        api_key: impl Into<String>,
+        name: impl Into<String>,
+        quota: MuxQuota,
+    ) -> Self {
+        self.keys.push((
+            api_key.into(),
+            Principal {
+                name: name.into(),
+                quota,
+            },
+        ));
+        self
+    }
+}
+
+impl Authenticator for StaticApiKeys {
+    // Every key is compared in full, so timing doesn't tell how much of a key was right
+    fn authenticate(&self, api_key: &str) -> Option<Principal> {
+        let mut found = None;
+        for (key, principal) in &self.keys {
+            if constant_time_eq(key.as_bytes(), api_key.as_bytes()) {
+                found = Some(principal);
+            }
+        }
+        found.cloned()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+impl MuxClient {
+    // Connects with the principal's name as prefix and under its quota
+    pub fn connect_authenticated(
+        &self,
+        authenticator: &dyn Authenticator,
+        api_key: &str,
+    ) -> Result<MuxConnection, MuxError> {
+        let principal = authenticator
+            .authenticate(api_key)
+            .ok_or(MuxError::Unauthorized)?;
+        self.connect_with_quota(principal.name, principal.quota)
+    }
+}
@@ -0,0 +1,207 @@
+// Analysis of position files too big to load at once. Every line of the input is a position,
+// either a JSON query as katago takes it or an SGF game whose last position is analyzed, and every
+// final result is written to the output as a JSON line as soon as it arrives. Lines katago or the
+// parser reject become katago style error lines, e.g. `{"id":"line-3","error":"..."}`, so one bad
+// position doesn't stop the run.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use futures_util::stream::FuturesUnordered;
+use futures_util::StreamExt;
+use serde_json::Value;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::sgf::SgfGame;
+use crate::{Client, ClientError, KataQuery, KataResponse, QueryId, Rules};
+
+const DEFAULT_CONCURRENCY: usize = 16;
+
+#[derive(Debug)]
+pub enum BatchError {
+    Io(io::Error),
+    // The engine went away, results still in flight are lost
+    Client(ClientError),
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BatchError::Io(err) => write!(f, "position file I/O failed: {err}"),
+            BatchError::Client(err) => write!(f, "batch analysis failed: {err}"),
+        }
+    }
+}
+
+impl Error for BatchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BatchError::Io(err) => Some(err),
+            BatchError::Client(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for BatchError {
+    fn from(err: io::Error) -> Self {
+        BatchError::Io(err)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub positions: u64,
+    pub results: u64,
+    pub errors: u64,
+}
+
+#[derive(Clone)]
+pub struct BatchAnalysis {
+    client: Client,
+    concurrency: usize,
+    default_rules: Option<Rules>,
+}
+
+impl BatchAnalysis {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            concurrency: DEFAULT_CONCURRENCY,
+            default_rules: None,
+        }
+    }
+
+    // How many positions are submitted before waiting for results, which bounds memory use
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    // For SGF games without a recognized RU property, which are errors otherwise
+    pub fn default_rules(mut self, rules: Rules) -> Self {
+        self.default_rules = Some(rules);
+        self
+    }
+
+    // Positions without an id get `line-N`, counting lines from 1. Results are written in the
+    // order they arrive, not in input order.
+    pub async fn run<R, W>(&self, input: R, output: W) -> Result<BatchSummary, BatchError>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut output = output;
+        let mut lines = input.lines();
+        let mut line_number = 0;
+        let mut summary = BatchSummary::default();
+        let mut in_flight = FuturesUnordered::new();
+        let mut eof = false;
+        loop {
+            while !eof && in_flight.len() < self.concurrency {
+                let Some(line) = lines.next_line().await? else {
+                    eof = true;
+                    break;
+                };
+                line_number += 1;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                summary.positions += 1;
+                let query = match self.parse(&line, line_number) {
+                    Ok(query) => query,
+                    Err((id, error)) => {
+                        summary.errors += 1;
+                        write_error(&mut output, id, error, None).await?;
+                        continue;
+                    }
+                };
+                let id = query.id().clone();
+                match self.client.submit(query) {
+                    Ok(handle) => in_flight.push(async move { (id, handle.results().await) }),
+                    Err(ClientError::Unsupported(reason)) => {
+                        summary.errors += 1;
+                        write_error(&mut output, id, reason, None).await?;
+                    }
+                    // Another line with the same id is still being analyzed
+                    Err(err @ ClientError::DuplicateId(_)) => {
+                        summary.errors += 1;
+                        write_error(&mut output, id, err.to_string(), Some("id".to_owned()))
+                            .await?;
+                    }
+                    Err(err) => return Err(BatchError::Client(err)),
+                }
+            }
+            let Some((id, results)) = in_flight.next().await else {
+                break;
+            };
+            match results {
+                Ok(results) => {
+                    for result in results {
+                        summary.results += 1;
+                        write_line(&mut output, &result).await?;
+                    }
+                }
+                Err(ClientError::Rejected { error, field }) => {
+                    summary.errors += 1;
+                    write_error(&mut output, id, error, field).await?;
+                }
+                Err(err) => return Err(BatchError::Client(err)),
+            }
+        }
+        output.flush().await?;
+        Ok(summary)
+    }
+
+    fn parse(&self, line: &str, line_number: usize) -> Result<KataQuery, (QueryId, String)> {
+        let line = line.trim();
+        if line.starts_with('(') {
+            let id = line_id(line_number);
+            let game = SgfGame::parse(line).map_err(|err| (id.clone(), err.to_string()))?;
+            let mut builder = game.query_builder(id.clone());
+            if let (None, Some(rules)) = (game.rules, self.default_rules) {
+                builder.rules(rules);
+            }
+            return builder.build().map_err(|err| (id, err.to_string()));
+        }
+        let mut value = serde_json::from_str::<Value>(line)
+            .map_err(|err| (line_id(line_number), err.to_string()))?;
+        let id = match value.get("id").and_then(Value::as_str) {
+            Some(id) => QueryId::from(id),
+            None => line_id(line_number),
+        };
+        if let Some(object) = value.as_object_mut() {
+            object
+                .entry("id")
+                .or_insert_with(|| Value::from(id.to_string()));
+        }
+        serde_json::from_value(value).map_err(|err| (id, err.to_string()))
+    }
+}
+
+fn line_id(line_number: usize) -> QueryId {
+    QueryId::from(format!("line-{line_number}"))
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(
+    output: &mut W,
+    response: &KataResponse,
+) -> io::Result<()> {
+    let mut line = serde_json::to_string(response).map_err(io::Error::other)?;
+    line.push('\n');
+    output.write_all(line.as_bytes()).await
+}
+
+async fn write_error<W: AsyncWrite + Unpin>(
+    output: &mut W,
+    id: QueryId,
+    error: String,
+    field: Option<String>,
+) -> io::Result<()> {
+    let response = KataResponse::Error {
+        id: Some(id),
+        error,
+        field,
+    };
+    write_line(output, &response).await
+}
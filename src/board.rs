@@ -0,0 +1,272 @@
+// Tracks the stones on the board as moves are played, e.g. to check moves before sending them or
+// to pair points with katago's ownership values. Boards don't need to be square, indices follow
+// katago's layout: row by row from the top left corner.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{Move, Player};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoardError {
+    OffBoard(Move),
+    Occupied(Move),
+    Suicide(Move),
+    Ko(Move),
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardError::OffBoard(point) => write!(f, "{point} is outside the board"),
+            BoardError::Occupied(point) => write!(f, "{point} is already occupied"),
+            BoardError::Suicide(point) => write!(f, "{point} would be suicide"),
+            BoardError::Ko(point) => write!(f, "{point} retakes a ko"),
+        }
+    }
+}
+
+impl Error for BoardError {}
+
+#[derive(Debug, Clone)]
+pub struct Board {
+    x_size: u8,
+    y_size: u8,
+    stones: Vec<Option<Player>>,
+    ko: Option<Move>,
+}
+
+impl Board {
+    pub fn new(x_size: u8, y_size: u8) -> Self {
+        Self {
+            x_size,
+            y_size,
+            stones: vec![None; x_size as usize * y_size as usize],
+            ko: None,
+        }
+    }
+
+    pub fn x_size(&self) -> u8 {
+        self.x_size
+    }
+
+    pub fn y_size(&self) -> u8 {
+        self.y_size
+    }
+
+    // Where `point` is in katago's ownership and policy arrays, None for passes and points off
+    // the board
+    pub fn index(&self, point: Move) -> Option<usize> {
+        point.index(self.x_size, self.y_size)
+    }
+
+    pub fn get(&self, point: Move) -> Option<Player> {
+        self.stones[self.index(point)?]
+    }
+
+    // Puts a setup stone down without playing it, so nothing is captured
+    pub fn place(&mut self, player: Player, point: Move) -> Result<(), BoardError> {
+        let index = self.index(point).ok_or(BoardError::OffBoard(point))?;
+        if self.stones[index].is_some() {
+            return Err(BoardError::Occupied(point));
+        }
+        self.stones[index] = Some(player);
+        self.ko = None;
+        Ok(())
+    }
+
+    // Plays a move and returns the stones it captured. Suicide is rejected, only a few rulesets
+    // allow it.
+    pub fn play(&mut self, player: Player, point: Move) -> Result<Vec<Move>, BoardError> {
+        if point == Move::Pass {
+            self.ko = None;
+            return Ok(Vec::new());
+        }
+        let index = self.index(point).ok_or(BoardError::OffBoard(point))?;
+        if self.stones[index].is_some() {
+            return Err(BoardError::Occupied(point));
+        }
+        if self.ko == Some(point) {
+            return Err(BoardError::Ko(point));
+        }
+
+        self.stones[index] = Some(player);
+        let mut captured = Vec::new();
+        for neighbour in self.neighbours(index) {
+            if self.stones[neighbour] == Some(player.opponent()) {
+                let (group, liberties) = self.group(neighbour);
+                if liberties == 0 {
+                    for stone in group {
+                        self.stones[stone] = None;
+                        captured.push(self.point(stone));
+                    }
+                }
+            }
+        }
+        let (group, liberties) = self.group(index);
+        if liberties == 0 {
+            self.stones[index] = None;
+            return Err(BoardError::Suicide(point));
+        }
+        // A single stone that captured a single stone and is left in atari can be retaken
+        // right away, which is what makes it a ko
+        self.ko = match (&captured[..], group.len(), liberties) {
+            ([taken], 1, 1) => Some(*taken),
+            _ => None,
+        };
+        Ok(captured)
+    }
+
+    // The point the last move made illegal to retake right away
+    pub(crate) fn ko(&self) -> Option<Move> {
+        self.ko
+    }
+
+    pub(crate) fn point(&self, index: usize) -> Move {
+        let x = (index % self.x_size as usize) as u8;
+        let row = (index / self.x_size as usize) as u8;
+        Move::point(x, self.y_size - 1 - row)
+    }
+
+    pub(crate) fn neighbours(&self, index: usize) -> impl Iterator<Item = usize> {
+        let (x_size, len) = (self.x_size as usize, self.stones.len());
+        let x = index % x_size;
+        [
+            (x > 0).then(|| index - 1),
+            (x + 1 < x_size).then(|| index + 1),
+            index.checked_sub(x_size),
+            Some(index + x_size).filter(|below| *below < len),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    // The stones connected to `index` and how many liberties they have
+    pub(crate) fn group(&self, index: usize) -> (Vec<usize>, usize) {
+        let player = self.stones[index];
+        let mut seen = vec![false; self.stones.len()];
+        let mut group = vec![index];
+        let mut liberties = 0;
+        seen[index] = true;
+        let mut next = 0;
+        while let Some(&stone) = group.get(next) {
+            next += 1;
+            for neighbour in self.neighbours(stone) {
+                if seen[neighbour] {
+                    continue;
+                }
+                seen[neighbour] = true;
+                match self.stones[neighbour] {
+                    None => liberties += 1,
+                    stone if stone == player => group.push(neighbour),
+                    _ => {}
+                }
+            }
+        }
+        (group, liberties)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KataResponse;
+
+    fn point(gtp: &str) -> Move {
+        gtp.parse().unwrap()
+    }
+
+    #[test]
+    fn off_board_on_non_square_boards() {
+        let mut board = Board::new(5, 3);
+        assert_eq!(
+            board.play(Player::Black, Move::point(5, 0)),
+            Err(BoardError::OffBoard(Move::point(5, 0)))
+        );
+        assert_eq!(
+            board.place(Player::Black, Move::point(0, 3)),
+            Err(BoardError::OffBoard(Move::point(0, 3)))
+        );
+        assert_eq!(board.play(Player::Black, point("E3")), Ok(Vec::new()));
+        assert_eq!(board.get(point("E3")), Some(Player::Black));
+    }
+
+    #[test]
+    fn captures_at_the_edges() {
+        let mut board = Board::new(5, 3);
+        // Bottom right corner
+        board.place(Player::White, point("E1")).unwrap();
+        board.play(Player::Black, point("D1")).unwrap();
+        assert_eq!(
+            board.play(Player::Black, point("E2")),
+            Ok(vec![point("E1")])
+        );
+        assert_eq!(board.get(point("E1")), None);
+        // Along the top edge, which is row 3 here
+        board.place(Player::White, point("B3")).unwrap();
+        board.place(Player::White, point("C3")).unwrap();
+        board.play(Player::Black, point("A3")).unwrap();
+        board.play(Player::Black, point("B2")).unwrap();
+        board.play(Player::Black, point("C2")).unwrap();
+        let mut captured = board.play(Player::Black, point("D3")).unwrap();
+        captured.sort_by_key(|captured| board.index(*captured));
+        assert_eq!(captured, vec![point("B3"), point("C3")]);
+    }
+
+    #[test]
+    fn suicide_and_ko_at_the_edges() {
+        let mut board = Board::new(5, 3);
+        board.place(Player::White, point("A2")).unwrap();
+        board.place(Player::White, point("B3")).unwrap();
+        assert_eq!(
+            board.play(Player::Black, point("A3")),
+            Err(BoardError::Suicide(point("A3")))
+        );
+        assert_eq!(board.get(point("A3")), None);
+
+        let mut board = Board::new(5, 3);
+        for (player, stone) in [
+            (Player::Black, "D1"),
+            (Player::Black, "E2"),
+            (Player::White, "C1"),
+            (Player::White, "D2"),
+        ] {
+            board.place(player, point(stone)).unwrap();
+        }
+        assert_eq!(
+            board.play(Player::White, point("E1")),
+            Ok(vec![point("D1")])
+        );
+        assert_eq!(
+            board.play(Player::Black, point("D1")),
+            Err(BoardError::Ko(point("D1")))
+        );
+    }
+
+    #[test]
+    fn ownership_of_non_square_boards() {
+        let ownership = (0..15)
+            .map(|index| index as f32 / 100.0)
+            .collect::<Vec<_>>();
+        let response: KataResponse = serde_json::from_value(serde_json::json!({
+            "id": "a",
+            "isDuringSearch": false,
+            "turnNumber": 0,
+            "moveInfos": [],
+            "rootInfo": {"winrate": 0.5, "scoreLead": 0.0, "scoreSelfplay": 0.0, "visits": 1},
+            "ownership": ownership,
+        }))
+        .unwrap();
+        let board = Board::new(5, 3);
+        for (gtp, index) in [("A3", 0), ("E3", 4), ("A2", 5), ("E1", 14)] {
+            assert_eq!(board.index(point(gtp)), Some(index));
+            assert_eq!(
+                response.ownership_at(point(gtp), 5, 3),
+                Some(index as f32 / 100.0)
+            );
+        }
+        assert_eq!(response.ownership_at(Move::point(5, 0), 5, 3), None);
+        // Arrays of another board size aren't indexed
+        assert_eq!(response.ownership_at(point("A1"), 4, 4), None);
+    }
+}
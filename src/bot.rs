@@ -0,0 +1,137 @@
+// When a bot should resign or pass. Both are easy to get wrong: resigning on one bad evaluation
+// throws won games away, and passing while stones are still dead in your territory loses them
+// under rules that score the board as it stands.
+
+use std::collections::VecDeque;
+
+use crate::{Board, Move, Player, Rules};
+
+// Ownership this sure counts as decided, weaker than `NEUTRAL` as dame or seki
+const SETTLED: f32 = 0.8;
+const NEUTRAL: f32 = 0.2;
+
+// Resigns once the bot's win rate stayed below the threshold for a number of its moves in a row
+#[derive(Clone, Debug)]
+pub struct ResignPolicy {
+    threshold: f32,
+    consecutive_moves: usize,
+    min_move_number: u32,
+    recent: VecDeque<f32>,
+}
+
+impl Default for ResignPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: 0.05,
+            consecutive_moves: 3,
+            min_move_number: 0,
+            recent: VecDeque::new(),
+        }
+    }
+}
+
+impl ResignPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn threshold(mut self, winrate: f32) -> Self {
+        self.threshold = winrate;
+        self
+    }
+
+    pub fn consecutive_moves(mut self, moves: usize) -> Self {
+        self.consecutive_moves = moves.max(1);
+        self
+    }
+
+    // Never resigns before this move, e.g. to give handicap games a chance
+    pub fn min_move_number(mut self, move_number: u32) -> Self {
+        self.min_move_number = move_number;
+        self
+    }
+
+    // Records the bot's win rate before its move at `move_number` and says whether to resign
+    pub fn observe(&mut self, move_number: u32, winrate: f32) -> bool {
+        self.recent.push_back(winrate);
+        while self.recent.len() > self.consecutive_moves {
+            self.recent.pop_front();
+        }
+        move_number >= self.min_move_number
+            && self.recent.len() == self.consecutive_moves
+            && self.recent.iter().all(|winrate| *winrate < self.threshold)
+    }
+
+    // For a new game
+    pub fn reset(&mut self) {
+        self.recent.clear();
+    }
+}
+
+// Passes only once every point is decided, and under rules without friendly passing, e.g.
+// Tromp-Taylor, only after dead stones have been captured since they'd count as alive
+#[derive(Clone, Debug)]
+pub struct PassPolicy {
+    require_opponent_pass: bool,
+    capture_dead_stones: bool,
+}
+
+impl PassPolicy {
+    pub fn for_rules(rules: Rules) -> Self {
+        Self {
+            require_opponent_pass: true,
+            capture_dead_stones: matches!(rules, Rules::TrompTaylor | Rules::StoneScoring),
+        }
+    }
+
+    // Whether passing first is fine too, otherwise only answering a pass is
+    pub fn require_opponent_pass(mut self, require: bool) -> Self {
+        self.require_opponent_pass = require;
+        self
+    }
+
+    pub fn capture_dead_stones(mut self, capture: bool) -> Self {
+        self.capture_dead_stones = capture;
+        self
+    }
+
+    // Ownership is from black's point of view, in katago's layout. False when it doesn't match
+    // the board.
+    pub fn should_pass(
+        &self,
+        board: &Board,
+        ownership: &[f32],
+        bot: Player,
+        opponent_passed: bool,
+    ) -> bool {
+        if self.require_opponent_pass && !opponent_passed {
+            return false;
+        }
+        if ownership.len() != board.x_size() as usize * board.y_size() as usize {
+            return false;
+        }
+        for y in 0..board.y_size() {
+            for x in 0..board.x_size() {
+                let point = Move::point(x, y);
+                let Some(index) = board.index(point) else {
+                    continue;
+                };
+                let owner = ownership[index];
+                if owner.abs() >= NEUTRAL && owner.abs() < SETTLED {
+                    return false;
+                }
+                // Dead opponent stones in the bot's area, its own dead stones are the
+                // opponent's job
+                let dead_opponent = match board.get(point) {
+                    Some(Player::Black) => bot == Player::White && owner <= -SETTLED,
+                    Some(Player::White) => bot == Player::Black && owner >= SETTLED,
+                    None => false,
+                };
+                if self.capture_dead_stones && dead_opponent {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::canonical::canonical_json;
+#[cfg(feature = "msgpack")]
+use crate::msgpack::{from_msgpack, to_msgpack};
+use crate::schema::{
+    migrate_results, read_results, schema_version, stamp_results, SchemaError, SCHEMA_VERSION,
+};
+use crate::sha256::Sha256;
+use crate::{KataQuery, KataResponse};
+
+static PARTIAL_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Final results stored on disk, one file per query. Queries are keyed by everything that affects
+// their results, i.e. the position and the settings, but not their id. The engine's model and
+// config aren't part of the query, so caches shared between them need a distinct `fingerprint`.
+// Entries carry the schema version they were written with and are migrated as they're read.
+#[derive(Clone, Debug)]
+pub struct ResultCache {
+    dir: PathBuf,
+    fingerprint: String,
+    #[cfg(feature = "msgpack")]
+    message_pack: bool,
+    compression: Option<Compression>,
+}
+
+type WrapWriter = Arc<dyn Fn(File) -> io::Result<Box<dyn Write>> + Send + Sync>;
+type WrapReader = Arc<dyn Fn(File) -> io::Result<Box<dyn Read>> + Send + Sync>;
+
+#[derive(Clone)]
+struct Compression {
+    extension: String,
+    writer: WrapWriter,
+    reader: WrapReader,
+}
+
+impl fmt::Debug for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Compression")
+            .field("extension", &self.extension)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResultCache {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            fingerprint: String::new(),
+            #[cfg(feature = "msgpack")]
+            message_pack: false,
+            compression: None,
+        })
+    }
+
+    // Mixed into every key, e.g. the model name
+    pub fn fingerprint(mut self, fingerprint: impl Into<String>) -> Self {
+        self.fingerprint = fingerprint.into();
+        self
+    }
+
+    // Stores entries as MessagePack, about half the size for results with ownership. Entries
+    // stored as JSON before aren't read anymore.
+    #[cfg(feature = "msgpack")]
+    pub fn message_pack(mut self) -> Self {
+        self.message_pack = true;
+        self
+    }
+
+    // Entries are written through `writer` and read through `reader`, e.g. a zstd encoder and
+    // decoder, and get `extension` appended to their file names. The writer is dropped once the
+    // entry is written, so it has to finish its stream on drop, like zstd's `auto_finish`.
+    // Entries stored uncompressed before aren't read anymore.
+    pub fn compression<W, R>(
+        mut self,
+        extension: impl Into<String>,
+        writer: impl Fn(File) -> io::Result<W> + Send + Sync + 'static,
+        reader: impl Fn(File) -> io::Result<R> + Send + Sync + 'static,
+    ) -> Self
+    where
+        W: Write + 'static,
+        R: Read + 'static,
+    {
+        self.compression = Some(Compression {
+            extension: extension.into(),
+            writer: Arc::new(move |file| Ok(Box::new(writer(file)?) as Box<dyn Write>)),
+            reader: Arc::new(move |file| Ok(Box::new(reader(file)?) as Box<dyn Read>)),
+        });
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // Lowercase hex sha256 of the fingerprint and the canonical JSON of the query
+    pub fn key(&self, query: &KataQuery) -> String {
+        let canonical = canonical_json(query);
+        let mut hasher = Sha256::new();
+        hasher.update(self.fingerprint.as_bytes());
+        hasher.update(b"\n");
+        hasher.update(canonical.as_bytes());
+        hasher.finish_hex()
+    }
+
+    // The final results of every analyzed turn, with the ids of the query they were stored for.
+    // Unreadable entries count as missing, entries written by a later release of the crate are an
+    // error instead.
+    pub fn get(&self, key: &str) -> io::Result<Option<Vec<KataResponse>>> {
+        let bytes = match self.read(&self.path(key)) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let Some(value) = self.decode(&bytes) else {
+            return Ok(None);
+        };
+        match read_results(value) {
+            Ok(results) => Ok(Some(results)),
+            Err(err @ SchemaError::Newer(_)) => Err(invalid_data(err)),
+            Err(_) => Ok(None),
+        }
+    }
+
+    pub fn put(&self, key: &str, results: &[KataResponse]) -> io::Result<()> {
+        let value = stamp_results(results).map_err(invalid_data)?;
+        self.write(&self.path(key), &value)
+    }
+
+    // Rewrites every entry stored with an older schema version, so they needn't be migrated on
+    // each read. Returns how many were.
+    pub fn migrate(&self) -> io::Result<usize> {
+        let extension = format!(".{}", self.extension());
+        let mut migrated = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_entry = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.ends_with(&extension));
+            if !is_entry {
+                continue;
+            }
+            let Some(value) = self.read(&path)?.and_then(|bytes| self.decode(&bytes)) else {
+                continue;
+            };
+            if schema_version(&value).map_or(true, |version| version >= SCHEMA_VERSION) {
+                continue;
+            }
+            self.write(&path, &migrate_results(value).map_err(invalid_data)?)?;
+            migrated += 1;
+        }
+        Ok(migrated)
+    }
+
+    pub fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.path(key)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    // Written under a temporary name first, so readers never see partial entries. The name is
+    // unique to the write, processes and threads storing the same entry don't share a file.
+    fn write(&self, path: &Path, value: &Value) -> io::Result<()> {
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(format!(
+            ".{}-{}.part",
+            std::process::id(),
+            PARTIAL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let partial = PathBuf::from(partial);
+        let bytes = self.encode(value)?;
+        let written = match &self.compression {
+            Some(compression) => File::create(&partial)
+                .and_then(|file| (compression.writer)(file))
+                .and_then(|mut writer| {
+                    writer.write_all(&bytes)?;
+                    writer.flush()
+                }),
+            None => fs::write(&partial, bytes),
+        };
+        match written.and_then(|()| fs::rename(&partial, path)) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                let _ = fs::remove_file(&partial);
+                Err(err)
+            }
+        }
+    }
+
+    // None for entries which don't decompress, they're as unreadable as ones which don't decode
+    fn read(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let Some(compression) = &self.compression else {
+            return fs::read(path).map(Some);
+        };
+        let file = File::open(path)?;
+        let mut bytes = Vec::new();
+        let decompressed =
+            (compression.reader)(file).and_then(|mut reader| reader.read_to_end(&mut bytes));
+        Ok(decompressed.ok().map(|_| bytes))
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{}", self.extension()))
+    }
+
+    fn extension(&self) -> String {
+        #[cfg(feature = "msgpack")]
+        let format = if self.message_pack { "msgpack" } else { "json" };
+        #[cfg(not(feature = "msgpack"))]
+        let format = "json";
+        match &self.compression {
+            Some(compression) => format!("{format}.{}", compression.extension),
+            None => format.to_owned(),
+        }
+    }
+
+    fn encode(&self, value: &Value) -> io::Result<Vec<u8>> {
+        #[cfg(feature = "msgpack")]
+        if self.message_pack {
+            return to_msgpack(value).map_err(invalid_data);
+        }
+        Ok(serde_json::to_vec(value)?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Option<Value> {
+        #[cfg(feature = "msgpack")]
+        if self.message_pack {
+            return from_msgpack(bytes).ok();
+        }
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+fn invalid_data(err: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,292 @@
+// How well the engine's evaluations predict how games actually end, over many finished games: a
+// well calibrated engine's 70% positions are won 70% of the time, and its score leads match the
+// final scores on average. Predictions and outcomes are binned into reliability curves, which can
+// be plotted against the diagonal. Like `DatasetStats` separate runs can be merged.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Analysis, KataResponse, Player};
+
+// How a game ended, from the SGF `RE` property or a server's records
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameResult {
+    // None for draws
+    pub winner: Option<Player>,
+    // Black's margin, None for resignations, timeouts and forfeits
+    pub score: Option<f32>,
+}
+
+impl GameResult {
+    pub fn win(winner: Player, margin: Option<f32>) -> Self {
+        let score = margin.map(|margin| match winner {
+            Player::Black => margin,
+            Player::White => -margin,
+        });
+        Self {
+            winner: Some(winner),
+            score,
+        }
+    }
+
+    pub fn draw() -> Self {
+        Self {
+            winner: None,
+            score: Some(0.0),
+        }
+    }
+
+    // `B+3.5`, `W+R`, `B+T`, `0` and the like. None for voided games and unknown results.
+    pub fn parse(re: &str) -> Option<Self> {
+        let re = re.trim();
+        if matches!(re, "0" | "Draw" | "Jigo") {
+            return Some(Self::draw());
+        }
+        let (winner, margin) = re.split_once('+')?;
+        let winner = match winner {
+            "B" => Player::Black,
+            "W" => Player::White,
+            _ => return None,
+        };
+        let margin = match margin {
+            "" | "R" | "Resign" | "T" | "Time" | "F" | "Forfeit" => None,
+            margin => Some(margin.parse().ok()?),
+        };
+        Some(Self::win(winner, margin))
+    }
+
+    // 1 for black wins, 0 for white's and a half for draws
+    fn black_outcome(&self) -> f64 {
+        match self.winner {
+            Some(Player::Black) => 1.0,
+            Some(Player::White) => 0.0,
+            None => 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReliabilityBin {
+    pub count: u64,
+    predicted_sum: f64,
+    observed_sum: f64,
+}
+
+impl ReliabilityBin {
+    // Average prediction of the positions in the bin
+    pub fn predicted(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.predicted_sum / self.count as f64)
+    }
+
+    // Average outcome of the positions in the bin
+    pub fn observed(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.observed_sum / self.count as f64)
+    }
+
+    fn add(&mut self, predicted: f64, observed: f64) {
+        self.count += 1;
+        self.predicted_sum += predicted;
+        self.observed_sum += observed;
+    }
+
+    fn merge(&mut self, other: &ReliabilityBin) {
+        self.count += other.count;
+        self.predicted_sum += other.predicted_sum;
+        self.observed_sum += other.observed_sum;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    pub games: u64,
+    // Of black's winrate, from 0 to 1 in equal steps
+    pub winrate_bins: Vec<ReliabilityBin>,
+    // Of black's score lead, keyed by the lower end of each bin
+    pub score_bins: BTreeMap<i32, ReliabilityBin>,
+    score_bin_width: u16,
+    skip_turns: u32,
+    // Summed over every position, for the Brier score and the score errors
+    squared_error: f64,
+    score_positions: u64,
+    score_error: f64,
+    absolute_score_error: f64,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Self {
+            games: 0,
+            winrate_bins: vec![ReliabilityBin::default(); 10],
+            score_bins: BTreeMap::new(),
+            score_bin_width: 5,
+            skip_turns: 0,
+            squared_error: 0.0,
+            score_positions: 0,
+            score_error: 0.0,
+            absolute_score_error: 0.0,
+        }
+    }
+
+    // Set before adding games, calibrations are only merged with ones binned the same way
+    pub fn winrate_bins(mut self, bins: usize) -> Self {
+        self.winrate_bins = vec![ReliabilityBin::default(); bins.max(1)];
+        self
+    }
+
+    // In points
+    pub fn score_bin_width(mut self, width: u16) -> Self {
+        self.score_bin_width = width.max(1);
+        self
+    }
+
+    // Openings are mostly even whoever goes on to win, leaving them out shows how the rest of the
+    // game is calibrated
+    pub fn skip_turns(mut self, turns: u32) -> Self {
+        self.skip_turns = turns;
+        self
+    }
+
+    // Results are the final results of any of the game's turns, interim results are skipped
+    pub fn add_game<'a>(
+        &mut self,
+        results: impl IntoIterator<Item = &'a KataResponse>,
+        result: GameResult,
+    ) {
+        self.games += 1;
+        let outcome = result.black_outcome();
+        let analyses = results
+            .into_iter()
+            .filter(|response| !response.is_during_search())
+            .filter_map(Analysis::from_response)
+            .filter(|analysis| analysis.turn() >= self.skip_turns);
+        for analysis in analyses {
+            let winrate = analysis.winrate_for(Player::Black) as f64;
+            let bins = self.winrate_bins.len();
+            let bin = ((winrate * bins as f64) as usize).min(bins - 1);
+            self.winrate_bins[bin].add(winrate, outcome);
+            self.squared_error += (winrate - outcome).powi(2);
+
+            let Some(score) = result.score else {
+                continue;
+            };
+            let (lead, score) = (analysis.score_lead_for(Player::Black) as f64, score as f64);
+            let width = self.score_bin_width as f64;
+            let bin = (lead / width).floor() as i32 * self.score_bin_width as i32;
+            self.score_bins.entry(bin).or_default().add(lead, score);
+            self.score_positions += 1;
+            self.score_error += lead - score;
+            self.absolute_score_error += (lead - score).abs();
+        }
+    }
+
+    pub fn positions(&self) -> u64 {
+        self.winrate_bins.iter().map(|bin| bin.count).sum()
+    }
+
+    // Mean squared difference of winrates and outcomes, 0.25 for always predicting 50%
+    pub fn brier_score(&self) -> Option<f64> {
+        let positions = self.positions();
+        (positions > 0).then(|| self.squared_error / positions as f64)
+    }
+
+    // Distance of the winrate curve from the diagonal, weighted by how many positions each bin has
+    pub fn expected_calibration_error(&self) -> Option<f64> {
+        let positions = self.positions();
+        (positions > 0).then(|| {
+            self.winrate_bins
+                .iter()
+                .filter(|bin| bin.count > 0)
+                .map(|bin| (bin.predicted_sum - bin.observed_sum).abs())
+                .sum::<f64>()
+                / positions as f64
+        })
+    }
+
+    // Positive when the engine expects more for black than black ends up with
+    pub fn score_bias(&self) -> Option<f64> {
+        (self.score_positions > 0).then(|| self.score_error / self.score_positions as f64)
+    }
+
+    pub fn score_mean_absolute_error(&self) -> Option<f64> {
+        (self.score_positions > 0).then(|| self.absolute_score_error / self.score_positions as f64)
+    }
+
+    // Panics when the two were binned differently
+    pub fn merge(&mut self, other: &Calibration) {
+        assert_eq!(
+            (self.winrate_bins.len(), self.score_bin_width),
+            (other.winrate_bins.len(), other.score_bin_width),
+            "calibrations are binned differently"
+        );
+        self.games += other.games;
+        for (bin, other) in self.winrate_bins.iter_mut().zip(&other.winrate_bins) {
+            bin.merge(other);
+        }
+        for (lower, other) in &other.score_bins {
+            self.score_bins.entry(*lower).or_default().merge(other);
+        }
+        self.squared_error += other.squared_error;
+        self.score_positions += other.score_positions;
+        self.score_error += other.score_error;
+        self.absolute_score_error += other.absolute_score_error;
+    }
+}
+
+// Both reliability curves as text tables, empty bins are left out
+impl fmt::Display for Calibration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} games, {} positions", self.games, self.positions())?;
+        if let (Some(brier), Some(ece)) = (self.brier_score(), self.expected_calibration_error()) {
+            writeln!(f, "brier score {brier:.4}, calibration error {ece:.4}")?;
+        }
+        if let (Some(bias), Some(mae)) = (self.score_bias(), self.score_mean_absolute_error()) {
+            writeln!(f, "score bias {bias:+.2}, mean absolute error {mae:.2}")?;
+        }
+        writeln!(
+            f,
+            "{:>11} {:>9} {:>8} {:>7}",
+            "winrate", "predicted", "observed", "count"
+        )?;
+        let step = 100.0 / self.winrate_bins.len() as f64;
+        for (i, bin) in self.winrate_bins.iter().enumerate() {
+            if let (Some(predicted), Some(observed)) = (bin.predicted(), bin.observed()) {
+                writeln!(
+                    f,
+                    "{:>4.0}%-{:>4.0}% {:>8.1}% {:>7.1}% {:>7}",
+                    i as f64 * step,
+                    (i + 1) as f64 * step,
+                    predicted * 100.0,
+                    observed * 100.0,
+                    bin.count
+                )?;
+            }
+        }
+        if !self.score_bins.is_empty() {
+            writeln!(
+                f,
+                "{:>11} {:>9} {:>8} {:>7}",
+                "lead", "predicted", "observed", "count"
+            )?;
+        }
+        for (lower, bin) in &self.score_bins {
+            if let (Some(predicted), Some(observed)) = (bin.predicted(), bin.observed()) {
+                writeln!(
+                    f,
+                    "{:>+5}..{:>+4} {:>+9.1} {:>+8.1} {:>7}",
+                    lower,
+                    lower + self.score_bin_width as i32,
+                    predicted,
+                    observed,
+                    bin.count
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
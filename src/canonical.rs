@@ -0,0 +1,82 @@
+use serde_json::Value;
+
+use crate::KataQuery;
+
+// Fields which don't change any of the responses
+const IGNORED_FIELDS: &[&str] = &["id", "priority", "priorities"];
+
+// Fields which only change the interim responses
+const INTERIM_FIELDS: &[&str] = &["reportDuringSearchEvery"];
+
+// Fields describing the position rather than how it's analyzed
+const POSITION_FIELDS: &[&str] = &[
+    "boardXSize",
+    "boardYSize",
+    "initialStones",
+    "initialPlayer",
+    "moves",
+    "analyzeTurns",
+];
+
+// The query as JSON with sorted keys and without the fields above, equal for queries that get the
+// same final results
+#[cfg(feature = "cache")]
+pub(crate) fn canonical_json(query: &KataQuery) -> String {
+    without_fields(query, &[IGNORED_FIELDS, INTERIM_FIELDS])
+}
+
+// Like `canonical_json` but with the interim reporting, equal for queries that get the same
+// responses during the search too
+pub(crate) fn canonical_search_json(query: &KataQuery) -> String {
+    without_fields(query, &[IGNORED_FIELDS])
+}
+
+// Like `canonical_json` but also without the position. Fields are left out rather than picked, so
+// ones added to `KataQuery` later are part of it.
+pub(crate) fn canonical_settings_json(query: &KataQuery) -> String {
+    without_fields(query, &[IGNORED_FIELDS, INTERIM_FIELDS, POSITION_FIELDS])
+}
+
+fn without_fields(query: &KataQuery, ignored: &[&[&str]]) -> String {
+    let mut value = serde_json::to_value(query).unwrap();
+    if let Value::Object(fields) = &mut value {
+        for field in ignored.iter().copied().flatten() {
+            fields.remove(*field);
+        }
+    }
+    let mut canonical = String::new();
+    write_canonical(&value, &mut canonical);
+    canonical
+}
+
+// JSON with object keys sorted, so equal queries hash equally whatever order their maps were
+// built in
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(fields) => {
+            let mut fields = fields.iter().collect::<Vec<_>>();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (key, value)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(']');
+        }
+        value => out.push_str(&value.to_string()),
+    }
+}
@@ -0,0 +1,116 @@
+// What the engine can do, so queries it can't run fail in the client with a clear error instead
+// of a round trip to katago. Limits nobody declared aren't checked. By default everything the
+// engine can't do is rejected, with `downgrade` optional outputs it can't produce are dropped
+// from the query instead.
+
+use crate::{ClientError, KataQuery};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EngineCapabilities {
+    pub(crate) max_board_size: Option<u8>,
+    pub(crate) ownership_stdev: Option<bool>,
+    pub(crate) human_model: Option<bool>,
+    pub(crate) search_limit_in_config: Option<bool>,
+    downgrade: bool,
+}
+
+impl EngineCapabilities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The largest board the engine was compiled for, 19 for release builds
+    pub fn max_board_size(mut self, max_board_size: u8) -> Self {
+        self.max_board_size = Some(max_board_size);
+        self
+    }
+
+    // Whether the engine's version reports `ownershipStdev`
+    pub fn ownership_stdev(mut self, supported: bool) -> Self {
+        self.ownership_stdev = Some(supported);
+        self
+    }
+
+    // Whether the engine was launched with a human model, which `humanSLProfile` requires
+    pub fn human_model(mut self, loaded: bool) -> Self {
+        self.human_model = Some(loaded);
+        self
+    }
+
+    // Whether the engine's config sets maxVisits, maxPlayouts or maxTime. Without one a query
+    // which doesn't bound its own search would run until terminated, so it's rejected.
+    pub fn search_limit_in_config(mut self, limited: bool) -> Self {
+        self.search_limit_in_config = Some(limited);
+        self
+    }
+
+    // Drop ownership stdev from queries instead of rejecting them. Queries which would be analyzed
+    // differently, like ones with a human profile, are still rejected.
+    pub fn downgrade(mut self, downgrade: bool) -> Self {
+        self.downgrade = downgrade;
+        self
+    }
+
+    pub fn board_size_limit(&self) -> Option<u8> {
+        self.max_board_size
+    }
+
+    pub fn has_ownership_stdev(&self) -> Option<bool> {
+        self.ownership_stdev
+    }
+
+    pub fn has_human_model(&self) -> Option<bool> {
+        self.human_model
+    }
+
+    pub fn has_search_limit_in_config(&self) -> Option<bool> {
+        self.search_limit_in_config
+    }
+
+    // Declared limits win over detected ones
+    pub(crate) fn or(self, detected: EngineCapabilities) -> Self {
+        Self {
+            max_board_size: self.max_board_size.or(detected.max_board_size),
+            ownership_stdev: self.ownership_stdev.or(detected.ownership_stdev),
+            human_model: self.human_model.or(detected.human_model),
+            search_limit_in_config: self
+                .search_limit_in_config
+                .or(detected.search_limit_in_config),
+            downgrade: self.downgrade,
+        }
+    }
+
+    pub(crate) fn apply(&self, query: &mut KataQuery) -> Result<(), ClientError> {
+        if let Some(max_board_size) = self.max_board_size {
+            if query.board_x_size > max_board_size || query.board_y_size > max_board_size {
+                return Err(ClientError::Unsupported(format!(
+                    "{}x{} boards are bigger than the engine's maximum of {max_board_size}x{max_board_size}",
+                    query.board_x_size, query.board_y_size
+                )));
+            }
+        }
+        if query.human_sl_profile().is_some() && self.human_model == Some(false) {
+            return Err(ClientError::Unsupported(
+                "humanSLProfile requires an engine launched with a human model".to_owned(),
+            ));
+        }
+        if self.search_limit_in_config == Some(false) && !query.bounds_search() {
+            return Err(ClientError::Unsupported(
+                "the engine's config doesn't limit the search, set max_visits or max_time"
+                    .to_owned(),
+            ));
+        }
+        let wants_stdev = query.inlcude_ownership_stdev == Some(true)
+            || query.include_moves_ownership_stdev == Some(true);
+        if wants_stdev && self.ownership_stdev == Some(false) {
+            if !self.downgrade {
+                return Err(ClientError::Unsupported(
+                    "the engine doesn't report ownershipStdev".to_owned(),
+                ));
+            }
+            query.inlcude_ownership_stdev = None;
+            query.include_moves_ownership_stdev = None;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1978 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::future::{join_all, select, Either};
+use futures_util::{SinkExt, StreamExt};
+use tokio::runtime::Handle;
+use tokio::sync::{mpsc, oneshot, watch, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use crate::canonical::canonical_search_json;
+use crate::metrics::{Metrics, SlowQueryLog, Timing};
+use crate::session::SessionState;
+#[cfg(feature = "process")]
+use crate::EngineHandle;
+#[cfg(feature = "cache")]
+use crate::ResultCache;
+use crate::{
+    ActionClearCache, ActionQueryVersion, ActionTerminate, EngineCapabilities, GameSession,
+    KataAction, KataQuery, KataQueryBuilder, KataResponse, LatencyHistogram, Priority, QueryId,
+    QueryIdGenerator, QueryLane, ReportAnalysisWinratesAs, Rules, SlowQuery, Throughput,
+};
+
+// Sizes katago is usually compiled for, tried from the largest down
+const BOARD_SIZES: [u8; 3] = [29, 25, 19];
+
+const DEFAULT_ID_NAMESPACE: &str = "kpae";
+
+// Seconds between the interim reports asked for to tell `QueryTtl::UntilStarted` queries have
+// started. Without them the first report would be the final result.
+const STARTED_REPORT_EVERY: f32 = 0.1;
+
+#[derive(Clone, Debug)]
+pub enum ClientError {
+    Rejected {
+        error: String,
+        field: Option<String>,
+    },
+    Unsupported(String),
+    Draining,
+    Closed,
+    // The query outlived its `QueryTtl`
+    Expired,
+    // Another query with the same id is still in flight, responses couldn't be told apart
+    DuplicateId(QueryId),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Rejected {
+                error,
+                field: Some(field),
+            } => write!(f, "engine rejected field {field:?}: {error}"),
+            ClientError::Rejected { error, field: None } => {
+                write!(f, "engine rejected action: {error}")
+            }
+            ClientError::Unsupported(reason) => write!(f, "engine doesn't support query: {reason}"),
+            ClientError::Draining => f.write_str("client is draining and accepts no new queries"),
+            ClientError::Closed => f.write_str("engine connection is closed"),
+            ClientError::Expired => f.write_str("query expired before the engine finished it"),
+            ClientError::DuplicateId(id) => write!(f, "query id {id} is already in flight"),
+        }
+    }
+}
+
+impl Error for ClientError {}
+
+// How long a query may take, see `Client::submit_with_ttl`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryTtl {
+    // Dropped if it's still waiting for a batch slot, or terminated if katago hasn't reported on
+    // it yet, e.g. because every search thread is busy with other queries. Queries which don't
+    // report during the search are made to, see `STARTED_REPORT_EVERY`, and those reports aren't
+    // passed on.
+    UntilStarted(Duration),
+    // Terminated if it hasn't finished
+    UntilFinished(Duration),
+}
+
+impl QueryTtl {
+    fn duration(self) -> Duration {
+        match self {
+            QueryTtl::UntilStarted(ttl) | QueryTtl::UntilFinished(ttl) => ttl,
+        }
+    }
+}
+
+// Issues `clear_cache` on its own to bound the memory used by katago's NN cache on long-lived
+// engines. Both triggers can be combined, whichever fires first clears the cache.
+#[derive(Clone, Debug, Default)]
+pub struct CacheClearPolicy {
+    idle_for: Option<Duration>,
+    every_queries: Option<u64>,
+}
+
+impl CacheClearPolicy {
+    // Clear once nothing was in flight for this long. An engine that stays idle is cleared only
+    // once.
+    pub fn idle_for(mut self, idle_for: Duration) -> Self {
+        self.idle_for = Some(idle_for);
+        self
+    }
+
+    // Clear before submitting a query whenever this many queries were submitted since the last
+    // clear
+    pub fn every_queries(mut self, every_queries: u64) -> Self {
+        self.every_queries = Some(every_queries);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ClientBuilder {
+    id_namespace: Option<String>,
+    cache_clear_policy: Option<CacheClearPolicy>,
+    capabilities: EngineCapabilities,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    #[cfg(feature = "cache")]
+    result_cache: Option<ResultCache>,
+    query_defaults: Option<KataQueryBuilder>,
+    deduplicate_queries: bool,
+    lane_priorities: HashMap<QueryLane, Priority>,
+    batch_in_flight_limit: Option<usize>,
+    slow_query_log: Option<SlowQueryLog>,
+    runtime: Option<Handle>,
+}
+
+impl ClientBuilder {
+    pub fn id_namespace(mut self, id_namespace: impl Into<String>) -> Self {
+        self.id_namespace = Some(id_namespace.into());
+        self
+    }
+
+    pub fn cache_clear_policy(mut self, cache_clear_policy: CacheClearPolicy) -> Self {
+        self.cache_clear_policy = Some(cache_clear_policy);
+        self
+    }
+
+    // Lets the client reject queries the engine can't run before sending them
+    #[cfg(feature = "process")]
+    pub fn engine(mut self, engine: &EngineHandle) -> Self {
+        if let Some(reported_as) = engine.report_analysis_winrates_as() {
+            self.report_analysis_winrates_as = Some(reported_as);
+        }
+        if let Some(human_model) = engine.has_human_model() {
+            self.capabilities = self.capabilities.human_model(human_model);
+        }
+        self
+    }
+
+    // Queries the engine can't run are rejected, or downgraded, before they're sent. Limits left
+    // undeclared can be found with `Client::detect_capabilities`.
+    pub fn capabilities(mut self, capabilities: EngineCapabilities) -> Self {
+        self.capabilities = capabilities.or(self.capabilities);
+        self
+    }
+
+    // How the engine was configured to report winrates, untagged results get tagged with it
+    pub fn report_analysis_winrates_as(mut self, reported_as: ReportAnalysisWinratesAs) -> Self {
+        self.report_analysis_winrates_as = Some(reported_as);
+        self
+    }
+
+    // Queries found in the cache are answered from it without reaching the engine, final results of
+    // the others are stored once every turn finished. Terminated queries aren't stored.
+    #[cfg(feature = "cache")]
+    pub fn result_cache(mut self, result_cache: ResultCache) -> Self {
+        self.result_cache = Some(result_cache);
+        self
+    }
+
+    // What `Client::query` starts every query from, e.g. the rules, komi, board size and visits,
+    // so callers only add the position. Any of them can still be overridden per query.
+    pub fn query_defaults(mut self, query_defaults: KataQueryBuilder) -> Self {
+        self.query_defaults = Some(query_defaults);
+        self
+    }
+
+    // Queries identical to one still in flight, apart from their id and priority, don't reach the
+    // engine but get copies of the first one's responses, interim ones included. Queries reporting
+    // during the search at a different interval, or not at all, get their own search. Terminating one of them while others
+    // still wait for the search only ends its own responses, without a final result.
+    pub fn deduplicate_queries(mut self, deduplicate_queries: bool) -> Self {
+        self.deduplicate_queries = deduplicate_queries;
+        self
+    }
+
+    // What `Client::submit_to` gives queries of `lane`, by default interactive queries get
+    // `Priority::HIGH` and batch ones `Priority::LOW`
+    pub fn lane_priority(mut self, lane: QueryLane, priority: Priority) -> Self {
+        self.lane_priorities.insert(lane, priority);
+        self
+    }
+
+    // At most `limit` batch queries are sent to the engine at once, the others wait in the client.
+    // Together with the lanes' priorities this keeps a background review from delaying
+    // interactive queries, katago only reorders queries it already received.
+    pub fn batch_in_flight_limit(mut self, limit: usize) -> Self {
+        self.batch_in_flight_limit = Some(limit);
+        self
+    }
+
+    // Calls `callback` for every query taking `threshold` or longer, e.g. to log positions which
+    // stall a shared engine. It's called from the task reading responses, so it shouldn't block.
+    pub fn slow_query_log(
+        mut self,
+        threshold: Duration,
+        callback: impl Fn(&SlowQuery) + Send + Sync + 'static,
+    ) -> Self {
+        self.slow_query_log = Some(SlowQueryLog {
+            threshold,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    // Where the client's tasks and timers run, by default the runtime `build` is called from. With
+    // a runtime driven on a thread of its own, the client can be built and used from any executor,
+    // like async-std's or smol's: its handles and methods then only wait on channels. See
+    // `response_stream` and `action_sink` for engines connected through other runtimes' pipes.
+    pub fn runtime(mut self, runtime: Handle) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
+    // Spawns the tasks driving the engine, so it must be called from within a tokio runtime unless
+    // it's given one
+    pub fn build<Si, St>(self, sink: Si, stream: St) -> Client
+    where
+        Si: Sink<KataAction> + Send + 'static,
+        St: Stream<Item = KataResponse> + Send + 'static,
+    {
+        let (actions, rx) = mpsc::unbounded_channel();
+        let routes = Arc::new(Mutex::new(Routes {
+            pending: HashMap::new(),
+            leaders: HashMap::new(),
+            followers: HashMap::new(),
+            in_flight: watch::channel(0).0,
+            queued: 0,
+            draining: false,
+            closed: false,
+            last_activity: Instant::now(),
+            dirty: false,
+        }));
+        let cache_clear_policy = self.cache_clear_policy.unwrap_or_default();
+        let runtime = self.runtime.unwrap_or_else(Handle::current);
+        let shared = Arc::new(Shared {
+            actions,
+            routes: routes.clone(),
+            ids: QueryIdGenerator::new(
+                self.id_namespace
+                    .unwrap_or_else(|| DEFAULT_ID_NAMESPACE.to_owned()),
+            ),
+            queries_since_clear: Mutex::new(0),
+            cache_clear_policy: cache_clear_policy.clone(),
+            capabilities: Mutex::new(self.capabilities.clone()),
+            declared_capabilities: self.capabilities,
+            report_analysis_winrates_as: self.report_analysis_winrates_as,
+            #[cfg(feature = "cache")]
+            result_cache: self.result_cache,
+            query_defaults: self.query_defaults.unwrap_or_default(),
+            deduplicate_queries: self.deduplicate_queries,
+            lane_priorities: self.lane_priorities,
+            batch_slots: self
+                .batch_in_flight_limit
+                .map(|limit| Arc::new(Semaphore::new(limit))),
+            queued: Mutex::new(HashMap::new()),
+            sessions: Mutex::new(HashMap::new()),
+            metadata: Mutex::new(HashMap::new()),
+            metrics: Arc::new(Metrics::new(self.slow_query_log)),
+            reader: Mutex::new(None),
+            runtime: runtime.clone(),
+        });
+
+        runtime.spawn(write(sink, rx, routes.clone()));
+        *shared.reader.lock().unwrap() =
+            Some(runtime.spawn(read(stream, routes, shared.report_analysis_winrates_as)));
+        if let Some(idle_for) = cache_clear_policy.idle_for {
+            runtime.spawn(clear_when_idle(Arc::downgrade(&shared), idle_for));
+        }
+
+        Client { shared }
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+enum Outgoing {
+    Action(KataAction),
+    // Written with a single flush
+    Actions(Vec<KataAction>),
+    // Closes the sink, which makes the engine quit once it answered everything
+    Close,
+}
+
+struct Shared {
+    actions: mpsc::UnboundedSender<Outgoing>,
+    routes: Arc<Mutex<Routes>>,
+    ids: QueryIdGenerator,
+    queries_since_clear: Mutex<u64>,
+    cache_clear_policy: CacheClearPolicy,
+    capabilities: Mutex<EngineCapabilities>,
+    declared_capabilities: EngineCapabilities,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    #[cfg(feature = "cache")]
+    result_cache: Option<ResultCache>,
+    query_defaults: KataQueryBuilder,
+    deduplicate_queries: bool,
+    lane_priorities: HashMap<QueryLane, Priority>,
+    batch_slots: Option<Arc<Semaphore>>,
+    // Queries waiting for a batch slot or a result cache lookup, dropping the sender cancels them
+    queued: Mutex<HashMap<QueryId, oneshot::Sender<()>>>,
+    sessions: Mutex<HashMap<String, Arc<Mutex<SessionState>>>>,
+    // Of queries still in flight, pruned as more are attached
+    metadata: Mutex<HashMap<QueryId, Metadata>>,
+    metrics: Arc<Metrics>,
+    reader: Mutex<Option<JoinHandle<()>>>,
+    runtime: Handle,
+}
+
+struct Routes {
+    pending: HashMap<QueryId, Pending>,
+    // Deduplicated queries in flight by their canonical JSON, and the queries following them
+    leaders: HashMap<String, QueryId>,
+    followers: HashMap<QueryId, QueryId>,
+    // Pending actions plus queued batch queries
+    in_flight: watch::Sender<usize>,
+    // Queries waiting for a batch slot or a result cache lookup, counted as in flight so `drain`
+    // waits for them too
+    queued: usize,
+    draining: bool,
+    closed: bool,
+    last_activity: Instant,
+    // Whether any query ran since the cache was last cleared
+    dirty: bool,
+}
+
+impl Routes {
+    fn close(&mut self) {
+        self.closed = true;
+        self.pending.clear();
+        self.leaders.clear();
+        self.followers.clear();
+        self.queued = 0;
+        self.publish();
+    }
+
+    fn publish(&self) {
+        self.in_flight
+            .send_replace(self.pending.len() + self.queued);
+    }
+
+    fn dequeue(&mut self, count: usize) {
+        self.queued = self.queued.saturating_sub(count);
+        self.publish();
+    }
+
+    fn track(
+        &mut self,
+        action: &KataAction,
+        remaining: usize,
+        on_complete: Option<OnComplete>,
+        timing: Option<Timing>,
+    ) -> (mpsc::UnboundedReceiver<KataResponse>, oneshot::Receiver<()>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (done, done_rx) = oneshot::channel();
+        let query = matches!(action, KataAction::Query { .. });
+        if query {
+            self.dirty = true;
+        }
+        self.last_activity = Instant::now();
+        self.pending.insert(
+            action.id().clone(),
+            Pending {
+                tx,
+                remaining,
+                finals: Vec::new(),
+                on_complete,
+                key: None,
+                followers: Vec::new(),
+                timing,
+                terminated: Terminated::None,
+                query,
+                _done: done,
+            },
+        );
+        self.publish();
+        (rx, done_rx)
+    }
+
+    fn remove(&mut self, id: &QueryId) {
+        if let Some(pending) = self.pending.remove(id) {
+            if let Some(key) = pending.key {
+                if self.leaders.get(&key) == Some(id) {
+                    self.leaders.remove(&key);
+                }
+            }
+            for follower in pending.followers {
+                self.followers.remove(&follower.id);
+            }
+        }
+        self.publish();
+    }
+
+    fn is_outstanding(&self, id: &QueryId) -> bool {
+        self.pending.contains_key(id) || self.followers.contains_key(id)
+    }
+
+    fn terminate(&mut self, id: &QueryId, turn_numbers: Option<&[u32]>) {
+        if let Some(pending) = self.pending.get_mut(id) {
+            pending.terminated.add(turn_numbers);
+        }
+    }
+
+    // Lets queries with the same canonical JSON follow the tracked query `id`
+    fn lead(&mut self, key: String, id: &QueryId) {
+        if let Some(pending) = self.pending.get_mut(id) {
+            pending.key = Some(key.clone());
+            self.leaders.insert(key, id.clone());
+        }
+    }
+
+    fn follow(
+        &mut self,
+        key: &str,
+        id: &QueryId,
+    ) -> Option<(mpsc::UnboundedReceiver<KataResponse>, oneshot::Receiver<()>)> {
+        let leader = self.leaders.get(key)?;
+        let pending = self.pending.get_mut(leader)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (done, done_rx) = oneshot::channel();
+        pending.followers.push(Follower {
+            id: id.clone(),
+            tx,
+            _done: done,
+        });
+        self.followers.insert(id.clone(), leader.clone());
+        self.last_activity = Instant::now();
+        Some((rx, done_rx))
+    }
+
+    // Stops sending `id` responses, true if the search is still needed by others
+    fn detach(&mut self, id: &QueryId) -> bool {
+        if let Some(leader) = self.followers.remove(id) {
+            if let Some(pending) = self.pending.get_mut(&leader) {
+                pending.followers.retain(|follower| follower.id != *id);
+            }
+            return true;
+        }
+        match self.pending.get_mut(id) {
+            Some(pending) if !pending.followers.is_empty() => {
+                pending.tx = mpsc::unbounded_channel().0;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Whatever the caller attached to a query, see `submit_with_metadata`
+type Metadata = Arc<dyn Any + Send + Sync>;
+
+// Called with the final results of every turn once the last one arrived
+type OnComplete = Box<dyn FnOnce(Vec<KataResponse>) + Send>;
+
+struct Pending {
+    tx: mpsc::UnboundedSender<KataResponse>,
+    // Every analyzed turn ends with exactly one final response
+    remaining: usize,
+    // Only collected when there's someone to hand them to
+    finals: Vec<KataResponse>,
+    on_complete: Option<OnComplete>,
+    // Set when identical queries may follow this one
+    key: Option<String>,
+    followers: Vec<Follower>,
+    // Only for queries
+    timing: Option<Timing>,
+    terminated: Terminated,
+    // Rather than a terminate, version query or cache clear
+    query: bool,
+    // Never sent, dropping it tells waiters the action is done
+    _done: oneshot::Sender<()>,
+}
+
+// Turns of a query the client asked katago to terminate
+enum Terminated {
+    None,
+    Turns(HashSet<u32>),
+    All,
+}
+
+impl Terminated {
+    fn add(&mut self, turn_numbers: Option<&[u32]>) {
+        match (turn_numbers, &mut *self) {
+            (None, _) => *self = Terminated::All,
+            (Some(_), Terminated::All) => {}
+            (Some(turn_numbers), Terminated::Turns(turns)) => turns.extend(turn_numbers),
+            (Some(turn_numbers), Terminated::None) => {
+                *self = Terminated::Turns(turn_numbers.iter().copied().collect())
+            }
+        }
+    }
+
+    fn covers(&self, turn_number: u32) -> bool {
+        match self {
+            Terminated::None => false,
+            Terminated::Turns(turns) => turns.contains(&turn_number),
+            Terminated::All => true,
+        }
+    }
+}
+
+// Gets copies of another query's responses, with its own id
+// A query waiting in `Client::queue`
+struct Queued {
+    query: KataQuery,
+    tx: mpsc::UnboundedSender<KataResponse>,
+    cancelled: oneshot::Receiver<()>,
+    // Dropped once the query finished
+    _done: oneshot::Sender<()>,
+}
+
+struct Follower {
+    id: QueryId,
+    tx: mpsc::UnboundedSender<KataResponse>,
+    _done: oneshot::Sender<()>,
+}
+
+// Routes responses back to the action which caused them, so many queries can be in flight on one
+// engine at once. Cloning is cheap and all clones share the engine.
+#[derive(Clone)]
+pub struct Client {
+    shared: Arc<Shared>,
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        Default::default()
+    }
+
+    pub fn new<Si, St>(sink: Si, stream: St) -> Self
+    where
+        Si: Sink<KataAction> + Send + 'static,
+        St: Stream<Item = KataResponse> + Send + 'static,
+    {
+        Self::builder().build(sink, stream)
+    }
+
+    pub fn next_id(&self) -> QueryId {
+        self.shared.ids.next_id()
+    }
+
+    // Queries and actions waiting for the engine, for routing between engines
+    #[cfg(feature = "process")]
+    pub(crate) fn in_flight(&self) -> usize {
+        let routes = self.shared.routes.lock().unwrap();
+        routes.pending.len() + routes.queued
+    }
+
+    #[cfg(feature = "process")]
+    pub(crate) fn is_closed(&self) -> bool {
+        self.shared.routes.lock().unwrap().closed
+    }
+
+    // Of every query finished so far, answers from the result cache and deduplicated queries
+    // following another aren't counted
+    pub fn latency(&self) -> LatencyHistogram {
+        self.shared.metrics.latency()
+    }
+
+    // Of this client's engine. Answers from the result cache and deduplicated queries following
+    // another don't add to it.
+    pub fn throughput(&self) -> Throughput {
+        self.shared.metrics.throughput()
+    }
+
+    // A builder with the client's query defaults and a fresh id
+    pub fn query(&self) -> KataQueryBuilder {
+        let mut query = self.shared.query_defaults.clone();
+        query.id(self.next_id());
+        query
+    }
+
+    pub fn submit(&self, query: KataQuery) -> Result<QueryHandle, ClientError> {
+        self.submit_tracked(query).map(|(handle, _)| handle)
+    }
+
+    // Submits with the lane's priority, unless the query has its own
+    pub fn submit_to(
+        &self,
+        lane: QueryLane,
+        mut query: KataQuery,
+    ) -> Result<QueryHandle, ClientError> {
+        query
+            .priority
+            .get_or_insert_with(|| self.lane_priority(lane));
+        match (&self.shared.batch_slots, lane) {
+            (Some(batch_slots), QueryLane::Batch) => {
+                if self.shared.routes.lock().unwrap().draining {
+                    return Err(ClientError::Draining);
+                }
+                self.check_unique(&query.id)?;
+                let (handle, _) = self.queue(vec![query], Some(batch_slots.clone())).remove(0);
+                Ok(handle)
+            }
+            _ => self.submit(query),
+        }
+    }
+
+    // The handles get the responses once the queries were looked up in the result cache, got the
+    // slot, if any, and ran. Those that missed the cache are written with a single flush. Errors
+    // submitting them then are passed on as error responses.
+    fn queue(
+        &self,
+        queries: Vec<KataQuery>,
+        slot: Option<Arc<Semaphore>>,
+    ) -> Vec<(QueryHandle, oneshot::Receiver<()>)> {
+        let count = queries.len();
+        let mut handles = Vec::with_capacity(count);
+        let mut queued = Vec::with_capacity(count);
+        {
+            let mut cancels = self.shared.queued.lock().unwrap();
+            for query in queries {
+                let (tx, responses) = mpsc::unbounded_channel();
+                let (cancel, cancelled) = oneshot::channel();
+                let (done, done_rx) = oneshot::channel();
+                cancels.insert(query.id.clone(), cancel);
+                handles.push((
+                    QueryHandle {
+                        id: query.id.clone(),
+                        query: Arc::new(query.clone()),
+                        responses,
+                        client: self.clone(),
+                        expired: None,
+                        metadata: None,
+                    },
+                    done_rx,
+                ));
+                queued.push(Queued {
+                    query,
+                    tx,
+                    cancelled,
+                    _done: done,
+                });
+            }
+        }
+        {
+            let mut routes = self.shared.routes.lock().unwrap();
+            routes.queued += count;
+            routes.publish();
+        }
+        self.shared
+            .runtime
+            .spawn(self.clone().run_queued(queued, slot));
+        handles
+    }
+
+    async fn run_queued(self, queued: Vec<Queued>, slot: Option<Arc<Semaphore>>) {
+        #[cfg(feature = "cache")]
+        let mut queued = self.serve_cached(queued).await;
+        #[cfg(not(feature = "cache"))]
+        let mut queued = queued;
+        if queued.is_empty() {
+            return;
+        }
+        // Given up on once every query waiting for it was cancelled
+        let _slot = match slot {
+            Some(slots) => {
+                let cancelled = join_all(queued.iter_mut().map(|queued| &mut queued.cancelled));
+                match select(pin!(slots.acquire_owned()), cancelled).await {
+                    Either::Left((Ok(slot), _)) => Some(slot),
+                    _ => return,
+                }
+            }
+            None => None,
+        };
+        // Terminated right before
+        let queued = {
+            let mut cancels = self.shared.queued.lock().unwrap();
+            queued
+                .into_iter()
+                .filter(|queued| cancels.remove(&queued.query.id).is_some())
+                .collect::<Vec<_>>()
+        };
+        if queued.is_empty() {
+            return;
+        }
+        // Accepted before any drain started, so they still run during one. Counted as queued
+        // until they're pending, so the in flight count doesn't drop to 0 in between.
+        let queries = queued.iter().map(|queued| queued.query.clone()).collect();
+        let submitted = self.track_queries(queries);
+        self.shared.routes.lock().unwrap().dequeue(queued.len());
+        let handles = match submitted {
+            Ok(handles) => handles,
+            Err(err) => {
+                for queued in queued {
+                    let _ = queued.tx.send(KataResponse::Error {
+                        id: Some(queued.query.id),
+                        error: err.to_string(),
+                        field: None,
+                    });
+                }
+                return;
+            }
+        };
+        // Keeps the slot until the queries finished, even if nobody listens anymore
+        join_all(
+            handles
+                .into_iter()
+                .zip(queued)
+                .map(|(mut handle, queued)| async move {
+                    while let Some(response) = handle.next().await {
+                        let _ = queued.tx.send(response);
+                    }
+                }),
+        )
+        .await;
+    }
+
+    // The queries that missed the cache, hits are answered right away
+    #[cfg(feature = "cache")]
+    async fn serve_cached(&self, queued: Vec<Queued>) -> Vec<Queued> {
+        let Some(cache) = self.shared.result_cache.clone() else {
+            return queued;
+        };
+        let keys = queued
+            .iter()
+            .map(|queued| cache.key(&queued.query))
+            .collect::<Vec<_>>();
+        // Reads files, which would block the runtime
+        let found = self
+            .shared
+            .runtime
+            .spawn_blocking(move || {
+                keys.iter()
+                    .map(|key| cache.get(key).ok().flatten())
+                    .collect::<Vec<_>>()
+            })
+            .await;
+        let Ok(found) = found else {
+            return queued;
+        };
+        let mut misses = Vec::new();
+        for (queued, results) in queued.into_iter().zip(found) {
+            let Some(results) = results else {
+                misses.push(queued);
+                continue;
+            };
+            // Terminated while it was looked up
+            if self
+                .shared
+                .queued
+                .lock()
+                .unwrap()
+                .remove(&queued.query.id)
+                .is_none()
+            {
+                continue;
+            }
+            self.shared.routes.lock().unwrap().dequeue(1);
+            for mut result in results {
+                result.for_each_id_mut(|id| *id = queued.query.id.clone());
+                if let Some(reported_as) = self.shared.report_analysis_winrates_as {
+                    result.tag_perspective(reported_as);
+                }
+                let _ = queued.tx.send(result);
+            }
+        }
+        misses
+    }
+
+    fn track_queries(&self, mut queries: Vec<KataQuery>) -> Result<Vec<QueryHandle>, ClientError> {
+        if queries.len() == 1 {
+            let (handle, _) = self.track_query(queries.remove(0))?;
+            return Ok(vec![handle]);
+        }
+        for query in &mut queries {
+            self.check_supported(query)?;
+        }
+        self.track_all(queries)
+    }
+
+    fn lane_priority(&self, lane: QueryLane) -> Priority {
+        self.shared
+            .lane_priorities
+            .get(&lane)
+            .copied()
+            .unwrap_or_else(|| lane.default_priority())
+    }
+
+    fn submit_tracked(
+        &self,
+        query: KataQuery,
+    ) -> Result<(QueryHandle, oneshot::Receiver<()>), ClientError> {
+        if self.shared.routes.lock().unwrap().draining {
+            return Err(ClientError::Draining);
+        }
+        #[cfg(feature = "cache")]
+        if self.shared.result_cache.is_some() {
+            let mut query = query;
+            self.check_supported(&mut query)?;
+            self.check_unique(&query.id)?;
+            return Ok(self.queue(vec![query], None).remove(0));
+        }
+        self.track_query(query)
+    }
+
+    fn track_query(
+        &self,
+        mut query: KataQuery,
+    ) -> Result<(QueryHandle, oneshot::Receiver<()>), ClientError> {
+        self.check_supported(&mut query)?;
+        self.check_unique(&query.id)?;
+        let key = self
+            .shared
+            .deduplicate_queries
+            .then(|| canonical_search_json(&query));
+        if let Some(key) = &key {
+            let followed = {
+                let mut routes = self.shared.routes.lock().unwrap();
+                if routes.is_outstanding(&query.id) {
+                    return Err(ClientError::DuplicateId(query.id));
+                }
+                routes.follow(key, &query.id)
+            };
+            if let Some((responses, done)) = followed {
+                let handle = QueryHandle {
+                    id: query.id.clone(),
+                    query: Arc::new(query),
+                    responses,
+                    client: self.clone(),
+                    expired: None,
+                    metadata: None,
+                };
+                return Ok((handle, done));
+            }
+        }
+        let turns = query.turn_count();
+        if let Some(every_queries) = self.shared.cache_clear_policy.every_queries {
+            let mut queries_since_clear = self.shared.queries_since_clear.lock().unwrap();
+            if *queries_since_clear >= every_queries {
+                self.send_clear_cache()?;
+                *queries_since_clear = 0;
+            }
+            *queries_since_clear += 1;
+        }
+
+        let on_complete = self.store_on_complete(&query);
+        let id = query.id.clone();
+        let original = Arc::new(query.clone());
+        let (responses, done) =
+            self.send_with(KataAction::Query { inner: query }, turns, on_complete)?;
+        if let Some(key) = key {
+            self.shared.routes.lock().unwrap().lead(key, &id);
+        }
+        let handle = QueryHandle {
+            id,
+            query: original,
+            responses,
+            client: self.clone(),
+            expired: None,
+            metadata: None,
+        };
+        Ok((handle, done))
+    }
+
+    // Like `submit` for every query, but they're all written to the engine with a single flush,
+    // which matters for bulk analysis. Nothing is submitted if any of them is unsupported.
+    pub fn submit_all(
+        &self,
+        queries: impl IntoIterator<Item = KataQuery>,
+    ) -> Result<Vec<QueryHandle>, ClientError> {
+        let mut queries = queries.into_iter().collect::<Vec<_>>();
+        let mut ids = HashSet::new();
+        for query in &mut queries {
+            self.check_supported(query)?;
+            self.check_unique(&query.id)?;
+            if !ids.insert(&query.id) {
+                return Err(ClientError::DuplicateId(query.id.clone()));
+            }
+        }
+        if self.shared.routes.lock().unwrap().draining {
+            return Err(ClientError::Draining);
+        }
+        #[cfg(feature = "cache")]
+        if self.shared.result_cache.is_some() {
+            let handles = self.queue(queries, None);
+            return Ok(handles.into_iter().map(|(handle, _)| handle).collect());
+        }
+        self.track_all(queries)
+    }
+
+    fn track_all(&self, queries: Vec<KataQuery>) -> Result<Vec<QueryHandle>, ClientError> {
+        let mut queries_since_clear = self.shared.queries_since_clear.lock().unwrap();
+        let mut routes = self.shared.routes.lock().unwrap();
+        if routes.closed {
+            return Err(ClientError::Closed);
+        }
+        if let Some(query) = queries
+            .iter()
+            .find(|query| routes.is_outstanding(&query.id))
+        {
+            return Err(ClientError::DuplicateId(query.id.clone()));
+        }
+        let mut actions = Vec::with_capacity(queries.len());
+        let mut handles = Vec::with_capacity(queries.len());
+        for query in queries {
+            let key = self
+                .shared
+                .deduplicate_queries
+                .then(|| canonical_search_json(&query));
+            if let Some(key) = &key {
+                if let Some((responses, _)) = routes.follow(key, &query.id) {
+                    handles.push(QueryHandle {
+                        id: query.id.clone(),
+                        query: Arc::new(query),
+                        responses,
+                        client: self.clone(),
+                        expired: None,
+                        metadata: None,
+                    });
+                    continue;
+                }
+            }
+            let on_complete = self.store_on_complete(&query);
+            if let Some(every_queries) = self.shared.cache_clear_policy.every_queries {
+                if *queries_since_clear >= every_queries {
+                    routes.dirty = false;
+                    actions.push(KataAction::ClearCache {
+                        id: self.next_id(),
+                        action: ActionClearCache::ActionClearCache,
+                    });
+                    *queries_since_clear = 0;
+                }
+                *queries_since_clear += 1;
+            }
+            let turns = query.turn_count();
+            let original = Arc::new(query.clone());
+            let action = KataAction::Query { inner: query };
+            let timing = Timing::start(&action, &self.shared.metrics);
+            let (responses, _) = routes.track(&action, turns, on_complete, timing);
+            if let Some(key) = key {
+                routes.lead(key, action.id());
+            }
+            handles.push(QueryHandle {
+                id: action.id().clone(),
+                query: original,
+                responses,
+                client: self.clone(),
+                expired: None,
+                metadata: None,
+            });
+            actions.push(action);
+        }
+        if self
+            .shared
+            .actions
+            .send(Outgoing::Actions(actions))
+            .is_err()
+        {
+            routes.close();
+            return Err(ClientError::Closed);
+        }
+        Ok(handles)
+    }
+
+    // Terminates the query once `cancel` completes, e.g. with `CancellationToken::cancelled_owned`.
+    // Nothing happens if it completes after the query finished.
+    pub fn submit_with_cancellation(
+        &self,
+        query: KataQuery,
+        cancel: impl Future<Output = ()> + Send + 'static,
+    ) -> Result<QueryHandle, ClientError> {
+        let id = query.id.clone();
+        let (handle, done) = self.submit_tracked(query)?;
+        let client = self.clone();
+        self.shared.runtime.spawn(async move {
+            if let Either::Left(_) = select(pin!(cancel), done).await {
+                let _ = client.terminate(&id, None).await;
+            }
+        });
+        Ok(handle)
+    }
+
+    // Gives up on the query once it outlived `ttl`, for work that goes stale, e.g. positions an
+    // interactive user already moved on from. Its handle's `result` and `results` then fail with
+    // `ClientError::Expired`, the responses of the terminated search are still streamed.
+    pub fn submit_with_ttl(
+        &self,
+        lane: QueryLane,
+        mut query: KataQuery,
+        ttl: QueryTtl,
+    ) -> Result<QueryHandle, ClientError> {
+        let original = Arc::new(query.clone());
+        let forced =
+            matches!(ttl, QueryTtl::UntilStarted(_)) && query.report_during_search_every.is_none();
+        if forced {
+            query.report_during_search_every = Some(STARTED_REPORT_EVERY);
+        }
+        let mut inner = self.submit_to(lane, query)?;
+        let id = inner.id.clone();
+        let expired = Arc::new(AtomicBool::new(false));
+        let (tx, responses) = mpsc::unbounded_channel();
+        let handle = QueryHandle {
+            id: id.clone(),
+            query: original,
+            responses,
+            client: self.clone(),
+            expired: Some(expired.clone()),
+            metadata: None,
+        };
+        let client = self.clone();
+        self.shared.runtime.spawn(async move {
+            let mut deadline = Some(Box::pin(tokio::time::sleep(ttl.duration())));
+            loop {
+                let response = match &mut deadline {
+                    Some(sleep) => match select(inner.next(), sleep.as_mut()).await {
+                        Either::Left((response, _)) => response,
+                        Either::Right(_) => {
+                            deadline = None;
+                            // Set first, so whatever the termination brings is already expired
+                            expired.store(true, Ordering::Relaxed);
+                            let client = client.clone();
+                            let id = id.clone();
+                            tokio::spawn(async move { client.terminate(&id, None).await });
+                            continue;
+                        }
+                    },
+                    None => inner.next().await,
+                };
+                let Some(response) = response else {
+                    break;
+                };
+                if let QueryTtl::UntilStarted(_) = ttl {
+                    deadline = None;
+                }
+                if forced && response.is_during_search() {
+                    continue;
+                }
+                let _ = tx.send(response);
+            }
+        });
+        Ok(handle)
+    }
+
+    // Attaches `metadata` to the query, e.g. the game, move number and user it was made for, so it
+    // doesn't have to be encoded in the id. The handle returns it with the results, and
+    // `metadata` finds it by id while the query is in flight, for code which only sees responses.
+    pub fn submit_with_metadata<T: Any + Send + Sync>(
+        &self,
+        query: KataQuery,
+        metadata: T,
+    ) -> Result<QueryHandle, ClientError> {
+        let metadata: Metadata = Arc::new(metadata);
+        let id = query.id.clone();
+        let mut handle = self.submit(query)?;
+        let mut attached = self.shared.metadata.lock().unwrap();
+        attached.retain(|id, _| self.is_outstanding(id));
+        attached.insert(id, metadata.clone());
+        handle.metadata = Some(metadata);
+        Ok(handle)
+    }
+
+    // None once the query finished, or when it has none or metadata of another type
+    pub fn metadata<T: Any + Send + Sync>(&self, id: &QueryId) -> Option<Arc<T>> {
+        let metadata = self.shared.metadata.lock().unwrap().get(id)?.clone();
+        metadata.downcast().ok()
+    }
+
+    // Calls `callback` with the final results of every analyzed turn once the query finished, for
+    // integrations which prefer being notified over holding a handle
+    pub fn submit_with_callback(
+        &self,
+        query: KataQuery,
+        callback: impl FnOnce(Result<Vec<KataResponse>, ClientError>) + Send + 'static,
+    ) -> Result<QueryId, ClientError> {
+        let handle = self.submit(query)?;
+        let id = handle.id().clone();
+        self.shared
+            .runtime
+            .spawn(async move { callback(handle.results().await) });
+        Ok(id)
+    }
+
+    // Drains the client once `cancel` completes, see `drain`. The client isn't kept alive by this.
+    pub fn shutdown_on(
+        &self,
+        cancel: impl Future<Output = ()> + Send + 'static,
+        deadline: Option<Duration>,
+    ) {
+        let shared = Arc::downgrade(&self.shared);
+        self.shared.runtime.spawn(async move {
+            cancel.await;
+            if let Some(shared) = shared.upgrade() {
+                let _ = Client { shared }.drain(deadline).await;
+            }
+        });
+    }
+
+    pub async fn terminate(
+        &self,
+        id: &QueryId,
+        turn_numbers: Option<Vec<u32>>,
+    ) -> Result<KataResponse, ClientError> {
+        // Queries still waiting for a batch slot are never sent. A search other deduplicated
+        // queries wait for keeps running. In both cases only `id` is acknowledged.
+        let unqueued =
+            turn_numbers.is_none() && self.shared.queued.lock().unwrap().remove(id).is_some();
+        if unqueued {
+            self.shared.routes.lock().unwrap().dequeue(1);
+        }
+        if unqueued || (turn_numbers.is_none() && self.shared.routes.lock().unwrap().detach(id)) {
+            return Ok(KataResponse::TerminateAck {
+                id: self.next_id(),
+                action: ActionTerminate::ActionTerminate,
+                turn_number: None,
+                terminate_id: id.clone(),
+            });
+        }
+        self.request(KataAction::Terminate {
+            id: self.next_id(),
+            action: ActionTerminate::ActionTerminate,
+            terminate_id: id.clone(),
+            turn_numbers,
+        })
+        .await
+    }
+
+    // The session called `name`, created with the client's defaults if there's none yet
+    pub fn game_session(&self, name: impl Into<String>) -> GameSession {
+        let name = name.into();
+        let state = self
+            .shared
+            .sessions
+            .lock()
+            .unwrap()
+            .entry(name.clone())
+            .or_default()
+            .clone();
+        GameSession::new(self.clone(), name, state)
+    }
+
+    // Every session not closed yet
+    pub fn sessions(&self) -> Vec<GameSession> {
+        let sessions = self.shared.sessions.lock().unwrap();
+        sessions
+            .iter()
+            .map(|(name, state)| GameSession::new(self.clone(), name.clone(), state.clone()))
+            .collect()
+    }
+
+    pub(crate) fn forget_session(&self, name: &str) {
+        self.shared.sessions.lock().unwrap().remove(name);
+    }
+
+    // Sent or waiting for a batch slot, and not finished yet
+    pub(crate) fn is_outstanding(&self, id: &QueryId) -> bool {
+        self.shared.queued.lock().unwrap().contains_key(id)
+            || self.shared.routes.lock().unwrap().is_outstanding(id)
+    }
+
+    pub fn capabilities(&self) -> EngineCapabilities {
+        self.shared.capabilities.lock().unwrap().clone()
+    }
+
+    // Finds the engine's limits with a few single visit queries and checks later queries against
+    // them. Only what wasn't declared is probed. Queries submitted while it runs are only checked
+    // against the declared limits.
+    pub async fn detect_capabilities(&self) -> Result<EngineCapabilities, ClientError> {
+        let declared = self.shared.declared_capabilities.clone();
+        *self.shared.capabilities.lock().unwrap() = declared.clone();
+        let mut detected = EngineCapabilities::new();
+
+        let sizes = if declared.max_board_size.is_none() {
+            &BOARD_SIZES[..]
+        } else {
+            &[]
+        };
+        for &size in sizes {
+            match self.submit(self.capability_probe(size)?)?.result().await {
+                Ok(_) => {
+                    detected.max_board_size = Some(size);
+                    break;
+                }
+                Err(ClientError::Rejected { .. }) => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        if declared.ownership_stdev.is_none() {
+            let mut probe = self.capability_probe(9)?;
+            probe.include_ownership = Some(true);
+            probe.inlcude_ownership_stdev = Some(true);
+            let result = self.submit(probe)?.result().await?;
+            detected.ownership_stdev = Some(matches!(
+                result,
+                KataResponse::Result {
+                    ownership_stdev: Some(_),
+                    ..
+                }
+            ));
+        }
+
+        if declared.human_model.is_none() {
+            let mut probe = self.capability_probe(9)?;
+            probe.override_settings = Some(serde_json::json!({ "humanSLProfile": "rank_9d" }));
+            detected.human_model = match self.submit(probe)?.result().await {
+                Ok(_) => Some(true),
+                Err(ClientError::Rejected { .. }) => Some(false),
+                Err(error) => return Err(error),
+            };
+        }
+
+        let capabilities = declared.or(detected);
+        *self.shared.capabilities.lock().unwrap() = capabilities.clone();
+        Ok(capabilities)
+    }
+
+    pub async fn query_version(&self) -> Result<KataResponse, ClientError> {
+        self.request(KataAction::QueryVersion {
+            id: self.next_id(),
+            action: ActionQueryVersion::ActionQueryVersion,
+        })
+        .await
+    }
+
+    pub async fn clear_cache(&self) -> Result<KataResponse, ClientError> {
+        self.request(KataAction::ClearCache {
+            id: self.next_id(),
+            action: ActionClearCache::ActionClearCache,
+        })
+        .await
+    }
+
+    // Stops accepting new queries and waits for the ones in flight to finish, batch queries waiting
+    // for a slot included. Queries still running after `deadline` are terminated, and queued ones
+    // dropped. Afterwards the engine is shut down and this returns once its
+    // output ended.
+    pub async fn drain(&self, deadline: Option<Duration>) -> Result<(), ClientError> {
+        let mut in_flight = {
+            let mut routes = self.shared.routes.lock().unwrap();
+            routes.draining = true;
+            routes.in_flight.subscribe()
+        };
+        let finished_in_time = match deadline {
+            // Timed on the client's runtime, the caller's needn't be tokio's
+            Some(deadline) => {
+                let mut in_flight = in_flight.clone();
+                self.shared
+                    .runtime
+                    .spawn(async move {
+                        let finished = in_flight.wait_for(|in_flight| *in_flight == 0);
+                        tokio::time::timeout(deadline, finished).await.is_ok()
+                    })
+                    .await
+                    .unwrap_or(false)
+            }
+            None => in_flight
+                .wait_for(|in_flight| *in_flight == 0)
+                .await
+                .is_ok(),
+        };
+
+        if !finished_in_time {
+            // Batch queries still waiting for a slot are never sent, their handles end without
+            // results like those of queries terminated before they were sent
+            let cancelled = std::mem::take(&mut *self.shared.queued.lock().unwrap());
+            {
+                let mut routes = self.shared.routes.lock().unwrap();
+                routes.queued = routes.queued.saturating_sub(cancelled.len());
+                routes.publish();
+            }
+            drop(cancelled);
+            // Other actions in flight end on their own
+            let queries = {
+                let routes = self.shared.routes.lock().unwrap();
+                routes
+                    .pending
+                    .iter()
+                    .filter(|(_, pending)| pending.query)
+                    .map(|(id, _)| id.clone())
+                    .collect::<Vec<_>>()
+            };
+            for id in queries {
+                // Terminate acks are part of the in flight count, so there's no need to await them
+                self.send(
+                    KataAction::Terminate {
+                        id: self.next_id(),
+                        action: ActionTerminate::ActionTerminate,
+                        terminate_id: id,
+                        turn_numbers: None,
+                    },
+                    1,
+                )?;
+            }
+            let _ = in_flight.wait_for(|in_flight| *in_flight == 0).await;
+        }
+
+        let _ = self.shared.actions.send(Outgoing::Close);
+        let reader = self.shared.reader.lock().unwrap().take();
+        if let Some(reader) = reader {
+            let _ = reader.await;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cache")]
+    fn store_on_complete(&self, query: &KataQuery) -> Option<OnComplete> {
+        let cache = self.shared.result_cache.clone()?;
+        let key = cache.key(query);
+        let runtime = self.shared.runtime.clone();
+        Some(Box::new(move |results: Vec<KataResponse>| {
+            // Queries without results, e.g. with visits 0 or terminated early, aren't worth storing,
+            // and terminated searches would be served as if they had finished
+            if results.iter().any(|result| {
+                matches!(result, KataResponse::Resultless { .. }) || result.is_terminated()
+            }) {
+                return;
+            }
+            runtime.spawn_blocking(move || {
+                let _ = cache.put(&key, &results);
+            });
+        }))
+    }
+
+    #[cfg(not(feature = "cache"))]
+    fn store_on_complete(&self, _query: &KataQuery) -> Option<OnComplete> {
+        None
+    }
+
+    // Queued batch queries count too, they'd reach the engine with the same id later
+    fn check_unique(&self, id: &QueryId) -> Result<(), ClientError> {
+        if self.is_outstanding(id) {
+            return Err(ClientError::DuplicateId(id.clone()));
+        }
+        Ok(())
+    }
+
+    fn capability_probe(&self, size: u8) -> Result<KataQuery, ClientError> {
+        KataQuery::builder()
+            .id(self.next_id())
+            .moves(Vec::new())
+            .rules(Rules::Chinese)
+            .board_x_size(size)
+            .board_y_size(size)
+            .max_visits(Some(1))
+            .build()
+            .map_err(|error| ClientError::Unsupported(error.to_string()))
+    }
+
+    fn check_supported(&self, query: &mut KataQuery) -> Result<(), ClientError> {
+        self.shared.capabilities.lock().unwrap().apply(query)
+    }
+
+    async fn request(&self, action: KataAction) -> Result<KataResponse, ClientError> {
+        let (mut responses, _) = self.send(action, 1)?;
+        match responses.recv().await {
+            Some(KataResponse::Error { error, field, .. }) => {
+                Err(ClientError::Rejected { error, field })
+            }
+            Some(response) => Ok(response),
+            None => Err(ClientError::Closed),
+        }
+    }
+
+    // The response isn't waited for, so it gets dropped by the reader
+    fn send_clear_cache(&self) -> Result<(), ClientError> {
+        let mut routes = self.shared.routes.lock().unwrap();
+        if routes.closed {
+            return Err(ClientError::Closed);
+        }
+        routes.dirty = false;
+        self.shared
+            .actions
+            .send(Outgoing::Action(KataAction::ClearCache {
+                id: self.next_id(),
+                action: ActionClearCache::ActionClearCache,
+            }))
+            .map_err(|_| ClientError::Closed)
+    }
+
+    fn send(
+        &self,
+        action: KataAction,
+        remaining: usize,
+    ) -> Result<(mpsc::UnboundedReceiver<KataResponse>, oneshot::Receiver<()>), ClientError> {
+        self.send_with(action, remaining, None)
+    }
+
+    fn send_with(
+        &self,
+        action: KataAction,
+        remaining: usize,
+        on_complete: Option<OnComplete>,
+    ) -> Result<(mpsc::UnboundedReceiver<KataResponse>, oneshot::Receiver<()>), ClientError> {
+        let mut routes = self.shared.routes.lock().unwrap();
+        if routes.closed {
+            return Err(ClientError::Closed);
+        }
+        // Checked again under the lock the query is tracked with, in case of a race
+        if routes.is_outstanding(action.id()) {
+            return Err(ClientError::DuplicateId(action.id().clone()));
+        }
+        if let KataAction::Terminate {
+            terminate_id,
+            turn_numbers,
+            ..
+        } = &action
+        {
+            // Results cut short by a terminate aren't the query's real results. Flagged before
+            // it's sent, so nothing katago sends in reply to it slips through unflagged.
+            if let Some(pending) = routes.pending.get_mut(terminate_id) {
+                pending.on_complete = None;
+            }
+            routes.terminate(terminate_id, turn_numbers.as_deref());
+        }
+        let timing = Timing::start(&action, &self.shared.metrics);
+        let (rx, done_rx) = routes.track(&action, remaining, on_complete, timing);
+        // Sending while holding the lock keeps the write order consistent with `pending`
+        if self.shared.actions.send(Outgoing::Action(action)).is_err() {
+            routes.close();
+            return Err(ClientError::Closed);
+        }
+        Ok((rx, done_rx))
+    }
+}
+
+// A final result and the query it answers, which shares one allocation with every other result
+// of the query
+#[derive(Clone, Debug)]
+pub struct QueryResult {
+    pub query: Arc<KataQuery>,
+    pub response: KataResponse,
+}
+
+// Yields every response to the query, interim ones included, and ends after the final one
+pub struct QueryHandle {
+    id: QueryId,
+    // As sent, after defaults and capability checks
+    query: Arc<KataQuery>,
+    responses: mpsc::UnboundedReceiver<KataResponse>,
+    client: Client,
+    // Only for queries submitted with a TTL
+    expired: Option<Arc<AtomicBool>>,
+    metadata: Option<Metadata>,
+}
+
+impl QueryHandle {
+    pub fn id(&self) -> &QueryId {
+        &self.id
+    }
+
+    // The query the responses are for, e.g. for its board size and rules
+    pub fn query(&self) -> &Arc<KataQuery> {
+        &self.query
+    }
+
+    // Whether the query outlived its TTL and was dropped or terminated
+    pub fn is_expired(&self) -> bool {
+        self.expired
+            .as_ref()
+            .is_some_and(|expired| expired.load(Ordering::Relaxed))
+    }
+
+    // Waits for the first final result, skipping interim ones. Queries analyzing several turns
+    // should use `results` instead.
+    pub async fn result(mut self) -> Result<KataResponse, ClientError> {
+        self.next_final().await?.ok_or(ClientError::Closed)
+    }
+
+    // Waits for the final results of every analyzed turn, in the order they arrive
+    pub async fn results(mut self) -> Result<Vec<KataResponse>, ClientError> {
+        let mut results = Vec::new();
+        while let Some(result) = self.next_final().await? {
+            results.push(result);
+        }
+        if results.is_empty() {
+            return Err(ClientError::Closed);
+        }
+        Ok(results)
+    }
+
+    pub async fn terminate(&self) -> Result<KataResponse, ClientError> {
+        self.client.terminate(&self.id, None).await
+    }
+
+    // Like `result`, with the query it's for
+    pub async fn result_with_query(self) -> Result<QueryResult, ClientError> {
+        let query = self.query.clone();
+        let response = self.result().await?;
+        Ok(QueryResult { query, response })
+    }
+
+    // Like `results`, each with the query they're for
+    pub async fn results_with_query(self) -> Result<Vec<QueryResult>, ClientError> {
+        let query = self.query.clone();
+        let results = self.results().await?;
+        Ok(results
+            .into_iter()
+            .map(|response| QueryResult {
+                query: query.clone(),
+                response,
+            })
+            .collect())
+    }
+
+    // What the query was submitted with, None when it has none of type `T`
+    pub fn metadata<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.metadata.clone()?.downcast().ok()
+    }
+
+    // Like `results`, with the query's metadata
+    pub async fn results_with_metadata<T: Any + Send + Sync>(
+        self,
+    ) -> Result<(Vec<KataResponse>, Option<Arc<T>>), ClientError> {
+        let metadata = self.metadata();
+        Ok((self.results().await?, metadata))
+    }
+
+    async fn next_final(&mut self) -> Result<Option<KataResponse>, ClientError> {
+        while let Some(response) = self.responses.recv().await {
+            if self.is_expired() {
+                return Err(ClientError::Expired);
+            }
+            match response {
+                KataResponse::Error { error, field, .. } => {
+                    return Err(ClientError::Rejected { error, field })
+                }
+                KataResponse::Warning { .. } => {}
+                response if response.is_during_search() => {}
+                response => return Ok(Some(response)),
+            }
+        }
+        if self.is_expired() {
+            return Err(ClientError::Expired);
+        }
+        Ok(None)
+    }
+}
+
+impl Stream for QueryHandle {
+    type Item = KataResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.responses.poll_recv(cx)
+    }
+}
+
+// Writes everything queued so far before flushing, so bursts of submissions share one flush
+async fn write<Si>(sink: Si, mut rx: mpsc::UnboundedReceiver<Outgoing>, routes: Arc<Mutex<Routes>>)
+where
+    Si: Sink<KataAction>,
+{
+    let mut sink = pin!(sink);
+    'outer: while let Some(outgoing) = rx.recv().await {
+        let mut next = Some(outgoing);
+        while let Some(outgoing) = next {
+            match outgoing {
+                Outgoing::Action(action) => {
+                    if sink.feed(action).await.is_err() {
+                        break 'outer;
+                    }
+                }
+                Outgoing::Actions(actions) => {
+                    for action in actions {
+                        if sink.feed(action).await.is_err() {
+                            break 'outer;
+                        }
+                    }
+                }
+                Outgoing::Close => {
+                    // Responses may still arrive, the reader closes the routes once they stop
+                    let _ = sink.close().await;
+                    return;
+                }
+            }
+            next = rx.try_recv().ok();
+        }
+        if sink.flush().await.is_err() {
+            break;
+        }
+    }
+    routes.lock().unwrap().close();
+}
+
+async fn read<St>(
+    stream: St,
+    routes: Arc<Mutex<Routes>>,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+) where
+    St: Stream<Item = KataResponse>,
+{
+    let mut stream = pin!(stream);
+    while let Some(mut response) = stream.next().await {
+        if let Some(reported_as) = report_analysis_winrates_as {
+            response.tag_perspective(reported_as);
+        }
+        let Some(id) = response.id().cloned() else {
+            continue;
+        };
+        let mut routes = routes.lock().unwrap();
+        let Some(pending) = routes.pending.get_mut(&id) else {
+            continue;
+        };
+        // Once a turn is terminated katago may or may not send another interim result before the
+        // final one, depending on timing. They're dropped, so what a terminated turn yields is
+        // always the interim results from before the terminate and a final result flagged as
+        // terminated.
+        if let KataResponse::Result {
+            turn_number,
+            is_during_search,
+            terminated,
+            ..
+        } = &mut response
+        {
+            if pending.terminated.covers(*turn_number) {
+                if *is_during_search {
+                    continue;
+                }
+                *terminated = true;
+            }
+        }
+        let done = match &response {
+            KataResponse::Warning { .. } => false,
+            KataResponse::Result { .. } | KataResponse::Resultless { .. } => {
+                if let Some(timing) = &mut pending.timing {
+                    timing.observe(&response);
+                }
+                if !response.is_during_search() {
+                    pending.remaining -= 1;
+                    if pending.on_complete.is_some() {
+                        pending.finals.push(response.clone());
+                    }
+                    if pending.remaining == 0 {
+                        if let Some(timing) = pending.timing.take() {
+                            timing.finish();
+                        }
+                    }
+                }
+                pending.remaining == 0
+            }
+            _ => true,
+        };
+        if pending.remaining == 0 {
+            if let Some(on_complete) = pending.on_complete.take() {
+                on_complete(std::mem::take(&mut pending.finals));
+            }
+        }
+        for follower in &pending.followers {
+            let mut response = response.clone();
+            response.for_each_id_mut(|response_id| *response_id = follower.id.clone());
+            let _ = follower.tx.send(response);
+        }
+        // The handle may have been dropped already, the bookkeeping above still applies
+        let _ = pending.tx.send(response);
+        if done {
+            routes.remove(&id);
+            routes.last_activity = Instant::now();
+        }
+    }
+    routes.lock().unwrap().close();
+}
+
+async fn clear_when_idle(shared: Weak<Shared>, idle_for: Duration) {
+    loop {
+        let (deadline, mut in_flight) = {
+            let Some(shared) = shared.upgrade() else {
+                return;
+            };
+            let routes = shared.routes.lock().unwrap();
+            if routes.closed {
+                return;
+            }
+            let in_flight = (!routes.pending.is_empty()).then(|| routes.in_flight.subscribe());
+            (routes.last_activity + idle_for, in_flight)
+        };
+        // The deadline is already past while queries run longer than `idle_for`, so wait for them
+        // to finish first and only then for the idle time
+        if let Some(in_flight) = &mut in_flight {
+            if in_flight
+                .wait_for(|in_flight| *in_flight == 0)
+                .await
+                .is_err()
+            {
+                return;
+            }
+            continue;
+        }
+        tokio::time::sleep_until(deadline).await;
+
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        let client = Client { shared };
+        let clear = {
+            let mut routes = client.shared.routes.lock().unwrap();
+            let idle =
+                routes.pending.is_empty() && routes.last_activity + idle_for <= Instant::now();
+            if idle && !routes.dirty {
+                // Nothing to clear, wait for the next activity
+                routes.last_activity = Instant::now();
+            }
+            idle && routes.dirty
+        };
+        if clear && client.send_clear_cache().is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_util::sync::PollSender;
+
+    use super::*;
+    use crate::Rules;
+
+    const RESULT: &str = include_str!("../tests/corpus/responses.jsonl");
+
+    fn batch_query(client: &Client, id: &str) -> KataQuery {
+        client
+            .query()
+            .id(id)
+            .moves(Vec::new())
+            .rules(Rules::Japanese)
+            .board_x_size(19)
+            .board_y_size(19)
+            .build()
+            .unwrap()
+    }
+
+    fn result(id: &QueryId) -> KataResponse {
+        // The first result of the corpus, for query `q1`
+        let line = RESULT.lines().nth(1).unwrap();
+        KataResponse::from_json(&line.replace("\"id\":\"q1\"", &format!("\"id\":\"{id}\"")))
+            .unwrap()
+    }
+
+    fn in_flight(client: &Client) -> usize {
+        *client.shared.routes.lock().unwrap().in_flight.borrow()
+    }
+
+    #[test]
+    fn drain_waits_for_queued_batch_queries() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (sink, mut engine) = mpsc::channel::<KataAction>(16);
+            let (responses, stream) = mpsc::unbounded_channel();
+            let client = Client::builder()
+                .batch_in_flight_limit(1)
+                .build(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+
+            let running = client
+                .submit_to(QueryLane::Batch, batch_query(&client, "running"))
+                .unwrap();
+            let queued = client
+                .submit_to(QueryLane::Batch, batch_query(&client, "queued"))
+                .unwrap();
+            assert_eq!(in_flight(&client), 2);
+
+            let drain = tokio::spawn({
+                let client = client.clone();
+                async move { client.drain(None).await }
+            });
+            tokio::task::yield_now().await;
+            assert!(matches!(
+                client.submit_to(QueryLane::Batch, batch_query(&client, "late")),
+                Err(ClientError::Draining)
+            ));
+
+            // Answered one at a time, the queued query only runs once the first one finished
+            for expected in ["running", "queued"] {
+                let query = engine.recv().await.unwrap();
+                assert_eq!(query.id().as_str(), expected);
+                responses.send(result(query.id())).unwrap();
+            }
+            assert!(running.result().await.is_ok());
+            assert!(queued.result().await.is_ok());
+
+            // Closing the sink ends the engine's output
+            assert!(engine.recv().await.is_none());
+            drop(responses);
+            drain.await.unwrap().unwrap();
+        });
+    }
+
+    #[test]
+    fn drain_deadline_drops_queued_batch_queries() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (sink, mut engine) = mpsc::channel::<KataAction>(16);
+            let (responses, stream) = mpsc::unbounded_channel();
+            let client = Client::builder()
+                .batch_in_flight_limit(1)
+                .build(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+
+            let running = client
+                .submit_to(QueryLane::Batch, batch_query(&client, "running"))
+                .unwrap();
+            let queued = client
+                .submit_to(QueryLane::Batch, batch_query(&client, "queued"))
+                .unwrap();
+
+            let drain = tokio::spawn({
+                let client = client.clone();
+                async move { client.drain(Some(Duration::from_millis(50))).await }
+            });
+            assert_eq!(engine.recv().await.unwrap().id().as_str(), "running");
+            let KataAction::Terminate {
+                id, terminate_id, ..
+            } = engine.recv().await.unwrap()
+            else {
+                panic!("expected a terminate");
+            };
+            assert_eq!(terminate_id.as_str(), "running");
+            // Katago still reports what it searched of a terminated query
+            responses.send(result(&terminate_id)).unwrap();
+            responses
+                .send(KataResponse::TerminateAck {
+                    id,
+                    action: ActionTerminate::ActionTerminate,
+                    turn_number: None,
+                    terminate_id,
+                })
+                .unwrap();
+
+            assert!(engine.recv().await.is_none());
+            drop(responses);
+            drain.await.unwrap().unwrap();
+            assert_eq!(in_flight(&client), 0);
+            assert_eq!(running.results().await.unwrap().len(), 1);
+            // Never sent
+            assert!(queued.results().await.unwrap_or_default().is_empty());
+        });
+    }
+
+    #[cfg(feature = "cache")]
+    #[test]
+    fn cached_queries_skip_the_engine() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let dir =
+                std::env::temp_dir().join(format!("kpae-client-cache-{}", std::process::id()));
+            let cache = ResultCache::open(&dir).unwrap();
+            let (sink, mut engine) = mpsc::channel::<KataAction>(16);
+            let (responses, stream) = mpsc::unbounded_channel();
+            let client = Client::builder()
+                .result_cache(cache.clone())
+                .build(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+
+            let hit = batch_query(&client, "hit");
+            let stored = QueryId::new("stored");
+            cache.put(&cache.key(&hit), &[result(&stored)]).unwrap();
+            let miss = client
+                .query()
+                .id("miss")
+                .moves(Vec::new())
+                .rules(Rules::Japanese)
+                .board_x_size(9)
+                .board_y_size(9)
+                .build()
+                .unwrap();
+
+            let handles = client.submit_all([hit, miss]).unwrap();
+            let query = engine.recv().await.unwrap();
+            assert_eq!(query.id().as_str(), "miss");
+            responses.send(result(query.id())).unwrap();
+            let mut handles = handles.into_iter();
+            let hit = handles.next().unwrap().result().await.unwrap();
+            assert_eq!(hit.id().unwrap().as_str(), "hit");
+            assert!(handles.next().unwrap().result().await.is_ok());
+            assert_eq!(in_flight(&client), 0);
+            let _ = std::fs::remove_dir_all(dir);
+        });
+    }
+
+    // Driven from an executor without tokio's timers, like another runtime's would be
+    #[test]
+    fn runs_on_its_own_runtime() {
+        let background = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let runtime = background.handle().clone();
+        let (stop, stopped) = oneshot::channel::<()>();
+        let thread = std::thread::spawn(move || background.block_on(stopped));
+        let (sink, mut engine) = mpsc::channel::<KataAction>(16);
+        let (responses, stream) = mpsc::unbounded_channel();
+        let client = Client::builder()
+            .runtime(runtime)
+            .build(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+        let foreign = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        foreign.block_on(async {
+            let handle = client
+                .submit_with_ttl(
+                    QueryLane::Interactive,
+                    batch_query(&client, "q"),
+                    QueryTtl::UntilFinished(Duration::from_secs(60)),
+                )
+                .unwrap();
+            let query = engine.recv().await.unwrap();
+            responses.send(result(query.id())).unwrap();
+            assert!(handle.result().await.is_ok());
+
+            drop(responses);
+            client.drain(Some(Duration::from_secs(1))).await.unwrap();
+        });
+        drop(stop);
+        thread.join().unwrap().unwrap_err();
+    }
+
+    #[test]
+    fn until_started_asks_for_interim_reports() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (sink, mut engine) = mpsc::channel::<KataAction>(16);
+            let (responses, stream) = mpsc::unbounded_channel();
+            let client = Client::builder()
+                .build(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+
+            let handle = client
+                .submit_with_ttl(
+                    QueryLane::Interactive,
+                    batch_query(&client, "q"),
+                    QueryTtl::UntilStarted(Duration::from_millis(50)),
+                )
+                .unwrap();
+            assert!(handle.query().report_during_search_every.is_none());
+            let KataAction::Query { inner } = engine.recv().await.unwrap() else {
+                panic!("expected the query");
+            };
+            assert_eq!(inner.report_during_search_every, Some(STARTED_REPORT_EVERY));
+            let mut interim = result(&inner.id);
+            if let KataResponse::Result {
+                is_during_search, ..
+            } = &mut interim
+            {
+                *is_during_search = true;
+            }
+            responses.send(interim).unwrap();
+
+            // Started before the ttl ran out, so it isn't terminated
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(engine.try_recv().is_err());
+            responses.send(result(&inner.id)).unwrap();
+            assert!(!handle.is_expired());
+            let received = handle.collect::<Vec<_>>().await;
+            assert_eq!(received.len(), 1);
+            assert!(!received[0].is_during_search());
+        });
+    }
+}
@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+// Seed of the deterministic preset, any fixed string works
+const DETERMINISTIC_SEED: &str = "kpae";
+
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportAnalysisWinratesAs {
+    #[serde(rename = "BLACK")]
+    Black,
+    #[serde(rename = "WHITE")]
+    White,
+    #[serde(rename = "SIDETOMOVE")]
+    SideToMove,
+}
+
+// Mirrors the keys of katago's analysis_example.cfg. Unset keys are left out, so katago's own
+// defaults apply, and keys without a field can be set through `extra`.
+#[serde_with::skip_serializing_none]
+#[derive(Serialize, Clone, Debug, Default, Builder)]
+#[builder(setter(into, strip_option), default)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisConfig {
+    log_dir: Option<PathBuf>,
+    log_all_requests: Option<bool>,
+    log_all_responses: Option<bool>,
+    log_search_info: Option<bool>,
+    log_errors_and_warnings: Option<bool>,
+    log_to_stderr: Option<bool>,
+    warn_unused_fields: Option<bool>,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    #[serde(rename = "analysisPVLen")]
+    analysis_pv_len: Option<u16>,
+    wide_root_noise: Option<f64>,
+    root_noise_enabled: Option<bool>,
+    root_num_symmetries_to_sample: Option<u8>,
+    ignore_pre_root_history: Option<bool>,
+    max_visits: Option<u64>,
+    max_playouts: Option<u64>,
+    max_time: Option<f64>,
+    num_analysis_threads: Option<u32>,
+    num_search_threads_per_analysis_thread: Option<u32>,
+    nn_max_batch_size: Option<u32>,
+    nn_cache_size_power_of_two: Option<u8>,
+    nn_mutex_pool_size_power_of_two: Option<u8>,
+    nn_randomize: Option<bool>,
+    nn_rand_seed: Option<String>,
+    search_rand_seed: Option<String>,
+    #[serde(rename = "numNNServerThreadsPerModel")]
+    num_nn_server_threads_per_model: Option<u32>,
+    #[builder(setter(custom))]
+    #[serde(flatten)]
+    extra: BTreeMap<String, String>,
+}
+
+impl AnalysisConfigBuilder {
+    // Settings for results that repeat from run to run: one search thread per query and one query
+    // at a time, every symmetry evaluated at the root and the same one below it, no noise and
+    // pinned seeds. Keys set later override these.
+    //
+    // What's left to chance:
+    // - time limits, queries need `max_visits` and no `max_time`, on a run and in the query
+    // - GPU backends, cuDNN and TensorRT don't promise equal floats for equal inputs, especially
+    //   in FP16 and across batch sizes, so only the Eigen (CPU) backend repeats exactly
+    // - other hardware, drivers and katago or model versions
+    // - queries overriding any of these through `overrideSettings`
+    pub fn deterministic(&mut self) -> &mut Self {
+        self.num_analysis_threads(1u32)
+            .num_search_threads_per_analysis_thread(1u32)
+            .nn_max_batch_size(1u32)
+            .root_num_symmetries_to_sample(8)
+            .nn_randomize(false)
+            .wide_root_noise(0.0)
+            .root_noise_enabled(false)
+            .nn_rand_seed(DETERMINISTIC_SEED)
+            .search_rand_seed(DETERMINISTIC_SEED)
+    }
+
+    pub fn extra(&mut self, key: impl Into<String>, value: impl ToString) -> &mut Self {
+        self.extra
+            .get_or_insert_with(Default::default)
+            .insert(key.into(), value.to_string());
+        self
+    }
+}
+
+impl AnalysisConfig {
+    pub fn builder() -> AnalysisConfigBuilder {
+        Default::default()
+    }
+
+    pub fn report_analysis_winrates_as(&self) -> Option<ReportAnalysisWinratesAs> {
+        self.report_analysis_winrates_as
+    }
+
+    // `key = value` lines in the cfg format katago reads
+    pub fn to_cfg_string(&self) -> String {
+        let serde_json::Value::Object(keys) = serde_json::to_value(self).unwrap() else {
+            unreachable!("AnalysisConfig serializes to a map")
+        };
+        let mut cfg = String::new();
+        for (key, value) in keys {
+            match value {
+                serde_json::Value::String(value) => writeln!(cfg, "{key} = {value}"),
+                value => writeln!(cfg, "{key} = {value}"),
+            }
+            .unwrap();
+        }
+        cfg
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_cfg_string())
+    }
+
+    // Writes the config to a new file in the temp directory and returns its path. The file isn't
+    // removed automatically, katago only reads it on startup.
+    pub fn write_temp(&self) -> io::Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!(
+            "kpae-analysis-{}-{}.cfg",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        self.write_to(&path)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+#[cfg(feature = "process")]
+use std::pin::Pin;
+#[cfg(feature = "process")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "process")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "process")]
+use futures_core::Stream;
+#[cfg(feature = "process")]
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+#[cfg(feature = "process")]
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FatalErrorKind {
+    OutOfMemory,
+    ModelNotFound,
+    UnsupportedBackend,
+    // Any other uncaught exception which made katago quit
+    Other,
+}
+
+// A fatal error katago reported on stderr before quitting
+#[derive(Clone, Debug)]
+pub struct FatalError {
+    kind: FatalErrorKind,
+    message: String,
+}
+
+impl FatalError {
+    pub fn kind(&self) -> FatalErrorKind {
+        self.kind
+    }
+
+    // The stderr line the error was recognized from
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for FatalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            FatalErrorKind::OutOfMemory => "engine ran out of memory",
+            FatalErrorKind::ModelNotFound => "engine could not open the model file",
+            FatalErrorKind::UnsupportedBackend => "engine backend is not supported on this machine",
+            FatalErrorKind::Other => "engine failed",
+        };
+        write!(f, "{kind}: {}", self.message)
+    }
+}
+
+impl std::error::Error for FatalError {}
+
+// Lowercase patterns, checked in order
+const PATTERNS: &[(&str, FatalErrorKind)] = &[
+    ("out of memory", FatalErrorKind::OutOfMemory),
+    ("cudaerrormemoryallocation", FatalErrorKind::OutOfMemory),
+    ("cudnn_status_alloc_failed", FatalErrorKind::OutOfMemory),
+    (
+        "cl_mem_object_allocation_failure",
+        FatalErrorKind::OutOfMemory,
+    ),
+    ("cl_out_of_resources", FatalErrorKind::OutOfMemory),
+    ("could not open file", FatalErrorKind::ModelNotFound),
+    ("model file", FatalErrorKind::ModelNotFound),
+    ("no cuda-capable device", FatalErrorKind::UnsupportedBackend),
+    ("no opencl devices", FatalErrorKind::UnsupportedBackend),
+    ("cl_device_not_found", FatalErrorKind::UnsupportedBackend),
+    ("cl_platform_not_found", FatalErrorKind::UnsupportedBackend),
+    (
+        "cuda driver version is insufficient",
+        FatalErrorKind::UnsupportedBackend,
+    ),
+    ("not compiled with", FatalErrorKind::UnsupportedBackend),
+    ("uncaught exception", FatalErrorKind::Other),
+];
+
+pub fn classify(line: &str) -> Option<FatalError> {
+    let lowercase = line.to_lowercase();
+    let looks_fatal = lowercase.contains("error") || lowercase.contains("exception");
+    PATTERNS
+        .iter()
+        .find(|(pattern, kind)| {
+            lowercase.contains(pattern) && (looks_fatal || *kind == FatalErrorKind::OutOfMemory)
+        })
+        .map(|(_, kind)| FatalError {
+            kind: *kind,
+            message: line.trim().to_owned(),
+        })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+// A line katago logged on stderr. katago doesn't log levels, they're inferred from the message.
+#[derive(Clone, Debug)]
+pub struct LogEvent {
+    // As katago printed it, e.g. `2024-05-01 12:00:00+0200`
+    pub timestamp: Option<String>,
+    pub level: LogLevel,
+    // The backend thread for lines like `CUDA backend thread 0: Found GPU ...`
+    pub subsystem: Option<String>,
+    pub message: String,
+}
+
+impl LogEvent {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        let (timestamp, message) = match split_timestamp(line) {
+            Some((timestamp, message)) => (Some(timestamp.to_owned()), message),
+            None => (None, line),
+        };
+        let subsystem = message
+            .split_once(": ")
+            .filter(|(prefix, _)| prefix.contains("backend thread"))
+            .map(|(prefix, _)| prefix.to_owned());
+        let lowercase = message.to_lowercase();
+        let level = if classify(message).is_some()
+            || lowercase.contains("error")
+            || lowercase.contains("exception")
+        {
+            LogLevel::Error
+        } else if lowercase.contains("warning") {
+            LogLevel::Warning
+        } else {
+            LogLevel::Info
+        };
+        Self {
+            timestamp,
+            level,
+            subsystem,
+            message: message.to_owned(),
+        }
+    }
+}
+
+// `YYYY-MM-DD HH:MM:SS<offset>: ` as katago's logger prefixes lines
+fn split_timestamp(line: &str) -> Option<(&str, &str)> {
+    let (timestamp, message) = line.split_once(": ")?;
+    let bytes = timestamp.as_bytes();
+    let looks_like_timestamp = bytes.len() >= 19
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && bytes[10] == b' '
+        && bytes[13] == b':'
+        && bytes[16] == b':'
+        && bytes[..4].iter().all(u8::is_ascii_digit);
+    looks_like_timestamp.then_some((timestamp, message))
+}
+
+// Stderr must be drained for the whole engine lifetime, a full pipe would block katago. The first
+// recognized fatal error is kept, later ones tend to be consequences of it.
+#[cfg(feature = "process")]
+pub(crate) async fn read_stderr(
+    stderr: impl AsyncRead + Unpin,
+    fatal_error: Arc<Mutex<Option<FatalError>>>,
+    logs: mpsc::Sender<LogEvent>,
+) {
+    let mut lines = BufReader::new(stderr).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(error) = classify(&line) {
+            fatal_error.lock().unwrap().get_or_insert(error);
+        }
+        if !logs.is_closed() {
+            // Nobody may ever read the logs, so they're dropped rather than buffered without bound
+            let _ = logs.try_send(LogEvent::parse(&line));
+        }
+    }
+}
+
+// Log lines of an engine, see `Engine::take_log_events`
+#[cfg(feature = "process")]
+pub struct LogEvents {
+    pub(crate) rx: mpsc::Receiver<LogEvent>,
+}
+
+#[cfg(feature = "process")]
+impl Stream for LogEvents {
+    type Item = LogEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
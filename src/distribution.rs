@@ -0,0 +1,141 @@
+// Final score estimates as a normal distribution around the score lead, with katago's score
+// standard deviation. Katago's stdev is of the self-play score, which is wider than the lead's
+// real uncertainty, so bands err on the side of being too wide.
+
+use crate::{MoveInfo, Player, ReportAnalysisWinratesAs, RootInfo};
+
+// From one player's point of view, positive scores are wins for them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreDistribution {
+    pub mean: f32,
+    pub stdev: f32,
+}
+
+impl ScoreDistribution {
+    pub fn new(mean: f32, stdev: f32) -> Self {
+        Self {
+            mean,
+            stdev: stdev.abs(),
+        }
+    }
+
+    // Of winning by more than `points`
+    pub fn probability_above(&self, points: f32) -> f32 {
+        1.0 - self.cdf(points)
+    }
+
+    pub fn probability_between(&self, low: f32, high: f32) -> f32 {
+        (self.cdf(high) - self.cdf(low)).max(0.0)
+    }
+
+    pub fn win_probability(&self) -> f32 {
+        self.probability_above(0.0)
+    }
+
+    // The score `probability` of outcomes are below, e.g. 0.5 for the median
+    pub fn percentile(&self, probability: f32) -> f32 {
+        if self.stdev == 0.0 {
+            return self.mean;
+        }
+        let probability = (probability as f64).clamp(1e-9, 1.0 - 1e-9);
+        self.mean + self.stdev * inverse_normal_cdf(probability) as f32
+    }
+
+    // The central range holding `probability` of outcomes, e.g. 2 to 8 points for 0.9
+    pub fn band(&self, probability: f32) -> (f32, f32) {
+        let tail = (1.0 - probability.clamp(0.0, 1.0)) / 2.0;
+        (self.percentile(tail), self.percentile(1.0 - tail))
+    }
+
+    fn cdf(&self, points: f32) -> f32 {
+        if self.stdev == 0.0 {
+            return if points < self.mean { 0.0 } else { 1.0 };
+        }
+        let z = (points - self.mean) as f64 / (self.stdev as f64 * std::f64::consts::SQRT_2);
+        (0.5 * (1.0 + erf(z))) as f32
+    }
+}
+
+impl RootInfo {
+    // None unless katago reported the root's score stdev
+    pub fn score_distribution_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+    ) -> Option<ScoreDistribution> {
+        let stdev = self.score_stdev?;
+        let lead = self.score_lead_for(player, reported_as)?;
+        Some(ScoreDistribution::new(lead, stdev))
+    }
+}
+
+impl MoveInfo {
+    pub fn score_distribution_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+        current_player: Option<Player>,
+    ) -> Option<ScoreDistribution> {
+        let lead = self.score_lead_for(player, reported_as, current_player)?;
+        Some(ScoreDistribution::new(lead, self.score_stdev))
+    }
+}
+
+// Abramowitz and Stegun 7.1.26, accurate to about 1.5e-7
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - polynomial * (-x * x).exp();
+    erf.copysign(x)
+}
+
+// Acklam's rational approximation, accurate to about 1.2e-9
+fn inverse_normal_cdf(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    const LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
@@ -0,0 +1,175 @@
+// Counting what's left to play in the endgame. Boundary plays are the legal points whose ownership
+// is still open, the likeliest of them by policy are probed twice: once taken by the player to
+// move, see `Client::probe`, and once by their opponent. The difference of the two score leads is the play's value,
+// counted the way endgame books do as the swing between the two sides getting it. Probes go
+// through the batch lane, so they queue behind interactive queries and use the client's result
+// cache like any other query.
+
+use futures_util::future::join_all;
+
+use crate::{
+    Client, ClientError, GamePhase, KataQuery, KataResponse, Move, Player, QueryLane,
+    ReportAnalysisWinratesAs,
+};
+
+// Ownership weaker than this is still open
+const OPEN: f32 = 0.6;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundaryPlay {
+    pub point: Move,
+    // Swing in points for the player to move, positive when taking it is better for them
+    pub value: f32,
+    // Score leads for the player to move after they or their opponent took it
+    pub taken: f32,
+    pub given: f32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndgameReport {
+    pub player: Player,
+    // Worth checking, the plays are only meaningful once the game is in its endgame
+    pub phase: GamePhase,
+    // Biggest first
+    pub plays: Vec<BoundaryPlay>,
+}
+
+pub struct EndgameCounter {
+    client: Client,
+    candidates: usize,
+    visits: u64,
+}
+
+impl EndgameCounter {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            candidates: 12,
+            visits: 200,
+        }
+    }
+
+    // How many boundary plays are probed
+    pub fn candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    // Of the position itself and of every probe, probes only need a rough score lead
+    pub fn visits(mut self, visits: u64) -> Self {
+        self.visits = visits;
+        self
+    }
+
+    // Counts the position at the end of `query`'s moves. Plays whose probes katago rejected, e.g.
+    // retaking a ko, are left out. Results without a known perspective are taken as reported for
+    // black.
+    pub async fn count(&self, query: &KataQuery) -> Result<EndgameReport, ClientError> {
+        let result = self.probe(query, None).await?;
+        let KataResponse::Result {
+            root_info,
+            ownership,
+            policy,
+            ..
+        } = &result
+        else {
+            return Err(ClientError::Unsupported(
+                "the position has no results to count".to_owned(),
+            ));
+        };
+        let (board_x_size, board_y_size) = (query.board_x_size, query.board_y_size);
+        let player = root_info.current_player.unwrap_or(query.player_to_move());
+        let move_number = query.initial_stones.iter().flatten().count() + query.moves.len();
+        let phase = GamePhase::classify(
+            move_number as u32,
+            board_x_size,
+            board_y_size,
+            ownership.as_deref(),
+        );
+
+        // Illegal points have a negative policy
+        let points = board_x_size as usize * board_y_size as usize;
+        let mut candidates = match (ownership, policy) {
+            (Some(ownership), Some(policy))
+                if ownership.len() == points && policy.len() > points =>
+            {
+                (0..board_y_size)
+                    .flat_map(|y| (0..board_x_size).map(move |x| Move::point(x, y)))
+                    .filter_map(|point| {
+                        let index = point.index(board_x_size, board_y_size)?;
+                        let policy = policy[index];
+                        (ownership[index].abs() < OPEN && policy >= 0.0).then_some((point, policy))
+                    })
+                    .collect::<Vec<_>>()
+            }
+            _ => Vec::new(),
+        };
+        candidates.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        candidates.truncate(self.candidates);
+
+        let probes = candidates.iter().map(|(point, _)| async move {
+            let (taken, given) = futures_util::join!(
+                self.client
+                    .probe_in(Some(QueryLane::Batch), query, *point, self.visits),
+                self.probe(query, Some((player.opponent(), *point)))
+            );
+            let taken = taken.ok()?.score_lead;
+            let given = lead_for(&given.ok()?, player)?;
+            Some(BoundaryPlay {
+                point: *point,
+                value: taken - given,
+                taken,
+                given,
+            })
+        });
+        let mut plays = join_all(probes)
+            .await
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        plays.sort_by(|a, b| b.value.total_cmp(&a.value));
+        Ok(EndgameReport {
+            player,
+            phase,
+            plays,
+        })
+    }
+
+    async fn probe(
+        &self,
+        query: &KataQuery,
+        play: Option<(Player, Move)>,
+    ) -> Result<KataResponse, ClientError> {
+        let mut probe = query.clone();
+        probe.id = self.client.next_id();
+        probe.moves.extend(play);
+        probe.analyze_turns = None;
+        probe.max_visits = Some(self.visits);
+        probe.report_during_search_every = None;
+        probe.priorities = None;
+        probe.priority = None;
+        if play.is_none() {
+            probe.include_ownership = Some(true);
+            probe.include_policy = Some(true);
+        }
+        self.client
+            .submit_to(QueryLane::Batch, probe)?
+            .result()
+            .await
+    }
+}
+
+fn lead_for(result: &KataResponse, player: Player) -> Option<f32> {
+    let KataResponse::Result {
+        root_info,
+        perspective,
+        ..
+    } = result
+    else {
+        return None;
+    };
+    root_info.score_lead_for(
+        player,
+        perspective.unwrap_or(ReportAnalysisWinratesAs::Black),
+    )
+}
@@ -0,0 +1,678 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::{pin, Pin};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::LinesStream;
+use tokio_util::codec::FramedWrite;
+
+use crate::diagnostics::{read_stderr, FatalError, LogEvents};
+use crate::protocol_log::{ProtocolLog, INCOMING, OUTGOING};
+#[cfg(feature = "priority")]
+use crate::ProcessPriority;
+use crate::{
+    AnalysisConfig, KataAction, KataActionEncoder, KataQuery, KataResponse, Move, Player,
+    QueryIdGenerator, ReportAnalysisWinratesAs, Rules,
+};
+
+#[cfg(not(windows))]
+const DEFAULT_PROGRAM: &str = "katago";
+#[cfg(windows)]
+const DEFAULT_PROGRAM: &str = "katago.exe";
+
+// Keeps a console window from popping up when spawned from a GUI application
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+const WARM_UP_BOARD_SIZE: u8 = 19;
+const WARM_UP_VISITS: u64 = 2;
+
+const LOG_BUFFER: usize = 1024;
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(io::Error),
+    Parse {
+        line: String,
+        source: serde_json::Error,
+    },
+    Rejected {
+        error: String,
+        field: Option<String>,
+    },
+    Fatal(FatalError),
+    Closed,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Io(err) => write!(f, "engine i/o failed: {err}"),
+            EngineError::Parse { line, source } => {
+                write!(f, "failed to parse engine response {line:?}: {source}")
+            }
+            EngineError::Rejected {
+                error,
+                field: Some(field),
+            } => write!(f, "engine rejected field {field:?}: {error}"),
+            EngineError::Rejected { error, field: None } => {
+                write!(f, "engine rejected action: {error}")
+            }
+            EngineError::Fatal(err) => err.fmt(f),
+            EngineError::Closed => f.write_str("engine output ended"),
+        }
+    }
+}
+
+impl Error for EngineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EngineError::Io(err) => Some(err),
+            EngineError::Parse { source, .. } => Some(source),
+            EngineError::Fatal(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(err: io::Error) -> Self {
+        EngineError::Io(err)
+    }
+}
+
+// Builds the `katago analysis` command line. The typed setters are shorthands for commonly used
+// `-override-config` keys, anything else can be passed with `override_config`.
+#[derive(Clone, Debug)]
+pub struct EngineBuilder {
+    program: PathBuf,
+    config: Option<PathBuf>,
+    model: Option<PathBuf>,
+    human_model: Option<PathBuf>,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    overrides: Vec<(String, String)>,
+    args: Vec<OsString>,
+    protocol_log: Option<PathBuf>,
+    #[cfg(feature = "priority")]
+    priority: Option<ProcessPriority>,
+    #[cfg(feature = "priority")]
+    cpu_affinity: Option<Vec<usize>>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self {
+            program: DEFAULT_PROGRAM.into(),
+            config: None,
+            model: None,
+            human_model: None,
+            report_analysis_winrates_as: None,
+            overrides: Vec::new(),
+            args: Vec::new(),
+            protocol_log: None,
+            #[cfg(feature = "priority")]
+            priority: None,
+            #[cfg(feature = "priority")]
+            cpu_affinity: None,
+        }
+    }
+}
+
+impl EngineBuilder {
+    pub fn program(mut self, program: impl Into<PathBuf>) -> Self {
+        self.program = program.into();
+        self
+    }
+
+    pub fn config(mut self, config: impl Into<PathBuf>) -> Self {
+        self.config = Some(config.into());
+        self
+    }
+
+    // Writes `config` to a new temp file used as the engine's config, see
+    // `AnalysisConfig::write_temp`
+    pub fn analysis_config(mut self, config: &AnalysisConfig) -> io::Result<Self> {
+        if let Some(reported_as) = config.report_analysis_winrates_as() {
+            self.report_analysis_winrates_as = Some(reported_as);
+        }
+        Ok(self.config(config.write_temp()?))
+    }
+
+    pub fn model(mut self, model: impl Into<PathBuf>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    // Human SL network, required by queries setting a `humanSLProfile`
+    pub fn human_model(mut self, human_model: impl Into<PathBuf>) -> Self {
+        self.human_model = Some(human_model.into());
+        self
+    }
+
+    // Tees the raw protocol traffic to `path`, see `ProtocolLog`. Use `Engine::log_protocol` to
+    // change how the file is rotated.
+    pub fn protocol_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.protocol_log = Some(path.into());
+        self
+    }
+
+    // Instead of running katago with `nice`, see `ProcessPriority`
+    #[cfg(feature = "priority")]
+    pub fn priority(mut self, priority: ProcessPriority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    // The CPUs katago may run on, counted from 0, instead of running it with `taskset`. Not
+    // supported on macOS, where spawning then fails.
+    #[cfg(feature = "priority")]
+    pub fn cpu_affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpu_affinity = Some(cpus.into_iter().collect());
+        self
+    }
+
+    // Extra arguments appended after everything else
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    // Setting a key again replaces the previous value. katago splits overrides on commas, so
+    // values can't contain them.
+    pub fn override_config(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        let key = key.into();
+        let value = value.to_string();
+        match self.overrides.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.overrides.push((key, value)),
+        }
+        self
+    }
+
+    pub fn report_analysis_winrates_as(mut self, reported_as: ReportAnalysisWinratesAs) -> Self {
+        self.report_analysis_winrates_as = Some(reported_as);
+        let value = match reported_as {
+            ReportAnalysisWinratesAs::Black => "BLACK",
+            ReportAnalysisWinratesAs::White => "WHITE",
+            ReportAnalysisWinratesAs::SideToMove => "SIDETOMOVE",
+        };
+        self.override_config("reportAnalysisWinratesAs", value)
+    }
+
+    pub fn num_search_threads(self, threads: u32) -> Self {
+        self.override_config("numSearchThreads", threads)
+    }
+
+    pub fn num_analysis_threads(self, threads: u32) -> Self {
+        self.override_config("numAnalysisThreads", threads)
+    }
+
+    pub fn nn_cache_size_power_of_two(self, power: u8) -> Self {
+        self.override_config("nnCacheSizePowerOfTwo", power)
+    }
+
+    pub fn nn_max_batch_size(self, batch_size: u32) -> Self {
+        self.override_config("nnMaxBatchSize", batch_size)
+    }
+
+    // One NN server thread per listed device, listing a device twice runs two threads on it
+    pub fn cuda_devices(self, devices: impl IntoIterator<Item = u32>) -> Self {
+        self.devices("cudaDeviceToUseThread", devices)
+    }
+
+    pub fn opencl_devices(self, devices: impl IntoIterator<Item = u32>) -> Self {
+        self.devices("openclDeviceToUseThread", devices)
+    }
+
+    // Restart strategy for `FatalErrorKind::OutOfMemory`: halves the configured nnMaxBatchSize,
+    // None when it wasn't set or can't be reduced any further
+    pub fn reduced_batch_size(&self) -> Option<Self> {
+        let batch_size = self
+            .overrides
+            .iter()
+            .find(|(key, _)| key == "nnMaxBatchSize")?
+            .1
+            .parse::<u32>()
+            .ok()?;
+        (batch_size > 1).then(|| self.clone().nn_max_batch_size(batch_size / 2))
+    }
+
+    fn devices(mut self, key_prefix: &str, devices: impl IntoIterator<Item = u32>) -> Self {
+        let devices = devices.into_iter().collect::<Vec<_>>();
+        if devices.is_empty() {
+            return self;
+        }
+        for (thread, device) in devices.iter().enumerate() {
+            self = self.override_config(format!("{key_prefix}{thread}"), device);
+        }
+        self.override_config("numNNServerThreadsPerModel", devices.len())
+    }
+
+    pub fn command(&self) -> Command {
+        #[cfg(not(windows))]
+        let mut cmd = Command::new(&self.program);
+        // Only a bare program name gets `.exe` appended by the PATH lookup, paths like
+        // `engines/katago` need the extension spelled out
+        #[cfg(windows)]
+        let mut cmd = {
+            let mut program = self.program.clone();
+            if program.extension().is_none() && program.components().count() > 1 {
+                program.set_extension("exe");
+            }
+            let mut cmd = Command::new(program);
+            #[cfg(feature = "priority")]
+            let priority_class = self.priority.map_or(0, ProcessPriority::priority_class);
+            #[cfg(not(feature = "priority"))]
+            let priority_class = 0;
+            cmd.creation_flags(CREATE_NO_WINDOW | priority_class);
+            cmd
+        };
+        #[cfg(all(unix, feature = "priority"))]
+        crate::process_priority::apply(&mut cmd, self.priority, self.cpu_affinity.as_deref());
+        cmd.arg("analysis");
+        if let Some(config) = &self.config {
+            cmd.arg("-config").arg(config);
+        }
+        if let Some(model) = &self.model {
+            cmd.arg("-model").arg(model);
+        }
+        if let Some(human_model) = &self.human_model {
+            cmd.arg("-human-model").arg(human_model);
+        }
+        if !self.overrides.is_empty() {
+            let overrides = self
+                .overrides
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(",");
+            cmd.arg("-override-config").arg(overrides);
+        }
+        cmd.args(&self.args);
+        cmd
+    }
+
+    pub fn spawn(&self) -> io::Result<Engine> {
+        let protocol_log = self
+            .protocol_log
+            .as_ref()
+            .map(ProtocolLog::create)
+            .transpose()?;
+        let mut engine = Engine::spawn(&mut self.command())?;
+        #[cfg(all(windows, feature = "priority"))]
+        if let Some(cpus) = &self.cpu_affinity {
+            crate::process_priority::set_affinity_after_spawn(&engine.child, cpus)?;
+        }
+        engine.protocol_log = protocol_log;
+        engine.handle.human_model = Some(self.human_model.is_some());
+        engine.handle.report_analysis_winrates_as = self.report_analysis_winrates_as;
+        Ok(engine)
+    }
+}
+
+// Cloneable view of the engine which stays usable after the engine itself was split or moved
+// into a client
+#[derive(Clone, Debug)]
+pub struct EngineHandle {
+    pid: Option<u32>,
+    human_model: Option<bool>,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    fatal_error: Arc<Mutex<Option<FatalError>>>,
+    exit_status: Arc<Mutex<Option<ExitStatus>>>,
+}
+
+impl EngineHandle {
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    // Only known for engines spawned by `EngineBuilder`
+    pub fn has_human_model(&self) -> Option<bool> {
+        self.human_model
+    }
+
+    // Only known when it was set through `EngineBuilder`, results are tagged with it
+    pub fn report_analysis_winrates_as(&self) -> Option<ReportAnalysisWinratesAs> {
+        self.report_analysis_winrates_as
+    }
+
+    // Fatal errors recognized on the engine's stderr so far
+    pub fn fatal_error(&self) -> Option<FatalError> {
+        self.fatal_error.lock().unwrap().clone()
+    }
+
+    // Known once the engine's output ended, the process is reaped before the stream ends
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        *self.exit_status.lock().unwrap()
+    }
+
+    // Stops the process with SIGSTOP, freeing the CPU and GPU it used without losing the NN cache
+    // and other warm state. Queries sent meanwhile are answered after `resume`.
+    #[cfg(all(unix, feature = "pause"))]
+    pub fn pause(&self) -> io::Result<()> {
+        self.signal(libc::SIGSTOP)
+    }
+
+    #[cfg(all(unix, feature = "pause"))]
+    pub fn resume(&self) -> io::Result<()> {
+        self.signal(libc::SIGCONT)
+    }
+
+    #[cfg(all(unix, feature = "pause"))]
+    fn signal(&self, signal: libc::c_int) -> io::Result<()> {
+        // The pid may already belong to another process once this one was reaped
+        let pid = self
+            .pid
+            .filter(|_| self.exit_status().is_none())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "engine already exited"))?;
+        // SAFETY: kill only takes plain integers and has no memory safety requirements
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+// A running katago analysis engine. Actions are sent through the `Sink` impl and responses are
+// read from the `Stream` impl, just like the pair returned by `start`. The stream ends when the
+// engine's output ends or can't be parsed, `Engine::error` tells which.
+pub struct Engine {
+    child: Child,
+    // Taken on close, only dropping stdin makes katago see the end of its input
+    sink: Option<FramedWrite<ChildStdin, KataActionEncoder>>,
+    lines: LinesStream<BufReader<ChildStdout>>,
+    // Responses received while the engine itself waited for something else, e.g. during warm up
+    buffered: VecDeque<KataResponse>,
+    finished: bool,
+    error: Option<EngineError>,
+    ids: QueryIdGenerator,
+    handle: EngineHandle,
+    stderr: Option<JoinHandle<()>>,
+    logs: Option<LogEvents>,
+    protocol_log: Option<ProtocolLog>,
+}
+
+impl Engine {
+    pub fn builder() -> EngineBuilder {
+        Default::default()
+    }
+
+    pub fn spawn(cmd: &mut Command) -> io::Result<Self> {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let handle = EngineHandle {
+            pid: child.id(),
+            human_model: None,
+            report_analysis_winrates_as: None,
+            fatal_error: Default::default(),
+            exit_status: Default::default(),
+        };
+        let (logs, rx) = mpsc::channel(LOG_BUFFER);
+        let stderr = tokio::spawn(read_stderr(
+            child.stderr.take().unwrap(),
+            handle.fatal_error.clone(),
+            logs,
+        ));
+
+        Ok(Self {
+            child,
+            sink: Some(FramedWrite::new(stdin, KataActionEncoder)),
+            lines: LinesStream::new(stdout.lines()),
+            buffered: VecDeque::new(),
+            finished: false,
+            error: None,
+            ids: QueryIdGenerator::new("kpae-engine"),
+            handle,
+            stderr: Some(stderr),
+            logs: Some(LogEvents { rx }),
+            protocol_log: None,
+        })
+    }
+
+    pub fn child(&self) -> &Child {
+        &self.child
+    }
+
+    pub fn child_mut(&mut self) -> &mut Child {
+        &mut self.child
+    }
+
+    // The engine's stderr lines as structured events, from the start. Only the first call gets
+    // them, lines are dropped while more than `LOG_BUFFER` are unread.
+    pub fn take_log_events(&mut self) -> Option<LogEvents> {
+        self.logs.take()
+    }
+
+    // Records the raw lines exchanged from now on
+    pub fn log_protocol(&mut self, protocol_log: ProtocolLog) {
+        self.protocol_log = Some(protocol_log);
+    }
+
+    pub fn handle(&self) -> EngineHandle {
+        self.handle.clone()
+    }
+
+    // Closes the engine's input, which makes katago finish the queries it has and quit, and kills
+    // it if it's still running after `grace`. Killing doesn't rely on signals, so this works the
+    // same on Windows.
+    pub async fn shutdown(&mut self, grace: Duration) -> io::Result<ExitStatus> {
+        SinkExt::close(self).await?;
+        let status = match tokio::time::timeout(grace, self.child.wait()).await {
+            Ok(status) => status?,
+            Err(_) => {
+                self.child.kill().await?;
+                self.child.wait().await?
+            }
+        };
+        *self.handle.exit_status.lock().unwrap() = Some(status);
+        Ok(status)
+    }
+
+    #[cfg(all(unix, feature = "pause"))]
+    pub fn pause(&self) -> io::Result<()> {
+        self.handle.pause()
+    }
+
+    #[cfg(all(unix, feature = "pause"))]
+    pub fn resume(&self) -> io::Result<()> {
+        self.handle.resume()
+    }
+
+    // Like `SinkExt::send`, but serializes the action from a reference, so large queries can be
+    // resent or sent to several engines without cloning them
+    pub async fn send_ref(&mut self, action: &KataAction) -> io::Result<()> {
+        futures_util::future::poll_fn(|cx| {
+            Sink::<KataAction>::poll_ready(Pin::new(&mut *self), cx)
+        })
+        .await?;
+        self.record_outgoing(action);
+        Sink::<&KataAction>::start_send(Pin::new(self.stdin()?), action)?;
+        futures_util::future::poll_fn(|cx| Sink::<KataAction>::poll_flush(Pin::new(&mut *self), cx))
+            .await
+    }
+
+    pub fn error(&self) -> Option<&EngineError> {
+        self.error.as_ref()
+    }
+
+    pub(crate) fn take_error(&mut self) -> Option<EngineError> {
+        self.error.take()
+    }
+
+    // Runs a tiny throwaway query and waits for its result, so that backend initialization (e.g.
+    // GPU graph compilation) happens now rather than on the first real query
+    pub async fn warm_up(&mut self) -> Result<(), EngineError> {
+        let id = self.ids.next_id();
+        let query = KataQuery::builder()
+            .id(id.clone())
+            .moves(Vec::<(Player, Move)>::new())
+            .rules(Rules::Chinese)
+            .board_x_size(WARM_UP_BOARD_SIZE)
+            .board_y_size(WARM_UP_BOARD_SIZE)
+            .max_visits(WARM_UP_VISITS)
+            .build()
+            .unwrap();
+        if let Err(err) = self.send(KataAction::Query { inner: query }).await {
+            // The engine most likely quit already, its remaining output may explain why
+            while let Some(response) = self.next_line().await {
+                self.buffered.push_back(response);
+            }
+            return Err(match &self.error {
+                Some(EngineError::Fatal(fatal)) => EngineError::Fatal(fatal.clone()),
+                _ => EngineError::Io(err),
+            });
+        }
+
+        let mut unrelated = VecDeque::new();
+        let result = loop {
+            let Some(response) = self.next_line().await else {
+                break Err(self.error.take().unwrap_or(EngineError::Closed));
+            };
+            if response.id() != Some(&id) {
+                unrelated.push_back(response);
+                continue;
+            }
+            match response {
+                KataResponse::Error { error, field, .. } => {
+                    break Err(EngineError::Rejected { error, field })
+                }
+                response if !response.is_during_search() => break Ok(()),
+                _ => {}
+            }
+        };
+        self.buffered.append(&mut unrelated);
+        result
+    }
+
+    async fn next_line(&mut self) -> Option<KataResponse> {
+        futures_util::future::poll_fn(|cx| self.poll_line(cx)).await
+    }
+
+    fn poll_line(&mut self, cx: &mut Context<'_>) -> Poll<Option<KataResponse>> {
+        if self.finished {
+            return Poll::Ready(None);
+        }
+        let line = match self.lines.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(line))) => line,
+            Poll::Ready(Some(Err(err))) => return self.finish(EngineError::Io(err)),
+            // Stderr is read to the end first, it usually explains why the output ended. The
+            // process is reaped afterwards so it doesn't outlive the stream.
+            Poll::Ready(None) => {
+                if let Some(stderr) = &mut self.stderr {
+                    if Pin::new(stderr).poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    self.stderr = None;
+                }
+                if self.handle.exit_status().is_none() {
+                    match pin!(self.child.wait()).poll(cx) {
+                        Poll::Ready(Ok(status)) => {
+                            *self.handle.exit_status.lock().unwrap() = Some(status)
+                        }
+                        Poll::Ready(Err(_)) => {}
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                return match self.handle.fatal_error() {
+                    Some(err) => self.finish(EngineError::Fatal(err)),
+                    None => {
+                        self.finished = true;
+                        Poll::Ready(None)
+                    }
+                };
+            }
+            Poll::Pending => return Poll::Pending,
+        };
+        if let Some(protocol_log) = &mut self.protocol_log {
+            protocol_log.record(INCOMING, &line);
+        }
+        match serde_json::from_str::<KataResponse>(&line) {
+            Ok(mut response) => {
+                if let Some(reported_as) = self.handle.report_analysis_winrates_as {
+                    response.tag_perspective(reported_as);
+                }
+                Poll::Ready(Some(response))
+            }
+            Err(source) => self.finish(EngineError::Parse { line, source }),
+        }
+    }
+
+    fn record_outgoing(&mut self, action: &KataAction) {
+        if let Some(protocol_log) = &mut self.protocol_log {
+            protocol_log.record(OUTGOING, &action.to_json());
+        }
+    }
+
+    fn stdin(&mut self) -> io::Result<&mut FramedWrite<ChildStdin, KataActionEncoder>> {
+        self.sink
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "engine input was closed"))
+    }
+
+    fn finish(&mut self, error: EngineError) -> Poll<Option<KataResponse>> {
+        self.finished = true;
+        self.error = Some(error);
+        Poll::Ready(None)
+    }
+}
+
+impl Sink<KataAction> for Engine {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Sink::<KataAction>::poll_ready(Pin::new(self.stdin()?), cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: KataAction) -> Result<(), Self::Error> {
+        self.record_outgoing(&item);
+        self.stdin()?.start_send_unpin(item)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        match &mut self.sink {
+            Some(sink) => Sink::<KataAction>::poll_flush(Pin::new(sink), cx),
+            None => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let Some(sink) = &mut self.sink else {
+            return Poll::Ready(Ok(()));
+        };
+        let result = ready!(Sink::<KataAction>::poll_close(Pin::new(sink), cx));
+        self.sink = None;
+        Poll::Ready(result)
+    }
+}
+
+impl Stream for Engine {
+    type Item = KataResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(response) = self.buffered.pop_front() {
+            return Poll::Ready(Some(response));
+        }
+        self.poll_line(cx)
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::process::ExitStatus;
+use std::task::{ready, Context, Poll, Waker};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::{SinkExt, StreamExt};
+
+use crate::{Engine, EngineError, KataAction, KataResponse, QueryId};
+
+#[derive(Debug)]
+pub enum EngineEvent {
+    // A query was flushed to the engine, queries only buffered or lost to a failed write don't start
+    QueryStarted(QueryId),
+    // A result or resultless response with `is_during_search`, more follow for the same turn
+    Interim(KataResponse),
+    // The last result or resultless response for a turn
+    Final(KataResponse),
+    Terminated {
+        id: QueryId,
+        terminate_id: QueryId,
+        turn_number: Option<u32>,
+    },
+    Rejected {
+        id: Option<QueryId>,
+        error: String,
+        field: Option<String>,
+    },
+    EngineWarning {
+        id: Option<QueryId>,
+        warning: String,
+        field: Option<String>,
+    },
+    // Version and cache cleared responses
+    Response(KataResponse),
+    // Always the last event. `error` tells why the output ended if it wasn't a clean exit, the
+    // status is None when the process couldn't be reaped.
+    EngineExited {
+        status: Option<ExitStatus>,
+        error: Option<EngineError>,
+    },
+}
+
+impl From<KataResponse> for EngineEvent {
+    fn from(response: KataResponse) -> Self {
+        match response {
+            KataResponse::TerminateAck {
+                id,
+                turn_number,
+                terminate_id,
+                ..
+            } => EngineEvent::Terminated {
+                id,
+                terminate_id,
+                turn_number,
+            },
+            KataResponse::Error { id, error, field } => EngineEvent::Rejected { id, error, field },
+            KataResponse::Warning { id, warning, field } => {
+                EngineEvent::EngineWarning { id, warning, field }
+            }
+            response @ (KataResponse::Result { .. } | KataResponse::Resultless { .. }) => {
+                if response.is_during_search() {
+                    EngineEvent::Interim(response)
+                } else {
+                    EngineEvent::Final(response)
+                }
+            }
+            response => EngineEvent::Response(response),
+        }
+    }
+}
+
+// An engine whose responses, writes and exit are read as one stream of events. Actions are still
+// sent through the `Sink` impl.
+pub struct EngineEvents {
+    engine: Engine,
+    // Sent but not flushed yet
+    unflushed: Vec<QueryId>,
+    started: VecDeque<QueryId>,
+    waker: Option<Waker>,
+    exited: bool,
+}
+
+impl Engine {
+    pub fn events(self) -> EngineEvents {
+        EngineEvents {
+            engine: self,
+            unflushed: Vec::new(),
+            started: VecDeque::new(),
+            waker: None,
+            exited: false,
+        }
+    }
+}
+
+impl EngineEvents {
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut Engine {
+        &mut self.engine
+    }
+
+    pub fn into_inner(self) -> Engine {
+        self.engine
+    }
+
+    fn flushed(&mut self, result: io::Result<()>) -> io::Result<()> {
+        let unflushed = std::mem::take(&mut self.unflushed);
+        if result.is_ok() && !unflushed.is_empty() {
+            self.started.extend(unflushed);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+        result
+    }
+}
+
+impl Sink<KataAction> for EngineEvents {
+    type Error = io::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.engine.poll_ready_unpin(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: KataAction) -> Result<(), Self::Error> {
+        let started = match &item {
+            KataAction::Query { inner } => Some(inner.id().clone()),
+            _ => None,
+        };
+        self.engine.start_send_unpin(item)?;
+        self.unflushed.extend(started);
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let flushed = ready!(self.engine.poll_flush_unpin(cx));
+        Poll::Ready(self.flushed(flushed))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let closed = ready!(self.engine.poll_close_unpin(cx));
+        Poll::Ready(self.flushed(closed))
+    }
+}
+
+impl Stream for EngineEvents {
+    type Item = EngineEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(id) = self.started.pop_front() {
+            return Poll::Ready(Some(EngineEvent::QueryStarted(id)));
+        }
+        if self.exited {
+            return Poll::Ready(None);
+        }
+        match self.engine.poll_next_unpin(cx) {
+            Poll::Ready(Some(response)) => Poll::Ready(Some(response.into())),
+            Poll::Ready(None) => {
+                self.exited = true;
+                Poll::Ready(Some(EngineEvent::EngineExited {
+                    status: self.engine.handle().exit_status(),
+                    error: self.engine.take_error(),
+                }))
+            }
+            Poll::Pending => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
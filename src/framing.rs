@@ -0,0 +1,148 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::future::{self, Ready};
+use futures_util::{SinkExt, StreamExt};
+use tokio_util::codec::Decoder;
+
+use crate::{KataAction, KataResponse, KataResponseDecoder};
+
+// Responses read from chunks of engine output, e.g. what async-std's or smol's process pipes read,
+// for transports that aren't tokio's `AsyncRead`. Lines are split and parsed the way
+// `KataResponseDecoder` does, a line that doesn't parse is an error and reading carries on.
+pub struct ResponseStream<St> {
+    chunks: St,
+    buffer: BytesMut,
+    decoder: KataResponseDecoder,
+    ended: bool,
+}
+
+pub fn response_stream<St, B, E>(chunks: St) -> ResponseStream<St>
+where
+    St: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: Into<io::Error>,
+{
+    ResponseStream {
+        chunks,
+        buffer: BytesMut::new(),
+        decoder: KataResponseDecoder::default(),
+        ended: false,
+    }
+}
+
+impl<St> ResponseStream<St> {
+    pub fn into_inner(self) -> St {
+        self.chunks
+    }
+}
+
+impl<St, B, E> Stream for ResponseStream<St>
+where
+    St: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: Into<io::Error>,
+{
+    type Item = io::Result<KataResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if this.ended {
+                return Poll::Ready(None);
+            }
+            if let Some(response) = this.decoder.decode(&mut this.buffer).transpose() {
+                return Poll::Ready(Some(response));
+            }
+            match ready!(this.chunks.poll_next_unpin(cx)) {
+                Some(Ok(chunk)) => this.buffer.extend_from_slice(chunk.as_ref()),
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                // The last line needn't end with a newline
+                None => {
+                    this.ended = true;
+                    return Poll::Ready(this.decoder.decode_eof(&mut this.buffer).transpose());
+                }
+            }
+        }
+    }
+}
+
+// Writes actions to a sink of bytes one line each, like `KataActionEncoder`, for the same
+// transports as `response_stream`
+pub fn action_sink<Si>(bytes: Si) -> impl Sink<KataAction, Error = Si::Error>
+where
+    Si: Sink<Bytes>,
+{
+    bytes.with(encode::<Si::Error>)
+}
+
+fn encode<E>(action: KataAction) -> Ready<Result<Bytes, E>> {
+    let mut line = action.to_json().into_bytes();
+    line.push(b'\n');
+    future::ready(Ok(Bytes::from(line)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use futures_util::stream;
+
+    use super::*;
+    use crate::QueryId;
+
+    fn responses(chunks: Vec<&'static str>) -> Vec<io::Result<KataResponse>> {
+        let chunks = stream::iter(chunks.into_iter().map(Ok::<_, io::Error>));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(response_stream(chunks).collect())
+    }
+
+    #[test]
+    fn lines_split_across_chunks() {
+        let responses = responses(vec![
+            r#"{"id":"a","action":"clear_cache"}"#,
+            "\n{\"id\":\"b\",\"act",
+            "ion\":\"clear_cache\"}\nnot json\n",
+            r#"{"id":"c","action":"clear_cache"}"#,
+        ]);
+        let ids = responses
+            .iter()
+            .map(|response| match response {
+                Ok(response) => response.id().unwrap().as_str(),
+                Err(_) => "error",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["a", "b", "error", "c"]);
+    }
+
+    #[test]
+    fn actions_are_lines() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut written = Vec::new();
+        runtime.block_on(async {
+            let sink = futures_util::sink::unfold(&mut written, |written, bytes: Bytes| {
+                written.extend_from_slice(&bytes);
+                future::ready(Ok::<_, Infallible>(written))
+            });
+            let mut sink = Box::pin(action_sink(sink));
+            sink.send(KataAction::ClearCache {
+                id: QueryId::new("a"),
+                action: crate::ActionClearCache::ActionClearCache,
+            })
+            .await
+            .unwrap();
+        });
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "{\"id\":\"a\",\"action\":\"clear_cache\"}\n"
+        );
+    }
+}
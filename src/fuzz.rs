@@ -0,0 +1,127 @@
+// Entry point for fuzzers such as cargo-fuzz or AFL: `data` is treated as engine output, one
+// response per line, and goes through the parser and everything built on parsed responses. Lines
+// katago could never have written only have to fail, a panic means a bug.
+
+use crate::{kata_analyze_info, lz_analyze_info, Analysis, KataResponse, Move, Player};
+
+pub fn fuzz_response(data: &[u8]) {
+    for line in data.split(|byte| *byte == b'\n') {
+        let Ok(line) = std::str::from_utf8(line) else {
+            continue;
+        };
+        let Ok(response) = KataResponse::from_json(line) else {
+            continue;
+        };
+        exercise(&response);
+        // Whatever was read has to read back the same way. Values too big for an f32 are written as
+        // null and don't.
+        let json = serde_json::to_string(&response).expect("responses serialize");
+        let Ok(again) = KataResponse::from_json(&json) else {
+            continue;
+        };
+        assert_eq!(
+            serde_json::to_string(&again).unwrap(),
+            json,
+            "response changed on a round trip"
+        );
+    }
+}
+
+fn exercise(response: &KataResponse) {
+    let _ = (
+        response.id(),
+        response.turn_number(),
+        response.is_during_search(),
+    );
+    let _ = (
+        response.winrate_for(Player::Black),
+        response.ownership_for(Player::White),
+    );
+    // Ownership of any length, against boards it may not fit
+    for size in [1, 9, 19] {
+        let _ = response.ownership_at(Move::Pass, size, size);
+        let _ = response.ownership_at(Move::from_top_left(0, 0, size).unwrap(), size, size);
+    }
+    if let Some(analysis) = Analysis::from_response(response) {
+        let _ = (
+            analysis.best(),
+            analysis.winrate_for(Player::White),
+            analysis.raw(),
+        );
+    }
+    let _ = (kata_analyze_info(response), lz_analyze_info(response));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Engine output of several katago versions, each behind a `# version` line. Lines right after
+    // `# invalid` are ones the parser has to reject.
+    const CORPUS: &str = include_str!("../tests/corpus/responses.jsonl");
+
+    fn lines() -> Vec<(&'static str, bool, &'static str)> {
+        let mut version = "";
+        let mut valid = true;
+        let mut lines = Vec::new();
+        for line in CORPUS.lines().filter(|line| !line.is_empty()) {
+            if let Some(header) = line.strip_prefix("# ") {
+                valid = header != "invalid";
+                if valid {
+                    version = header;
+                }
+                continue;
+            }
+            lines.push((version, valid, line));
+        }
+        lines
+    }
+
+    #[test]
+    fn corpus_parses() {
+        for (version, valid, line) in lines() {
+            let parsed = KataResponse::from_json(line);
+            assert_eq!(parsed.is_ok(), valid, "{version}: {line}: {parsed:?}");
+        }
+    }
+
+    #[test]
+    fn corpus_layouts() {
+        let results = lines()
+            .into_iter()
+            .filter(|(_, valid, _)| *valid)
+            .map(|(version, _, line)| (version, KataResponse::from_json(line).unwrap()))
+            .filter(|(_, response)| matches!(response, KataResponse::Result { .. }))
+            .collect::<Vec<_>>();
+        assert!(results.len() >= 5);
+        for (version, response) in &results {
+            let analysis = Analysis::from_response(response).unwrap();
+            assert!(analysis.best().is_some(), "{version}");
+        }
+        let old = &results.first().unwrap().1;
+        assert!(!old.is_during_search());
+    }
+
+    #[test]
+    fn corpus_round_trips() {
+        fuzz_response(CORPUS.as_bytes());
+    }
+
+    // Truncated and corrupted copies of every line, like a fuzzer's first mutations
+    #[test]
+    fn mutated_corpus() {
+        for (_, _, line) in lines() {
+            let bytes = line.as_bytes();
+            for end in 0..bytes.len() {
+                fuzz_response(&bytes[..end]);
+            }
+            for (at, replacement) in
+                (0..bytes.len()).zip([b'"', b'0', b'-', b'}', b'[', b'e', 0xff].iter().cycle())
+            {
+                let mut mutated = bytes.to_vec();
+                mutated[at] = *replacement;
+                fuzz_response(&mutated);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Settings for engines sharing one GPU, so together they stay within its memory instead of
+// crashing with CUDA out of memory errors once enough of them run. Each engine needs memory for
+// its CUDA context and the model, and a fixed amount per position in its NN batches. The defaults
+// are rough figures for an 18 block, 384 channel model on 19x19 boards in FP16, measure the
+// engines' usage with `nvidia-smi` for anything else.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::EngineBuilder;
+
+const MIB: u64 = 1024 * 1024;
+
+const DEFAULT_RESERVED: u64 = 1024 * MIB;
+const DEFAULT_PER_ENGINE: u64 = 768 * MIB;
+const DEFAULT_PER_BATCH_ENTRY: u64 = 8 * MIB;
+const DEFAULT_MAX_BATCH_SIZE: u32 = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GpuBudgetError {
+    // Not even batches of one position fit, `fit` is how many engines would
+    TooManyEngines { engines: usize, fit: usize },
+}
+
+impl fmt::Display for GpuBudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GpuBudgetError::TooManyEngines { engines, fit } => write!(
+                f,
+                "{engines} engines don't fit in the GPU's memory, at most {fit} do"
+            ),
+        }
+    }
+}
+
+impl Error for GpuBudgetError {}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuBudget {
+    vram: u64,
+    reserved: u64,
+    per_engine: u64,
+    per_batch_entry: u64,
+    max_batch_size: u32,
+    search_threads: u32,
+    device: Option<u32>,
+}
+
+impl GpuBudget {
+    // `vram` is the GPU's total memory in bytes
+    pub fn new(vram: u64) -> Self {
+        Self {
+            vram,
+            reserved: DEFAULT_RESERVED,
+            per_engine: DEFAULT_PER_ENGINE,
+            per_batch_entry: DEFAULT_PER_BATCH_ENTRY,
+            max_batch_size: DEFAULT_MAX_BATCH_SIZE,
+            search_threads: 1,
+            device: None,
+        }
+    }
+
+    // Left for the display, the driver and other programs, 1 GiB by default
+    pub fn reserved(mut self, bytes: u64) -> Self {
+        self.reserved = bytes;
+        self
+    }
+
+    // Used by every engine whatever its batch size, for the CUDA context, the model's weights and
+    // cuDNN's workspace
+    pub fn per_engine(mut self, bytes: u64) -> Self {
+        self.per_engine = bytes;
+        self
+    }
+
+    pub fn per_batch_entry(mut self, bytes: u64) -> Self {
+        self.per_batch_entry = bytes.max(1);
+        self
+    }
+
+    // Larger batches barely search faster but still take memory
+    pub fn max_batch_size(mut self, max_batch_size: u32) -> Self {
+        self.max_batch_size = max_batch_size.max(1);
+        self
+    }
+
+    // Per analysis thread, the analysis threads are chosen to fill the batches
+    pub fn search_threads(mut self, search_threads: u32) -> Self {
+        self.search_threads = search_threads.max(1);
+        self
+    }
+
+    // The CUDA device the engines run on, katago's default device otherwise
+    pub fn device(mut self, device: u32) -> Self {
+        self.device = Some(device);
+        self
+    }
+
+    // How many engines fit with batches of one position
+    pub fn max_engines(&self) -> usize {
+        let per_engine = self.per_engine + self.per_batch_entry;
+        (self.vram.saturating_sub(self.reserved) / per_engine) as usize
+    }
+
+    // Splits the memory evenly between `engines` engines
+    pub fn limits(&self, engines: usize) -> Result<EngineLimits, GpuBudgetError> {
+        let engines = engines.max(1);
+        let share = self.vram.saturating_sub(self.reserved) / engines as u64;
+        let batch_size = share.saturating_sub(self.per_engine) / self.per_batch_entry;
+        let batch_size = batch_size.min(self.max_batch_size as u64) as u32;
+        if batch_size == 0 {
+            return Err(GpuBudgetError::TooManyEngines {
+                engines,
+                fit: self.max_engines(),
+            });
+        }
+        // Batches are only full when every search thread has a position waiting
+        let search_threads = self.search_threads.min(batch_size);
+        let analysis_threads = batch_size / search_threads;
+        Ok(EngineLimits {
+            nn_max_batch_size: analysis_threads * search_threads,
+            num_analysis_threads: analysis_threads,
+            num_search_threads: search_threads,
+            device: self.device,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EngineLimits {
+    pub nn_max_batch_size: u32,
+    pub num_analysis_threads: u32,
+    pub num_search_threads: u32,
+    pub device: Option<u32>,
+}
+
+impl EngineLimits {
+    // Also pins the engine to a single NN server thread, each of which would allocate its own
+    // batches
+    pub fn apply(&self, engine: EngineBuilder) -> EngineBuilder {
+        let engine = engine
+            .nn_max_batch_size(self.nn_max_batch_size)
+            .num_analysis_threads(self.num_analysis_threads)
+            .override_config("numSearchThreadsPerAnalysisThread", self.num_search_threads);
+        match self.device {
+            Some(device) => engine.cuda_devices([device]),
+            None => engine.override_config("numNNServerThreadsPerModel", 1),
+        }
+    }
+}
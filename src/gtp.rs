@@ -0,0 +1,142 @@
+use std::fmt::Write as _;
+
+use crate::{KataResponse, MoveInfo, ReportAnalysisWinratesAs, RootInfo};
+
+// Results as the `info move ...` lines GTP engines print for `kata-analyze` and `lz-analyze`,
+// which GUIs like Lizzie, Sabaki and KaTrain read. Both report values for the side to move. None
+// for responses other than results.
+pub fn kata_analyze_info(response: &KataResponse) -> Option<String> {
+    let (move_infos, root_info, ownership, reported_as) = parts(response)?;
+    let mut line = String::new();
+    for move_info in move_infos {
+        let values = for_side_to_move(move_info, root_info, reported_as);
+        // katago prints the lead as scoreMean too, for older GUIs
+        write!(
+            line,
+            "info move {} visits {} utility {} winrate {} scoreMean {} scoreStdev {} scoreLead {} \
+             scoreSelfplay {} prior {} lcb {} utilityLcb {} order {} pv {} ",
+            move_info.r#move,
+            move_info.visits,
+            values.utility,
+            values.winrate,
+            values.score_lead,
+            move_info.score_stdev,
+            values.score_lead,
+            values.score_selfplay,
+            move_info.prior,
+            values.lcb,
+            values.utility_lcb,
+            move_info.order,
+            move_info.pv.join(" "),
+        )
+        .unwrap();
+    }
+    if let Some(ownership) = ownership {
+        // Positive for points the side to move owns, like the values above
+        let flipped = flipped(root_info, reported_as);
+        line.push_str("ownership");
+        for value in ownership {
+            // Subtracted so zeros aren't printed as -0
+            let value = if flipped { 0.0 - value } else { *value };
+            write!(line, " {value}").unwrap();
+        }
+    }
+    Some(line.trim_end().to_owned())
+}
+
+// Leela Zero's format, with winrates, priors and lcbs in hundredths of a percent
+pub fn lz_analyze_info(response: &KataResponse) -> Option<String> {
+    let (move_infos, root_info, _, reported_as) = parts(response)?;
+    let mut line = String::new();
+    for move_info in move_infos {
+        let values = for_side_to_move(move_info, root_info, reported_as);
+        write!(
+            line,
+            "info move {} visits {} winrate {} prior {} lcb {} order {} pv {} ",
+            move_info.r#move,
+            move_info.visits,
+            per_ten_thousand(values.winrate),
+            per_ten_thousand(move_info.prior),
+            per_ten_thousand(values.lcb),
+            move_info.order,
+            move_info.pv.join(" "),
+        )
+        .unwrap();
+    }
+    Some(line.trim_end().to_owned())
+}
+
+type Parts<'a> = (
+    Vec<&'a MoveInfo>,
+    &'a RootInfo,
+    Option<&'a Vec<f32>>,
+    ReportAnalysisWinratesAs,
+);
+
+fn parts(response: &KataResponse) -> Option<Parts<'_>> {
+    let KataResponse::Result {
+        move_infos,
+        root_info,
+        ownership,
+        perspective,
+        ..
+    } = response
+    else {
+        return None;
+    };
+    let mut move_infos = move_infos.iter().collect::<Vec<_>>();
+    move_infos.sort_by_key(|move_info| move_info.order);
+    // katago reports for black unless configured otherwise
+    let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+    Some((move_infos, root_info, ownership.as_ref(), reported_as))
+}
+
+// Values as the side to move sees them
+struct SideToMove {
+    winrate: f32,
+    score_lead: f32,
+    score_selfplay: f32,
+    utility: f32,
+    lcb: f32,
+    utility_lcb: f32,
+}
+
+fn flipped(root_info: &RootInfo, reported_as: ReportAnalysisWinratesAs) -> bool {
+    let current_player = root_info.current_player;
+    match (reported_as.player(current_player), current_player) {
+        (Some(reported_for), Some(current_player)) => reported_for != current_player,
+        _ => false,
+    }
+}
+
+fn for_side_to_move(
+    move_info: &MoveInfo,
+    root_info: &RootInfo,
+    reported_as: ReportAnalysisWinratesAs,
+) -> SideToMove {
+    if !flipped(root_info, reported_as) {
+        return SideToMove {
+            winrate: move_info.winrate,
+            score_lead: move_info.score_lead,
+            score_selfplay: move_info.score_selfplay,
+            utility: move_info.utility,
+            lcb: move_info.lcb,
+            utility_lcb: move_info.utility_lcb,
+        };
+    }
+    // Lower confidence bounds keep their distance below the flipped value
+    let winrate = 1.0 - move_info.winrate;
+    let utility = -move_info.utility;
+    SideToMove {
+        winrate,
+        score_lead: -move_info.score_lead,
+        score_selfplay: -move_info.score_selfplay,
+        utility,
+        lcb: winrate - (move_info.winrate - move_info.lcb),
+        utility_lcb: utility - (move_info.utility - move_info.utility_lcb),
+    }
+}
+
+fn per_ten_thousand(value: f32) -> i32 {
+    (value * 10000.0).round() as i32
+}
@@ -0,0 +1,127 @@
+// What players of different strengths would play in one position, from the human model's policy
+// for each `humanSLProfile`, e.g. `rank_5k` against `rank_3d`. The policy comes straight from the
+// net, so every profile is evaluated with a single visit and all of them are submitted together.
+
+use std::fmt;
+
+use crate::policy_eval::{policy_probability, top_moves};
+use crate::{Client, ClientError, KataQuery, KataResponse, Move, Player};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankPolicy {
+    pub profile: String,
+    // Katago's layout with the pass last, negative for illegal moves
+    pub policy: Vec<f32>,
+    // Most likely first, illegal moves left out
+    pub top_moves: Vec<(Move, f32)>,
+}
+
+impl RankPolicy {
+    // The chance a player of this rank plays `point`, 0 for illegal moves
+    pub fn probability(&self, point: Move, board_x_size: u8, board_y_size: u8) -> f32 {
+        policy_probability(&self.policy, point, board_x_size, board_y_size)
+    }
+}
+
+// In the order the profiles were given
+#[derive(Clone, Debug, PartialEq)]
+pub struct HumanSweep {
+    // The side to move
+    pub player: Player,
+    pub board_x_size: u8,
+    pub board_y_size: u8,
+    pub ranks: Vec<RankPolicy>,
+}
+
+impl HumanSweep {
+    pub fn get(&self, profile: &str) -> Option<&RankPolicy> {
+        self.ranks.iter().find(|rank| rank.profile == profile)
+    }
+
+    // How likely each profile is to play `point`, e.g. for a curve of the move over the ranks
+    pub fn probabilities(&self, point: Move) -> Vec<(&str, f32)> {
+        self.ranks
+            .iter()
+            .map(|rank| {
+                let probability = rank.probability(point, self.board_x_size, self.board_y_size);
+                (rank.profile.as_str(), probability)
+            })
+            .collect()
+    }
+}
+
+// One line per profile with its most likely moves
+impl fmt::Display for HumanSweep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rank in &self.ranks {
+            write!(f, "{:>12}", rank.profile)?;
+            for (point, probability) in &rank.top_moves {
+                write!(f, " {:>5} {:>4.1}%", point.to_string(), probability * 100.0)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    // Evaluates the last turn of `position` with each profile, keeping the `top` most likely moves
+    // of each. Needs an engine launched with a human model, any failed profile fails the sweep.
+    pub async fn human_sweep<P: Into<String>>(
+        &self,
+        position: &KataQuery,
+        profiles: impl IntoIterator<Item = P>,
+        top: usize,
+    ) -> Result<HumanSweep, ClientError> {
+        let (board_x_size, board_y_size) = position.board_size();
+        let profiles = profiles
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<String>>();
+        let queries = profiles
+            .iter()
+            .map(|profile| {
+                let mut query = position.clone();
+                query.id = self.next_id();
+                query.analyze_turns = None;
+                query.max_visits = Some(1);
+                query.include_policy = Some(true);
+                query.report_during_search_every = None;
+                query.priorities = None;
+                let settings = query
+                    .override_settings
+                    .get_or_insert_with(|| serde_json::Value::Object(Default::default()));
+                if let serde_json::Value::Object(settings) = settings {
+                    settings.insert("humanSLProfile".to_owned(), profile.as_str().into());
+                }
+                query
+            })
+            .collect::<Vec<_>>();
+        let handles = self.submit_all(queries)?;
+        let mut ranks = Vec::with_capacity(profiles.len());
+        for (profile, handle) in profiles.into_iter().zip(handles) {
+            let result = handle.result().await?;
+            let KataResponse::Result {
+                human_policy: Some(policy),
+                ..
+            } = result
+            else {
+                return Err(ClientError::Unsupported(format!(
+                    "katago reported no human policy for {profile}"
+                )));
+            };
+            let top_moves = top_moves(&policy, board_x_size, board_y_size, top);
+            ranks.push(RankPolicy {
+                profile,
+                policy,
+                top_moves,
+            });
+        }
+        Ok(HumanSweep {
+            player: position.player_to_move(),
+            board_x_size,
+            board_y_size,
+            ranks,
+        })
+    }
+}
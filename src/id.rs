@@ -0,0 +1,131 @@
+use std::borrow::Borrow;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+const NAMESPACE_SEPARATOR: char = ':';
+
+// Any string katago accepts is a valid id, so ids that were not produced by a
+// QueryIdGenerator are kept verbatim
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct QueryId(String);
+
+impl QueryId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn namespaced(namespace: &str, counter: u64) -> Self {
+        Self(format!("{namespace}{NAMESPACE_SEPARATOR}{counter}"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0
+    }
+
+    // Returns None for raw ids that don't follow the `<namespace>:<counter>` layout
+    pub fn namespace(&self) -> Option<&str> {
+        self.split().map(|(namespace, _)| namespace)
+    }
+
+    pub fn counter(&self) -> Option<u64> {
+        self.split().map(|(_, counter)| counter)
+    }
+
+    fn split(&self) -> Option<(&str, u64)> {
+        let (namespace, counter) = self.0.rsplit_once(NAMESPACE_SEPARATOR)?;
+        Some((namespace, counter.parse().ok()?))
+    }
+}
+
+impl fmt::Display for QueryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for QueryId {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(s))
+    }
+}
+
+impl From<String> for QueryId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl From<&str> for QueryId {
+    fn from(id: &str) -> Self {
+        Self::new(id)
+    }
+}
+
+impl From<QueryId> for String {
+    fn from(id: QueryId) -> Self {
+        id.0
+    }
+}
+
+impl AsRef<str> for QueryId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for QueryId {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq<str> for QueryId {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+
+impl PartialEq<&str> for QueryId {
+    fn eq(&self, other: &&str) -> bool {
+        self.0 == *other
+    }
+}
+
+// Hands out `<namespace>:<n>` ids with a monotonically increasing n. Clients sharing one engine
+// should use distinct namespaces so their ids never collide.
+#[derive(Debug)]
+pub struct QueryIdGenerator {
+    namespace: String,
+    counter: AtomicU64,
+}
+
+impl QueryIdGenerator {
+    pub fn new(namespace: impl Into<String>) -> Self {
+        Self {
+            namespace: namespace.into(),
+            counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    pub fn next_id(&self) -> QueryId {
+        QueryId::namespaced(
+            &self.namespace,
+            self.counter.fetch_add(1, Ordering::Relaxed),
+        )
+    }
+}
@@ -0,0 +1,184 @@
+// Import of game records from Tygem (.gib) and WBaduk (.ngf), into the same `SgfGame` SGF import
+// produces. GIB records are always 19x19. Neither format says which rules were used, so `rules`
+// is left for the caller. Handicap stones aren't listed either, they're put on the points
+// GTP's `fixed_handicap` uses, which is where both servers place them.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+use crate::sgf::SgfGame;
+use crate::{handicap_points, Move, Player};
+
+#[derive(Debug, Clone)]
+pub enum ImportError {
+    Missing(&'static str),
+    Invalid { field: &'static str, value: String },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Missing(field) => write!(f, "game record has no {field}"),
+            ImportError::Invalid { field, value } => write!(f, "invalid {field} {value:?}"),
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+// Header lines between `\HS` and `\HE` look like `\[GAMEBLACKNAME=name\]`, the moves between `\GS`
+// and `\GE` like `STO 0 12 2 15 3`: move number, color (1 black, 2 white) and the point counted
+// from the top left. `SKI 0 12` is a pass, `INI 0 1 3 &4` starts a game with 3 handicap stones.
+pub fn parse_gib(gib: &str) -> Result<SgfGame, ImportError> {
+    let mut header = HashMap::new();
+    let mut handicap = 0;
+    let mut moves = Vec::new();
+    let mut in_game = false;
+    for line in gib.lines().map(str::trim) {
+        if let Some(entry) = line
+            .strip_prefix("\\[")
+            .and_then(|entry| entry.strip_suffix("\\]"))
+        {
+            if let Some((key, value)) = entry.split_once('=') {
+                header.insert(key.trim(), value.trim());
+            }
+            continue;
+        }
+        match line {
+            "\\GS" => in_game = true,
+            "\\GE" => in_game = false,
+            _ if in_game => {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                match fields[..] {
+                    ["INI", _, _, stones, ..] => handicap = number("handicap", stones)?,
+                    ["STO", _, _, color, x, y, ..] => {
+                        let player = match color {
+                            "1" => Player::Black,
+                            "2" => Player::White,
+                            _ => return Err(invalid("color", color)),
+                        };
+                        let point = Move::from_top_left(number("x", x)?, number("y", y)?, 19)
+                            .filter(|point| point.is_on_board(19, 19))
+                            .ok_or_else(|| invalid("move", line))?;
+                        moves.push((player, point));
+                    }
+                    ["SKI", ..] => {
+                        let player = next_player(&moves, handicap);
+                        moves.push((player, Move::Pass));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    if header.is_empty() && moves.is_empty() {
+        return Err(ImportError::Missing("GIB header"));
+    }
+
+    // Komi is in tenths of a point, newer files keep it in GAMEINFOMAIN, older in GAMEGONGJE
+    let gongje = header
+        .get("GAMEINFOMAIN")
+        .and_then(|info| {
+            info.split(',')
+                .find_map(|field| field.trim().strip_prefix("GONGJE:"))
+        })
+        .or_else(|| header.get("GAMEGONGJE").copied());
+    let komi = gongje
+        .map(|gongje| number::<i32>("komi", gongje).map(|tenths| tenths as f32 / 10.0))
+        .transpose()?;
+    game(19, komi, handicap, moves)
+}
+
+// Twelve header lines, of which the board size is the 2nd, the handicap the 6th and komi the 8th,
+// then one line per move like `PMABBDD`: `PM`, a two letter move number, the color and the point
+// with `B` for the first column and row from the top left, written twice
+pub fn parse_ngf(ngf: &str) -> Result<SgfGame, ImportError> {
+    let lines = ngf.lines().map(str::trim).collect::<Vec<_>>();
+    let line = |index: usize, field| lines.get(index).copied().ok_or(ImportError::Missing(field));
+    let size = number::<u8>("board size", line(1, "board size")?)?;
+    if !(1..=25).contains(&size) {
+        return Err(invalid("board size", line(1, "board size")?));
+    }
+    let handicap = number::<u8>("handicap", line(5, "handicap")?)?;
+    let mut komi = line(7, "komi")?
+        .parse::<f32>()
+        .map_err(|_| invalid("komi", lines[7]))?;
+    // WBaduk drops the half point of even games' komi
+    if handicap < 2 && komi.fract() == 0.0 {
+        komi += 0.5;
+    }
+
+    let mut moves = Vec::new();
+    for line in lines.iter().skip(12) {
+        let Some(played) = line.strip_prefix("PM") else {
+            continue;
+        };
+        let bytes = played.as_bytes();
+        let (Some(&color), Some(&x), Some(&y)) = (bytes.get(2), bytes.get(3), bytes.get(4)) else {
+            return Err(invalid("move", line));
+        };
+        let player = match color {
+            b'B' => Player::Black,
+            b'W' => Player::White,
+            _ => return Err(invalid("move", line)),
+        };
+        // Points off the board, usually `AA`, are passes
+        let (x, y) = (x.wrapping_sub(b'B'), y.wrapping_sub(b'B'));
+        let point = if x < size && y < size {
+            Move::from_top_left(x, y, size).ok_or_else(|| invalid("move", line))?
+        } else {
+            Move::Pass
+        };
+        moves.push((player, point));
+    }
+    game(size, Some(komi), handicap, moves)
+}
+
+fn game(
+    size: u8,
+    komi: Option<f32>,
+    handicap: u8,
+    moves: Vec<(Player, Move)>,
+) -> Result<SgfGame, ImportError> {
+    let initial_stones = if handicap >= 2 {
+        handicap_points(handicap, size)
+            .ok_or_else(|| invalid("handicap", &handicap.to_string()))?
+            .into_iter()
+            .map(|point| (Player::Black, point))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok(SgfGame {
+        board_x_size: size,
+        board_y_size: size,
+        rules: None,
+        ruleset: None,
+        komi,
+        handicap,
+        initial_stones,
+        initial_player: None,
+        moves,
+    })
+}
+
+fn next_player(moves: &[(Player, Move)], handicap: u8) -> Player {
+    match moves.last() {
+        Some((last, _)) => last.opponent(),
+        None if handicap >= 2 => Player::White,
+        None => Player::Black,
+    }
+}
+
+fn number<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<T, ImportError> {
+    value.trim().parse().map_err(|_| invalid(field, value))
+}
+
+fn invalid(field: &'static str, value: &str) -> ImportError {
+    ImportError::Invalid {
+        field,
+        value: value.to_owned(),
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt;
+use tokio::sync::watch;
+
+use crate::{Client, ClientError, KataQuery, KataResponse, QueryId};
+
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum JobStatus {
+    // The latest interim result, if the query reports during search
+    Pending { latest: Option<KataResponse> },
+    // Final results of every analyzed turn
+    Done(Vec<KataResponse>),
+    Failed(ClientError),
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobStatus::Pending { .. })
+    }
+}
+
+// Decouples submitting queries from reading their results: jobs are identified by their query id
+// and their results are kept until removed, so callers can poll or subscribe at any later point,
+// e.g. after a web client reconnected. Combine with `ClientBuilder::result_cache` to also keep
+// results across restarts.
+#[derive(Clone)]
+pub struct JobQueue {
+    client: Client,
+    jobs: Arc<Mutex<HashMap<QueryId, watch::Sender<JobStatus>>>>,
+}
+
+impl JobQueue {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            jobs: Default::default(),
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    // Returns the job's token, which is the query's id
+    pub fn submit(&self, query: KataQuery) -> Result<QueryId, ClientError> {
+        let mut handle = self.client.submit(query)?;
+        let id = handle.id().clone();
+        let (status, _) = watch::channel(JobStatus::Pending { latest: None });
+        self.jobs.lock().unwrap().insert(id.clone(), status.clone());
+
+        tokio::spawn(async move {
+            let mut finals = Vec::new();
+            while let Some(response) = handle.next().await {
+                match response {
+                    KataResponse::Error { error, field, .. } => {
+                        status.send_replace(JobStatus::Failed(ClientError::Rejected {
+                            error,
+                            field,
+                        }));
+                        return;
+                    }
+                    KataResponse::Warning { .. } => {}
+                    response if response.is_during_search() => {
+                        status.send_replace(JobStatus::Pending {
+                            latest: Some(response),
+                        });
+                    }
+                    response => finals.push(response),
+                }
+            }
+            status.send_replace(if finals.is_empty() {
+                JobStatus::Failed(ClientError::Closed)
+            } else {
+                JobStatus::Done(finals)
+            });
+        });
+        Ok(id)
+    }
+
+    // None for unknown or removed jobs
+    pub fn status(&self, id: &QueryId) -> Option<JobStatus> {
+        Some(self.jobs.lock().unwrap().get(id)?.borrow().clone())
+    }
+
+    // Sees every status change from now on, the current status is marked as seen already
+    pub fn subscribe(&self, id: &QueryId) -> Option<watch::Receiver<JobStatus>> {
+        Some(self.jobs.lock().unwrap().get(id)?.subscribe())
+    }
+
+    // Waits until the job finished, without removing it
+    pub async fn wait(&self, id: &QueryId) -> Option<JobStatus> {
+        let mut status = self.subscribe(id)?;
+        let status = status.wait_for(JobStatus::is_finished).await.ok()?;
+        Some(status.clone())
+    }
+
+    // Forgets the job, a job still running keeps running
+    pub fn remove(&self, id: &QueryId) -> Option<JobStatus> {
+        let status = self.jobs.lock().unwrap().remove(id)?;
+        let status = status.borrow().clone();
+        Some(status)
+    }
+
+    pub async fn terminate(&self, id: &QueryId) -> Result<KataResponse, ClientError> {
+        self.client.terminate(id, None).await
+    }
+}
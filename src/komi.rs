@@ -0,0 +1,165 @@
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// Komi katago accepts, a whole or half number of points from -150 to 150. Kept as half points, so
+// it's exact and always serializes without an exponent or a negative zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct KomiValue {
+    halves: i32,
+}
+
+impl KomiValue {
+    pub const ZERO: KomiValue = KomiValue { halves: 0 };
+    pub const MAX: KomiValue = KomiValue { halves: 300 };
+    pub const MIN: KomiValue = KomiValue { halves: -300 };
+
+    pub fn new(komi: f32) -> Result<Self, KomiError> {
+        let halves = komi * 2.0;
+        if !halves.is_finite() || halves.fract() != 0.0 || halves.abs() > Self::MAX.halves as f32 {
+            return Err(KomiError(komi));
+        }
+        Ok(Self {
+            halves: halves as i32,
+        })
+    }
+
+    pub fn from_halves(halves: i32) -> Result<Self, KomiError> {
+        if halves.abs() > Self::MAX.halves {
+            return Err(KomiError(halves as f32 / 2.0));
+        }
+        Ok(Self { halves })
+    }
+
+    pub fn halves(&self) -> i32 {
+        self.halves
+    }
+
+    pub fn get(&self) -> f32 {
+        self.halves as f32 / 2.0
+    }
+}
+
+impl TryFrom<f32> for KomiValue {
+    type Error = KomiError;
+
+    fn try_from(komi: f32) -> Result<Self, Self::Error> {
+        Self::new(komi)
+    }
+}
+
+impl From<KomiValue> for f32 {
+    fn from(komi: KomiValue) -> Self {
+        komi.get()
+    }
+}
+
+// So it can be passed to `KataQueryBuilder::komi` as is
+impl From<KomiValue> for Option<f32> {
+    fn from(komi: KomiValue) -> Self {
+        Some(komi.get())
+    }
+}
+
+impl fmt::Display for KomiValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.get(), f)
+    }
+}
+
+impl Serialize for KomiValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f32(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for KomiValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        KomiValue::new(f32::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KomiError(f32);
+
+impl fmt::Display for KomiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "komi {} isn't a whole or half point from -150 to 150",
+            self.0
+        )
+    }
+}
+
+impl Error for KomiError {}
+
+// Komi in queries is a plain f32 for the builder's sake, this writes valid ones like `KomiValue`
+pub(crate) fn serialize_komi<S: Serializer>(
+    komi: &Option<f32>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match komi.map(KomiValue::new) {
+        Some(Ok(komi)) => komi.serialize(serializer),
+        _ => komi.serialize(serializer),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_whole_and_half_points() {
+        for komi in [0.0, -0.0, 0.5, -0.5, 6.5, 7.0, -150.0, 150.0] {
+            assert_eq!(
+                KomiValue::new(komi).map(|komi| komi.get()),
+                Ok(komi),
+                "{komi}"
+            );
+        }
+        assert_eq!(KomiValue::new(-0.0).unwrap(), KomiValue::ZERO);
+    }
+
+    #[test]
+    fn rejects_other_values() {
+        for komi in [
+            0.25,
+            6.3,
+            -7.1,
+            0.1,
+            f32::NAN,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            150.5,
+            -151.0,
+            1e6,
+            1e10,
+        ] {
+            assert!(KomiValue::new(komi).is_err(), "{komi}");
+        }
+        assert!(KomiValue::from_halves(301).is_err());
+        assert!(KomiValue::from_halves(-301).is_err());
+        assert!(serde_json::from_str::<KomiValue>("6.3").is_err());
+        assert_eq!(
+            serde_json::from_str::<KomiValue>("7.5").unwrap(),
+            KomiValue::from_halves(15).unwrap()
+        );
+        assert!(serde_json::from_str::<KomiValue>("1000000.0").is_err());
+    }
+
+    #[test]
+    fn serializes_without_exponents() {
+        for (komi, written) in [
+            (KomiValue::ZERO, "0.0"),
+            (KomiValue::new(-0.0).unwrap(), "0.0"),
+            (KomiValue::from_halves(13).unwrap(), "6.5"),
+            (KomiValue::from_halves(-1).unwrap(), "-0.5"),
+            (KomiValue::MAX, "150.0"),
+            (KomiValue::MIN, "-150.0"),
+        ] {
+            assert_eq!(serde_json::to_string(&komi).unwrap(), written);
+        }
+    }
+}
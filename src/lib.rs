@@ -1,26 +1,273 @@
+use std::collections::HashSet;
+#[cfg(feature = "process")]
 use std::error::Error;
+#[cfg(feature = "process")]
 use std::process::Stdio;
+use std::time::Duration;
 
-use bytes::BytesMut;
+use bytes::{BufMut, BytesMut};
 use derive_builder::Builder;
+#[cfg(feature = "process")]
 use futures_core::Stream;
+#[cfg(feature = "process")]
 use futures_sink::Sink;
+#[cfg(feature = "process")]
 use futures_util::StreamExt;
+#[cfg(feature = "process")]
 use tokio::io::{AsyncBufReadExt, BufReader};
+#[cfg(feature = "process")]
 use tokio::process::Command;
 
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "process")]
 use tokio_stream::wrappers::LinesStream;
-use tokio_util::codec::{Encoder, FramedWrite};
+#[cfg(feature = "process")]
+use tokio_util::codec::FramedWrite;
+use tokio_util::codec::{Decoder, Encoder, LinesCodec, LinesCodecError};
 
-// Errors and warnings are currently not handled
-#[derive(Deserialize, Clone, Debug)]
+mod annotation;
+mod auth;
+mod batch;
+mod board;
+mod bot;
+#[cfg(feature = "cache")]
+mod cache;
+mod calibration;
+mod canonical;
+mod capabilities;
+mod client;
+mod config;
+mod diagnostics;
+mod distribution;
+mod endgame;
+#[cfg(feature = "process")]
+mod engine;
+#[cfg(feature = "process")]
+mod events;
+mod framing;
+mod fuzz;
+#[cfg(feature = "process")]
+mod gpu_budget;
+mod gtp;
+mod human_sweep;
+mod id;
+pub mod import;
+mod jobs;
+mod komi;
+mod loader;
+mod metrics;
+pub mod models;
+mod moves;
+#[cfg(feature = "msgpack")]
+mod msgpack;
+mod mux;
+mod names;
+pub mod ogs;
+mod ownership;
+mod perspective;
+mod phase;
+mod point_values;
+mod policy_eval;
+#[cfg(feature = "process")]
+mod pool;
+mod priority;
+mod probe;
+#[cfg(feature = "priority")]
+mod process_priority;
+mod progress;
+mod protocol_log;
+mod received;
+pub mod render;
+mod replay;
+#[cfg(feature = "report")]
+mod report;
+mod review_budget;
+mod schema;
+mod selection;
+mod series;
+mod session;
+pub mod sgf;
+mod sha256;
+#[cfg(feature = "signal")]
+mod signal;
+mod snapshot;
+mod special;
+mod split;
+mod sse;
+mod startup;
+mod stats;
+mod summary;
+pub mod typed;
+mod version_diff;
+
+pub use annotation::{Annotation, Annotator, Loss};
+pub use auth::{Authenticator, Principal, StaticApiKeys};
+pub use batch::{BatchAnalysis, BatchError, BatchSummary};
+pub use board::{Board, BoardError};
+pub use bot::{PassPolicy, ResignPolicy};
+#[cfg(feature = "cache")]
+pub use cache::ResultCache;
+pub use calibration::{Calibration, GameResult, ReliabilityBin};
+pub use capabilities::EngineCapabilities;
+pub use client::{
+    CacheClearPolicy, Client, ClientBuilder, ClientError, QueryHandle, QueryResult, QueryTtl,
+};
+pub use config::{AnalysisConfig, AnalysisConfigBuilder, ReportAnalysisWinratesAs};
+#[cfg(feature = "process")]
+pub use diagnostics::LogEvents;
+pub use diagnostics::{
+    classify as classify_stderr_line, FatalError, FatalErrorKind, LogEvent, LogLevel,
+};
+pub use distribution::ScoreDistribution;
+pub use endgame::{BoundaryPlay, EndgameCounter, EndgameReport};
+#[cfg(feature = "process")]
+pub use engine::{Engine, EngineBuilder, EngineError, EngineHandle};
+#[cfg(feature = "process")]
+pub use events::{EngineEvent, EngineEvents};
+pub use framing::{action_sink, response_stream, ResponseStream};
+pub use fuzz::fuzz_response;
+#[cfg(feature = "process")]
+pub use gpu_budget::{EngineLimits, GpuBudget, GpuBudgetError};
+pub use gtp::{kata_analyze_info, lz_analyze_info};
+pub use human_sweep::{HumanSweep, RankPolicy};
+pub use id::{QueryId, QueryIdGenerator};
+pub use jobs::{JobQueue, JobStatus};
+pub use komi::{KomiError, KomiValue};
+pub use loader::{LoadError, LoadedGame, SgfLoader};
+pub use metrics::{LatencyHistogram, SlowQuery, Throughput};
+pub use moves::{Move, ParseMoveError};
+#[cfg(feature = "msgpack")]
+pub use msgpack::{from_msgpack, to_msgpack, MsgpackError};
+pub use mux::{MuxClient, MuxConnection, MuxError, MuxQuota};
+pub use names::ParseNameError;
+pub use ownership::{OwnershipDelta, OwnershipRegion};
+pub use phase::GamePhase;
+pub use point_values::{PointValue, PointValueEstimator, PointValues, ValueSource};
+pub use policy_eval::PolicyEvaluation;
+#[cfg(feature = "process")]
+pub use pool::{EnginePool, EnginePoolBuilder};
+pub use priority::{Priority, QueryLane};
+pub use probe::{ComparedMove, Comparison, Probe};
+#[cfg(feature = "priority")]
+pub use process_priority::ProcessPriority;
+pub use progress::{Progress, SearchProgress};
+#[cfg(feature = "process")]
+pub use protocol_log::ProtocolLog;
+pub use received::{stamp_received, ReceivedResponse, Stamped};
+pub use replay::Replay;
+#[cfg(feature = "report")]
+pub use report::html_report;
+pub use review_budget::{PlannedTurn, ReviewBudget, ReviewPlan};
+pub use schema::{
+    migrate_results, read_results, schema_version, stamp_results, SchemaError, SCHEMA_VERSION,
+};
+pub use selection::{MoveInfos, SortKey};
+pub use series::{EvaluationPoint, EvaluationSeries};
+pub use session::GameSession;
+#[cfg(feature = "signal")]
+pub use signal::shutdown_signal;
+pub use snapshot::{Analysis, CandidateMove, Position, RawEvaluation};
+pub use special::{
+    find_special_positions, probe_special_positions, SpecialKind, SpecialOutcome, SpecialPosition,
+};
+pub use split::{split_by_id, QueryStream, SplitById};
+pub use sse::{sse_event, sse_stream};
+#[cfg(feature = "process")]
+pub use startup::StartupEvents;
+pub use startup::{StartupPhase, StartupProgress};
+pub use stats::{DatasetStats, MoveStats};
+pub use summary::{summarize, summarize_with, EnglishSummary, SummaryPhrases};
+pub use version_diff::{PositionDiff, VersionDiff};
+
+// Deserialized by looking at which keys are present rather than by trying every variant in turn,
+// see `KataResponse::kind`. That keeps acks from being mistaken for results and the errors name the
+// field that's actually wrong.
+#[derive(Serialize, Clone, Debug)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum KataResponse {
     #[serde(rename_all = "camelCase")]
     Result {
-        id: String,
+        id: QueryId,
+        // Missing from versions before interim results, whose results are all final
+        #[serde(default)]
+        is_during_search: bool,
+        turn_number: u32,
+        move_infos: Vec<MoveInfo>,
+        root_info: RootInfo,
+        #[serde(default)]
+        ownership: Option<Vec<f32>>,
+        #[serde(default)]
+        ownership_stdev: Option<Vec<f32>>,
+        #[serde(default)]
+        policy: Option<Vec<f32>>,
+        // The human model's policy for the query's `humanSLProfile`, reported along with `policy`
+        #[serde(default)]
+        human_policy: Option<Vec<f32>>,
+        // Not part of katago's output, filled in by `Engine` or `Client` when they know how the
+        // engine was configured
+        #[serde(skip)]
+        perspective: Option<ReportAnalysisWinratesAs>,
+        // Not part of katago's output either, set by `Client` on the final result of a terminated
+        // turn, which has whatever the search found until then
+        #[serde(skip)]
+        terminated: bool,
+    },
+
+    #[serde(rename_all = "camelCase")]
+    Resultless {
+        id: QueryId,
+        #[serde(default)]
+        is_during_search: bool,
+        turn_number: u32,
+        no_results: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    TerminateAck {
+        id: QueryId,
+        action: ActionTerminate,
+        #[serde(default)]
+        turn_number: Option<u32>,
+        terminate_id: QueryId,
+    },
+    Version {
+        action: ActionQueryVersion,
+        git_hash: String,
+        id: QueryId,
+        version: String,
+    },
+    CacheCleared {
+        id: QueryId,
+        action: ActionClearCache,
+    },
+    // katago omits the id when the offending line couldn't be parsed at all
+    Error {
+        #[serde(default)]
+        id: Option<QueryId>,
+        error: String,
+        #[serde(default)]
+        field: Option<String>,
+    },
+    Warning {
+        #[serde(default)]
+        id: Option<QueryId>,
+        warning: String,
+        #[serde(default)]
+        field: Option<String>,
+    },
+}
+
+// Mirrors `KataResponse` variant for variant, serde checks the two stay in sync
+#[derive(Deserialize)]
+#[serde(remote = "KataResponse")]
+#[allow(clippy::large_enum_variant)]
+enum KataResponseRepr {
+    #[serde(rename_all = "camelCase")]
+    Result {
+        id: QueryId,
+        #[serde(default)]
         is_during_search: bool,
+        turn_number: u32,
         move_infos: Vec<MoveInfo>,
         root_info: RootInfo,
         #[serde(default)]
@@ -29,76 +276,240 @@ pub enum KataResponse {
         ownership_stdev: Option<Vec<f32>>,
         #[serde(default)]
         policy: Option<Vec<f32>>,
+        #[serde(default)]
+        human_policy: Option<Vec<f32>>,
+        #[serde(skip)]
+        perspective: Option<ReportAnalysisWinratesAs>,
+        #[serde(skip)]
+        terminated: bool,
     },
 
     #[serde(rename_all = "camelCase")]
     Resultless {
-        id: String,
+        id: QueryId,
+        #[serde(default)]
         is_during_search: bool,
-        turn_number: u16,
+        turn_number: u32,
         no_results: bool,
     },
     #[serde(rename_all = "camelCase")]
     TerminateAck {
-        id: String,
+        id: QueryId,
         action: ActionTerminate,
         #[serde(default)]
-        turn_number: Option<u16>,
-        terminate_id: String,
+        turn_number: Option<u32>,
+        terminate_id: QueryId,
     },
     Version {
         action: ActionQueryVersion,
         git_hash: String,
-        id: String,
+        id: QueryId,
         version: String,
     },
     CacheCleared {
-        id: String,
+        id: QueryId,
         action: ActionClearCache,
     },
+    // katago omits the id when the offending line couldn't be parsed at all
+    Error {
+        #[serde(default)]
+        id: Option<QueryId>,
+        error: String,
+        #[serde(default)]
+        field: Option<String>,
+    },
+    Warning {
+        #[serde(default)]
+        id: Option<QueryId>,
+        warning: String,
+        #[serde(default)]
+        field: Option<String>,
+    },
 }
 
-#[derive(Clone, Deserialize, Debug)]
+impl<'de> Deserialize<'de> for KataResponse {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let value = serde_json::Value::deserialize(deserializer)?;
+        let kind = KataResponse::kind(&value).map_err(D::Error::custom)?;
+        let tagged = serde_json::Value::Object([(kind.to_owned(), value)].into_iter().collect());
+        KataResponseRepr::deserialize(tagged)
+            .map_err(|err| D::Error::custom(format_args!("invalid {kind} response: {err}")))
+    }
+}
+
+impl KataResponse {
+    // Errors and warnings can carry any of the other keys, `action` is only present in acks and
+    // `noResults` only when there was nothing to analyze
+    fn kind(value: &serde_json::Value) -> Result<&'static str, String> {
+        let serde_json::Value::Object(keys) = value else {
+            return Err(format!("expected a JSON object, got {value}"));
+        };
+        if keys.contains_key("error") {
+            return Ok("Error");
+        }
+        if keys.contains_key("warning") {
+            return Ok("Warning");
+        }
+        if let Some(action) = keys.get("action") {
+            return match action.as_str() {
+                Some("terminate") => Ok("TerminateAck"),
+                Some("query_version") => Ok("Version"),
+                Some("clear_cache") => Ok("CacheCleared"),
+                _ => Err(format!("unknown action {action}")),
+            };
+        }
+        if keys.contains_key("noResults") {
+            return Ok("Resultless");
+        }
+        Ok("Result")
+    }
+
+    pub fn id(&self) -> Option<&QueryId> {
+        match self {
+            KataResponse::Result { id, .. }
+            | KataResponse::Resultless { id, .. }
+            | KataResponse::TerminateAck { id, .. }
+            | KataResponse::Version { id, .. }
+            | KataResponse::CacheCleared { id, .. } => Some(id),
+            KataResponse::Error { id, .. } | KataResponse::Warning { id, .. } => id.as_ref(),
+        }
+    }
+
+    // Interim results sent because of `report_during_search_every`, more responses follow for the
+    // same query and turn
+    pub fn is_during_search(&self) -> bool {
+        match self {
+            KataResponse::Result {
+                is_during_search, ..
+            }
+            | KataResponse::Resultless {
+                is_during_search, ..
+            } => *is_during_search,
+            _ => false,
+        }
+    }
+
+    // Whether the result is what the search found until the client terminated it, rather than
+    // what it would have found. The client can't tell searches which were about to finish anyway
+    // apart, those are flagged too.
+    pub fn is_terminated(&self) -> bool {
+        matches!(
+            self,
+            KataResponse::Result {
+                terminated: true,
+                ..
+            }
+        )
+    }
+
+    // The turn a result is for
+    pub fn turn_number(&self) -> Option<u32> {
+        match self {
+            KataResponse::Result { turn_number, .. }
+            | KataResponse::Resultless { turn_number, .. } => Some(*turn_number),
+            KataResponse::TerminateAck { turn_number, .. } => *turn_number,
+            _ => None,
+        }
+    }
+
+    // The ownership of `point`, None unless it was requested. The board size has to be the
+    // query's, an array of a different size is rejected rather than indexed as if it matched.
+    pub fn ownership_at(&self, point: Move, board_x_size: u8, board_y_size: u8) -> Option<f32> {
+        let KataResponse::Result {
+            ownership: Some(ownership),
+            ..
+        } = self
+        else {
+            return None;
+        };
+        if ownership.len() != board_x_size as usize * board_y_size as usize {
+            return None;
+        }
+        ownership
+            .get(point.index(board_x_size, board_y_size)?)
+            .copied()
+    }
+
+    pub(crate) fn for_each_id_mut(&mut self, mut f: impl FnMut(&mut QueryId)) {
+        match self {
+            KataResponse::TerminateAck {
+                id, terminate_id, ..
+            } => {
+                f(id);
+                f(terminate_id);
+            }
+            KataResponse::Result { id, .. }
+            | KataResponse::Resultless { id, .. }
+            | KataResponse::Version { id, .. }
+            | KataResponse::CacheCleared { id, .. } => f(id),
+            KataResponse::Error { id, .. } | KataResponse::Warning { id, .. } => {
+                if let Some(id) = id {
+                    f(id)
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum GitHashOmitted {
     #[serde(rename = "<omitted>")]
     Omitted,
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 #[serde(untagged)]
 pub enum GitHash {
     Omitted(GitHashOmitted),
     Included(String),
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RootInfo {
     pub winrate: f32,
     pub score_lead: f32,
     pub score_selfplay: f32,
     #[serde(default)]
+    pub score_stdev: Option<f32>,
+    #[serde(default)]
     pub utility: Option<f32>,
-    pub visits: u32,
+    pub visits: u64,
     #[serde(default)]
     pub this_hash: Option<String>,
     #[serde(default)]
     pub sym_hash: Option<String>,
     #[serde(default)]
     pub current_player: Option<Player>,
-    // It is unclear then katago includes this fields in the response, and even are they optional,
-    // so they are ignored currently
-    //pub raw_st_wr_error: f32,
-    //pub raw_st_score_error: f32,
-    //pub raw_var_time_left: u32,
+    // The net's own evaluation of the root, before any search, from the same point of view as the
+    // values above. Only reported by recent katago versions.
+    #[serde(default)]
+    pub raw_winrate: Option<f32>,
+    #[serde(default)]
+    pub raw_lead: Option<f32>,
+    #[serde(default)]
+    pub raw_score_selfplay: Option<f32>,
+    #[serde(default)]
+    pub raw_score_selfplay_stdev: Option<f32>,
+    #[serde(default)]
+    pub raw_no_result_prob: Option<f32>,
+    // How far the net expects the search's winrate and score to move from its own evaluation
+    #[serde(default)]
+    pub raw_st_wr_error: Option<f32>,
+    #[serde(default)]
+    pub raw_st_score_error: Option<f32>,
+    #[serde(default)]
+    pub raw_var_time_left: Option<f32>,
 }
 
-#[derive(Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MoveInfo {
     pub r#move: String,
     pub winrate: f32,
-    pub visits: u32,
+    pub visits: u64,
     pub score_lead: f32,
     pub score_selfplay: f32,
     pub score_stdev: f32,
@@ -110,9 +521,9 @@ pub struct MoveInfo {
     pub is_symmetry_of: Option<String>,
     pub pv: Vec<String>,
     #[serde(default)]
-    pub pv_visits: Option<Vec<u32>>,
+    pub pv_visits: Option<Vec<u64>>,
     #[serde(default)]
-    pub pv_edge_visits: Option<Vec<u32>>,
+    pub pv_edge_visits: Option<Vec<u64>>,
     #[serde(default)]
     pub ownership: Option<Vec<f32>>,
     #[serde(default)]
@@ -122,28 +533,53 @@ pub struct MoveInfo {
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Clone, Debug)]
 #[serde(untagged)]
+#[allow(clippy::large_enum_variant)]
 pub enum KataAction {
     Query {
         #[serde(flatten)]
         inner: KataQuery,
     },
     QueryVersion {
-        id: String,
+        id: QueryId,
         action: ActionQueryVersion,
     },
     ClearCache {
-        id: String,
+        id: QueryId,
         action: ActionClearCache,
     },
     #[serde(rename_all = "camelCase")]
     Terminate {
-        id: String,
+        id: QueryId,
         action: ActionTerminate,
-        terminate_id: String,
-        turn_numbers: Option<Vec<u16>>,
+        terminate_id: QueryId,
+        turn_numbers: Option<Vec<u32>>,
     },
 }
 
+impl KataAction {
+    pub fn id(&self) -> &QueryId {
+        match self {
+            KataAction::Query { inner } => &inner.id,
+            KataAction::QueryVersion { id, .. }
+            | KataAction::ClearCache { id, .. }
+            | KataAction::Terminate { id, .. } => id,
+        }
+    }
+
+    pub(crate) fn for_each_id_mut(&mut self, mut f: impl FnMut(&mut QueryId)) {
+        match self {
+            KataAction::Query { inner } => f(&mut inner.id),
+            KataAction::Terminate {
+                id, terminate_id, ..
+            } => {
+                f(id);
+                f(terminate_id);
+            }
+            KataAction::QueryVersion { id, .. } | KataAction::ClearCache { id, .. } => f(id),
+        }
+    }
+}
+
 #[derive(Serialize, Clone, Debug, Deserialize)]
 pub enum ActionQueryVersion {
     #[serde(rename = "query_version")]
@@ -163,28 +599,42 @@ pub enum ActionTerminate {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Serialize, Clone, Debug, Builder)]
-#[builder(setter(into))]
+#[derive(Serialize, Deserialize, Clone, Debug, Builder)]
+#[builder(setter(into), build_fn(validate = "Self::validate"), derive(Debug))]
 #[serde(rename_all = "camelCase")]
 pub struct KataQuery {
-    id: String,
+    id: QueryId,
     #[builder(default)]
-    initial_stones: Option<Vec<(Player, String)>>,
-    moves: Vec<(Player, String)>,
+    initial_stones: Option<Vec<(Player, Move)>>,
+    moves: Vec<(Player, Move)>,
     // Passing custom rule set is not yet supported, only shorthands can be passed at the moment
     rules: Rules,
     #[builder(default)]
     initial_player: Option<Player>,
+    // Whole or half points, see `KomiValue`
     #[builder(default)]
+    #[serde(serialize_with = "komi::serialize_komi")]
     komi: Option<f32>,
     #[builder(default)]
     white_handicap_bonus: Option<WhiteHandicapBonus>,
     board_x_size: u8,
     board_y_size: u8,
     #[builder(default)]
-    analyze_turns: Option<Vec<u16>>,
+    analyze_turns: Option<Vec<u32>>,
+    #[builder(default)]
+    max_visits: Option<u64>,
+    // katago has no query field for it, it's sent as `maxTime` in `override_settings`
+    #[builder(default)]
+    #[serde(skip)]
+    max_time: Option<Duration>,
+    // Also only settable through `override_settings`. From -3 to 3, positive values make the
+    // engine play as if it were stronger, aggressively.
     #[builder(default)]
-    max_visits: Option<u32>,
+    #[serde(skip)]
+    playout_doubling_advantage: Option<f32>,
+    #[builder(default)]
+    #[serde(skip)]
+    anti_mirror: Option<bool>,
     #[builder(default)]
     root_policy_temperature: Option<f32>,
     #[builder(default)]
@@ -193,7 +643,9 @@ pub struct KataQuery {
     anaysis_pv_len: Option<u16>,
     #[builder(default)]
     include_ownership: Option<bool>,
+    // Misspelled here, katago reads it as `includeOwnershipStdev`
     #[builder(default)]
+    #[serde(rename = "includeOwnershipStdev")]
     inlcude_ownership_stdev: Option<bool>,
     #[builder(default)]
     include_moves_ownership: Option<bool>,
@@ -208,23 +660,386 @@ pub struct KataQuery {
     #[builder(default)]
     allow_moves: Option<[MoveGroup; 1]>,
     // TODO: Maybe use HashMap here instead of Value?
-    #[builder(default)]
+    #[builder(field(build = "self.build_override_settings()"))]
+    #[serde(serialize_with = "serialize_settings")]
     override_settings: Option<serde_json::Value>,
     #[builder(default)]
     report_during_search_every: Option<f32>,
     #[builder(default)]
-    priority: Option<i32>,
+    priority: Option<Priority>,
     #[builder(default)]
-    priorities: Option<Vec<i32>>,
+    priorities: Option<Vec<Priority>>,
+}
+
+impl KataQueryBuilder {
+    // These append to what's already set, so positions can be built up move by move
+    pub fn add_move(&mut self, player: Player, played: Move) -> &mut Self {
+        self.extend_moves([(player, played)])
+    }
+
+    pub fn extend_moves(&mut self, moves: impl IntoIterator<Item = (Player, Move)>) -> &mut Self {
+        self.moves.get_or_insert_with(Vec::new).extend(moves);
+        self
+    }
+
+    pub fn add_initial_stone(&mut self, player: Player, point: Move) -> &mut Self {
+        self.initial_stones
+            .get_or_insert(None)
+            .get_or_insert_with(Vec::new)
+            .push((player, point));
+        self
+    }
+
+    // Moves aren't checked for occupied points, a point can be played again once it's captured
+    fn validate(&self) -> Result<(), String> {
+        let (Some(board_x_size), Some(board_y_size)) = (self.board_x_size, self.board_y_size)
+        else {
+            return Ok(());
+        };
+        if board_x_size == 0 || board_y_size == 0 {
+            return Err(format!("{board_x_size}x{board_y_size} isn't a board"));
+        }
+        let off_board =
+            |(_, point): &&(Player, Move)| !point.is_on_board(board_x_size, board_y_size);
+        let initial_stones = self.initial_stones.iter().flatten().flatten();
+        let moves = self.moves.iter().flatten();
+        if let Some((_, point)) = initial_stones.clone().chain(moves).find(off_board) {
+            return Err(format!(
+                "{point} is outside the {board_x_size}x{board_y_size} board"
+            ));
+        }
+        let mut occupied = HashSet::new();
+        for (_, stone) in initial_stones {
+            if *stone == Move::Pass {
+                return Err("initial stones can't be passes".to_owned());
+            }
+            if !occupied.insert(stone) {
+                return Err(format!("{stone} has more than one initial stone"));
+            }
+        }
+        if let Some(komi) = self.komi.flatten() {
+            KomiValue::new(komi).map_err(|err| err.to_string())?;
+        }
+        if self
+            .max_time
+            .flatten()
+            .is_some_and(|max_time| max_time.is_zero())
+        {
+            return Err("max_time must be positive".to_owned());
+        }
+        if let Some(advantage) = self.playout_doubling_advantage.flatten() {
+            if !(-3.0..=3.0).contains(&advantage) {
+                return Err(format!(
+                    "playout_doubling_advantage {advantage} isn't between -3 and 3"
+                ));
+            }
+        }
+        let typed_settings = self.max_time.flatten().is_some()
+            || self.playout_doubling_advantage.flatten().is_some()
+            || self.anti_mirror.flatten().is_some();
+        if typed_settings
+            && !matches!(
+                self.override_settings,
+                None | Some(None) | Some(Some(serde_json::Value::Object(_)))
+            )
+        {
+            return Err(
+                "override_settings must be an object to set max_time, playout_doubling_advantage \
+                 or anti_mirror"
+                    .to_owned(),
+            );
+        }
+        Ok(())
+    }
+
+    // Playout doubling advantage for a bot giving `handicap` stones, so it plays for the win
+    // instead of defending a position it thinks is lost: 1 for 2 stones, up to 2.75 for 9
+    pub fn handicap_aggression(&mut self, handicap: u8) -> &mut Self {
+        let advantage = match handicap {
+            ..=1 => 0.0,
+            handicap => (1.0 + (handicap - 2) as f32 * 0.25).min(3.0),
+        };
+        self.playout_doubling_advantage(advantage)
+    }
+
+    fn build_override_settings(&self) -> Option<serde_json::Value> {
+        let mut override_settings = self.override_settings.clone().flatten();
+        let typed_settings = [
+            (
+                "maxTime",
+                self.max_time
+                    .flatten()
+                    .map(|max_time| max_time.as_secs_f64().into()),
+            ),
+            (
+                "playoutDoublingAdvantage",
+                // Through its shortest decimal form, widening 0.1f32 would send 0.10000000149
+                self.playout_doubling_advantage
+                    .flatten()
+                    .and_then(|advantage| advantage.to_string().parse::<f64>().ok())
+                    .map(Into::into),
+            ),
+            ("antiMirror", self.anti_mirror.flatten().map(Into::into)),
+        ];
+        for (key, value) in typed_settings {
+            let Some(value) = value else {
+                continue;
+            };
+            if let serde_json::Value::Object(settings) = override_settings
+                .get_or_insert_with(|| serde_json::Value::Object(Default::default()))
+            {
+                settings.insert(key.to_owned(), value);
+            }
+        }
+        if let Some(override_settings) = &mut override_settings {
+            normalize_numbers(override_settings);
+        }
+        override_settings
+    }
+}
+
+// The star points GTP's `fixed_handicap` puts `handicap` stones on, in its order
+pub(crate) fn handicap_points(handicap: u8, board_size: u8) -> Option<Vec<Move>> {
+    let max_handicap = match board_size {
+        ..=6 => 0,
+        size if size >= 9 && size % 2 == 1 => 9,
+        _ => 4,
+    };
+    if !(2..=max_handicap).contains(&handicap) {
+        return None;
+    }
+    let edge = if board_size <= 12 { 2 } else { 3 };
+    let (low, middle, high) = (edge, board_size / 2, board_size - 1 - edge);
+    let mut points = vec![(low, low), (high, high), (low, high), (high, low)];
+    points.truncate(handicap.min(4) as usize);
+    if handicap >= 6 {
+        points.extend([(low, middle), (high, middle)]);
+    }
+    if handicap >= 8 {
+        points.extend([(middle, low), (middle, high)]);
+    }
+    // From 5 stones odd counts put the last one on tengen
+    if handicap >= 5 && handicap % 2 == 1 {
+        points.push((middle, middle));
+    }
+    Some(points.into_iter().map(|(x, y)| Move::point(x, y)).collect())
+}
+
+// katago reads settings as the type it expects, an integer setting like `maxVisits` written as 2.0
+// or 1e16 isn't taken as one, so whole floats are written as integers
+fn normalize_numbers(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Number(number) => {
+            if let Some(float) = number.as_f64().filter(|_| number.is_f64()) {
+                if float.fract() == 0.0 && float.abs() < i64::MAX as f64 {
+                    *value = (float as i64).into();
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(normalize_numbers),
+        serde_json::Value::Object(values) => values.values_mut().for_each(normalize_numbers),
+        _ => {}
+    }
+}
+
+// serde_json writes floats like 1e-7 with an exponent, these are written out in full instead
+fn serialize_settings<S: serde::Serializer>(
+    settings: &Option<serde_json::Value>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let Some(settings) = settings else {
+        return serializer.serialize_none();
+    };
+    let mut json = String::new();
+    write_without_exponents(settings, &mut json);
+    serde_json::value::RawValue::from_string(json)
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
+}
+
+fn write_without_exponents(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Number(number) if number.is_f64() => {
+            out.push_str(&number.as_f64().unwrap_or_default().to_string())
+        }
+        serde_json::Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_without_exponents(value, out);
+            }
+            out.push(']');
+        }
+        serde_json::Value::Object(values) => {
+            out.push('{');
+            for (i, (key, value)) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                out.push(':');
+                write_without_exponents(value, out);
+            }
+            out.push('}');
+        }
+        value => out.push_str(&value.to_string()),
+    }
 }
 
 impl KataQuery {
     pub fn builder() -> KataQueryBuilder {
         Default::default()
     }
+
+    // An empty square board with `handicap` black stones on the star points, in the order GTP's
+    // `fixed_handicap` places them, and white to play. Up to 9 stones fit on odd boards from 9x9,
+    // 4 on smaller and even ones.
+    pub fn with_handicap(
+        handicap: u8,
+        board_size: u8,
+        rules: Rules,
+    ) -> Result<KataQueryBuilder, KataQueryBuilderError> {
+        let points = handicap_points(handicap, board_size).ok_or_else(|| {
+            format!(
+                "{handicap} handicap stones can't be placed on a {board_size}x{board_size} board"
+            )
+        })?;
+
+        let mut builder = Self::builder();
+        builder
+            .initial_stones(
+                points
+                    .into_iter()
+                    .map(|point| (Player::Black, point))
+                    .collect::<Vec<_>>(),
+            )
+            .moves(Vec::new())
+            .initial_player(Player::White)
+            .white_handicap_bonus(rules.white_handicap_bonus())
+            .rules(rules)
+            .board_x_size(board_size)
+            .board_y_size(board_size);
+        Ok(builder)
+    }
+
+    pub fn id(&self) -> &QueryId {
+        &self.id
+    }
+
+    pub fn board_size(&self) -> (u8, u8) {
+        (self.board_x_size, self.board_y_size)
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    pub fn komi(&self) -> Option<f32> {
+        self.komi
+    }
+
+    pub fn initial_stones(&self) -> &[(Player, Move)] {
+        self.initial_stones.as_deref().unwrap_or_default()
+    }
+
+    pub fn initial_player(&self) -> Option<Player> {
+        self.initial_player
+    }
+
+    pub fn moves(&self) -> &[(Player, Move)] {
+        &self.moves
+    }
+
+    // None when only the last turn is analyzed
+    pub fn analyze_turns(&self) -> Option<&[u32]> {
+        self.analyze_turns.as_deref()
+    }
+
+    pub fn max_visits(&self) -> Option<u64> {
+        self.max_visits
+    }
+
+    // Lowercase hex sha256 of how the query is analyzed: rules, komi, visits, include flags,
+    // overrides and everything else besides the position, the id and the priority. Equal for
+    // queries whose results can be told apart only by their positions, e.g. to group runs of an
+    // A/B comparison.
+    pub fn settings_fingerprint(&self) -> String {
+        let mut hasher = sha256::Sha256::new();
+        hasher.update(canonical::canonical_settings_json(self).as_bytes());
+        hasher.finish_hex()
+    }
+
+    // Every analyzed turn gets its own final response
+    pub(crate) fn turn_count(&self) -> usize {
+        self.analyze_turns
+            .as_ref()
+            .map_or(1, |turns| turns.len())
+            .max(1)
+    }
+
+    // Whose turn it is after the moves, black unless the moves or `initial_player` say otherwise
+    pub(crate) fn player_to_move(&self) -> Player {
+        self.moves
+            .last()
+            .map(|(last, _)| last.opponent())
+            .or(self.initial_player)
+            .unwrap_or(Player::Black)
+    }
+
+    // Also read from `override_settings`, where deserialized queries have it
+    pub fn max_time(&self) -> Option<Duration> {
+        self.max_time.or_else(|| {
+            let seconds = self.override_settings.as_ref()?.get("maxTime")?.as_f64()?;
+            Duration::try_from_secs_f64(seconds).ok()
+        })
+    }
+
+    pub fn playout_doubling_advantage(&self) -> Option<f32> {
+        self.playout_doubling_advantage.or_else(|| {
+            let advantage = self
+                .override_settings
+                .as_ref()?
+                .get("playoutDoublingAdvantage")?;
+            advantage.as_f64().map(|advantage| advantage as f32)
+        })
+    }
+
+    pub fn anti_mirror(&self) -> Option<bool> {
+        self.anti_mirror.or_else(|| {
+            self.override_settings
+                .as_ref()?
+                .get("antiMirror")?
+                .as_bool()
+        })
+    }
+
+    // Whether the query itself stops the search, through max_visits, max_time or a visit or
+    // playout limit in `override_settings`. Otherwise only the engine's config does.
+    pub fn bounds_search(&self) -> bool {
+        let overridden = |key| {
+            self.override_settings
+                .as_ref()
+                .and_then(|settings| settings.get(key))
+                .is_some_and(|limit| !limit.is_null())
+        };
+        self.max_visits.is_some()
+            || self.max_time().is_some()
+            || overridden("maxVisits")
+            || overridden("maxPlayouts")
+    }
+
+    // Set through `override_settings`, only supported by engines launched with a human model
+    pub fn human_sl_profile(&self) -> Option<&str> {
+        self.override_settings
+            .as_ref()?
+            .get("humanSLProfile")?
+            .as_str()
+    }
 }
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MoveGroup {
     player: Player,
@@ -232,7 +1047,18 @@ pub struct MoveGroup {
     until_depth: u32,
 }
 
-#[derive(Serialize, Debug, Clone)]
+impl MoveGroup {
+    // Applies to `player`'s moves for the first `until_depth` plies of the search
+    pub fn new(player: Player, moves: impl IntoIterator<Item = Move>, until_depth: u32) -> Self {
+        Self {
+            player,
+            moves: moves.into_iter().map(|played| played.to_string()).collect(),
+            until_depth,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WhiteHandicapBonus {
     #[serde(rename = "0")]
     Zero,
@@ -241,7 +1067,7 @@ pub enum WhiteHandicapBonus {
     NMinusOne,
 }
 
-#[derive(Serialize, Debug, Clone, Deserialize)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 pub enum Player {
     #[serde(rename = "B")]
     Black,
@@ -249,7 +1075,7 @@ pub enum Player {
     White,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum Rules {
     TrompTaylor,
@@ -265,6 +1091,22 @@ pub enum Rules {
     AgaButton,
 }
 
+impl Rules {
+    // Points white gets per handicap stone, the defaults katago uses for these shorthands
+    pub fn white_handicap_bonus(&self) -> WhiteHandicapBonus {
+        match self {
+            Rules::Chinese | Rules::ChineseOgs | Rules::ChineseKgs => WhiteHandicapBonus::N,
+            Rules::Aga | Rules::Bga | Rules::AgaButton => WhiteHandicapBonus::NMinusOne,
+            Rules::TrompTaylor
+            | Rules::Japanese
+            | Rules::Korean
+            | Rules::StoneScoring
+            | Rules::NewZealand => WhiteHandicapBonus::Zero,
+        }
+    }
+}
+
+#[cfg(feature = "process")]
 pub fn start(
     cmd: &mut Command,
 ) -> (
@@ -287,14 +1129,171 @@ pub fn start(
     )
 }
 
-struct KataActionEncoder;
+// Newline delimited JSON, as katago reads it. Works with any `AsyncWrite` through `FramedWrite`.
+pub struct KataActionEncoder;
 
 impl Encoder<KataAction> for KataActionEncoder {
     type Error = std::io::Error;
 
     fn encode(&mut self, item: KataAction, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        dst.extend_from_slice(serde_json::to_vec(&item).unwrap().as_slice());
-        dst.extend_from_slice(b"\n");
+        self.encode(&item, dst)
+    }
+}
+
+// Serializes straight into the write buffer, so actions can be sent without cloning them
+impl Encoder<&KataAction> for KataActionEncoder {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, item: &KataAction, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        serde_json::to_writer(dst.writer(), item)?;
+        dst.put_u8(b'\n');
         Ok(())
     }
 }
+
+// Reads responses the way `KataActionEncoder` writes actions, e.g. through `FramedRead` over a
+// socket connected to a remote engine
+#[derive(Default)]
+pub struct KataResponseDecoder {
+    lines: LinesCodec,
+}
+
+impl Decoder for KataResponseDecoder {
+    type Item = KataResponse;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.lines
+            .decode(src)
+            .map_err(lines_error)?
+            .map(|line| KataResponse::from_json(&line).map_err(Into::into))
+            .transpose()
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.lines
+            .decode_eof(src)
+            .map_err(lines_error)?
+            .map(|line| KataResponse::from_json(&line).map_err(Into::into))
+            .transpose()
+    }
+}
+
+fn lines_error(err: LinesCodecError) -> std::io::Error {
+    match err {
+        LinesCodecError::Io(err) => err,
+        err => std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+    }
+}
+
+// For transports which frame messages themselves, e.g. a WebSocket carrying one JSON document per
+// message
+impl KataAction {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+impl KataResponse {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_json(komi: f32, override_settings: serde_json::Value) -> String {
+        let query = KataQueryBuilder::default()
+            .id("a")
+            .moves(Vec::new())
+            .rules(Rules::Japanese)
+            .komi(komi)
+            .board_x_size(19)
+            .board_y_size(19)
+            .override_settings(override_settings)
+            .build()
+            .unwrap();
+        serde_json::to_string(&KataAction::Query { inner: query }).unwrap()
+    }
+
+    #[test]
+    fn komi_without_exponents() {
+        for (komi, written) in [
+            (0.0, r#""komi":0.0"#),
+            (-0.0, r#""komi":0.0"#),
+            (7.5, r#""komi":7.5"#),
+            (-150.0, r#""komi":-150.0"#),
+            (150.0, r#""komi":150.0"#),
+        ] {
+            let json = query_json(komi, serde_json::json!({}));
+            assert!(json.contains(written), "{komi}: {json}");
+        }
+        // Outside the range katago accepts
+        assert!(KataQueryBuilder::default()
+            .id("a")
+            .moves(Vec::new())
+            .rules(Rules::Japanese)
+            .komi(1e6)
+            .board_x_size(19)
+            .board_y_size(19)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn override_settings_without_exponents() {
+        let json = query_json(
+            6.5,
+            serde_json::json!({
+                "maxVisits": 2.0,
+                "maxPlayouts": 1e16,
+                "wideRootNoise": 1e-7,
+                "cpuctExploration": 0.9,
+                "huge": 1e20,
+                "nested": [0.000002, -3.0],
+            }),
+        );
+        let settings = &json[json.find("\"overrideSettings\"").unwrap()..];
+        for written in [
+            r#""maxVisits":2"#,
+            r#""maxPlayouts":10000000000000000"#,
+            r#""wideRootNoise":0.0000001"#,
+            r#""cpuctExploration":0.9"#,
+            r#""huge":100000000000000000000"#,
+            r#""nested":[0.000002,-3]"#,
+        ] {
+            assert!(settings.contains(written), "{written}: {settings}");
+        }
+        let exponent = settings
+            .as_bytes()
+            .windows(2)
+            .any(|pair| pair[0].is_ascii_digit() && pair[1].eq_ignore_ascii_case(&b'e'));
+        assert!(!exponent, "{settings}");
+        // Still JSON, and the same values
+        let value = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(value["overrideSettings"]["wideRootNoise"], 1e-7);
+        assert_eq!(value["overrideSettings"]["maxVisits"], 2);
+        // Settings written as raw JSON still go into cache and dedup keys
+        let query = serde_json::from_value::<KataQuery>(value).unwrap();
+        assert!(canonical::canonical_search_json(&query).contains("wideRootNoise"));
+    }
+
+    #[test]
+    fn typed_settings_without_exponents() {
+        let query = KataQueryBuilder::default()
+            .id("a")
+            .moves(Vec::new())
+            .rules(Rules::Japanese)
+            .board_x_size(19)
+            .board_y_size(19)
+            .max_time(Duration::from_micros(1))
+            .playout_doubling_advantage(0.1)
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&query).unwrap();
+        assert!(json.contains(r#""maxTime":0.000001"#), "{json}");
+        assert!(json.contains(r#""playoutDoublingAdvantage":0.1"#), "{json}");
+    }
+}
@@ -0,0 +1,312 @@
+// Loading game archives, every SGF, GIB and NGF file in a directory and the directories below it.
+// Archives collected from servers and clubs are messy, so files which can't be read or parsed
+// don't stop the walk, they're yielded as errors and the next file is read.
+//
+// Text is decoded by the charset in SGF's `CA` property. UTF-8 and Latin-1 are built in, files
+// without `CA` are read as UTF-8 when they're valid UTF-8 and as Latin-1, SGF's default, when
+// they aren't. Other charsets, like GB2312 or Shift_JIS, need a decoder, e.g. one using
+// `encoding_rs`. Only ASCII properties matter for analysis, so without one names and comments come
+// out garbled but the games themselves don't.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::import::{parse_gib, parse_ngf};
+use crate::sgf::{SgfCollection, SgfError, SgfGame};
+
+type Decoder = Arc<dyn Fn(&[u8], Option<&str>) -> Option<String> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Sgf(SgfError),
+    Import(crate::import::ImportError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "couldn't read game record: {err}"),
+            LoadError::Sgf(err) => err.fmt(f),
+            LoadError::Import(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+// One game of a file, collections yield one per game tree
+#[derive(Debug, Clone)]
+pub struct LoadedGame {
+    pub path: PathBuf,
+    // Of the game tree in the file, 0 unless it's a collection
+    pub index: usize,
+    pub game: SgfGame,
+}
+
+#[derive(Clone)]
+pub struct SgfLoader {
+    root: PathBuf,
+    decoder: Option<Decoder>,
+}
+
+impl fmt::Debug for SgfLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SgfLoader")
+            .field("root", &self.root)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SgfLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            decoder: None,
+        }
+    }
+
+    // Called with the charset of files naming one other than UTF-8 or Latin-1, and for files
+    // without `CA` which aren't UTF-8. Returning None falls back to lossy UTF-8 decoding, or
+    // Latin-1 for files without `CA`.
+    pub fn decoder(
+        mut self,
+        decoder: impl Fn(&[u8], Option<&str>) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.decoder = Some(Arc::new(decoder));
+        self
+    }
+
+    // Walks the directory on the calling thread, in path order so runs are repeatable
+    pub fn load(&self) -> impl Iterator<Item = Result<LoadedGame, (PathBuf, LoadError)>> + '_ {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        walk(&self.root, &mut files, &mut errors);
+        files.sort();
+        errors
+            .into_iter()
+            .map(Err)
+            .chain(files.into_iter().flat_map(|path| self.load_file(path)))
+    }
+
+    // Like `load`, walking on a blocking thread. Files are read as the stream is polled, at most a
+    // few games ahead. Must be called from within a tokio runtime.
+    pub fn stream(self) -> ReceiverStream<Result<LoadedGame, (PathBuf, LoadError)>> {
+        let (tx, rx) = mpsc::channel(16);
+        tokio::task::spawn_blocking(move || {
+            for game in self.load() {
+                if tx.blocking_send(game).is_err() {
+                    return;
+                }
+            }
+        });
+        ReceiverStream::new(rx)
+    }
+
+    fn load_file(&self, path: PathBuf) -> Vec<Result<LoadedGame, (PathBuf, LoadError)>> {
+        let loaded = fs::read(&path)
+            .map_err(LoadError::Io)
+            .and_then(|bytes| self.parse(&path, &bytes));
+        match loaded {
+            Ok(games) => games
+                .into_iter()
+                .enumerate()
+                .map(|(index, game)| {
+                    Ok(LoadedGame {
+                        path: path.clone(),
+                        index,
+                        game,
+                    })
+                })
+                .collect(),
+            Err(err) => vec![Err((path, err))],
+        }
+    }
+
+    fn parse(&self, path: &Path, bytes: &[u8]) -> Result<Vec<SgfGame>, LoadError> {
+        let text = self.decode(bytes);
+        match extension(path).as_deref() {
+            Some("gib") => Ok(vec![parse_gib(&text).map_err(LoadError::Import)?]),
+            Some("ngf") => Ok(vec![parse_ngf(&text).map_err(LoadError::Import)?]),
+            _ => {
+                let collection = SgfCollection::parse(&text).map_err(LoadError::Sgf)?;
+                collection
+                    .games
+                    .iter()
+                    .map(|root| root.game(&[]).map_err(LoadError::Sgf))
+                    .collect()
+            }
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        // A byte order mark wins over whatever `CA` says
+        if let Some(bytes) = bytes.strip_prefix(b"\xef\xbb\xbf") {
+            return String::from_utf8_lossy(bytes).into_owned();
+        }
+        let charset = charset(bytes);
+        match charset.as_deref().map(Charset::of) {
+            Some(Charset::Utf8) => String::from_utf8_lossy(bytes).into_owned(),
+            Some(Charset::Latin1) => latin1(bytes),
+            Some(Charset::Other) => self
+                .custom_decode(bytes, charset.as_deref())
+                .unwrap_or_else(|| String::from_utf8_lossy(bytes).into_owned()),
+            None => match std::str::from_utf8(bytes) {
+                Ok(text) => text.to_owned(),
+                Err(_) => self
+                    .custom_decode(bytes, None)
+                    .unwrap_or_else(|| latin1(bytes)),
+            },
+        }
+    }
+
+    fn custom_decode(&self, bytes: &[u8], charset: Option<&str>) -> Option<String> {
+        self.decoder.as_ref()?(bytes, charset)
+    }
+}
+
+enum Charset {
+    Utf8,
+    Latin1,
+    Other,
+}
+
+impl Charset {
+    // Names are matched loosely, files spell them `UTF-8`, `utf8`, `ISO_8859-1` and so on
+    fn of(name: &str) -> Charset {
+        let name = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "utf8" => Charset::Utf8,
+            "iso88591" | "latin1" | "l1" => Charset::Latin1,
+            _ => Charset::Other,
+        }
+    }
+}
+
+fn latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+// Unreadable directories are reported, symlinked ones aren't followed so loops can't happen
+fn walk(dir: &Path, files: &mut Vec<PathBuf>, errors: &mut Vec<(PathBuf, LoadError)>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            errors.push((dir.to_owned(), LoadError::Io(err)));
+            return;
+        }
+    };
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                errors.push((dir.to_owned(), LoadError::Io(err)));
+                continue;
+            }
+        };
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => walk(&path, files, errors),
+            Ok(_) if matches!(extension(&path).as_deref(), Some("sgf" | "gib" | "ngf")) => {
+                files.push(path)
+            }
+            Ok(_) => {}
+            Err(err) => errors.push((path, LoadError::Io(err))),
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    Some(path.extension()?.to_str()?.to_ascii_lowercase())
+}
+
+// The charset is ASCII, so it can be found before the text is decoded. Only the root node's `CA`
+// counts, looked up property by property so values, e.g. a comment quoting `CA[...]`, are skipped
+// with their `\]` escapes.
+fn charset(bytes: &[u8]) -> Option<String> {
+    let mut pos = bytes.iter().position(|&b| b == b'(')? + 1;
+    let skip_whitespace = |pos: &mut usize| {
+        while bytes.get(*pos).is_some_and(u8::is_ascii_whitespace) {
+            *pos += 1;
+        }
+    };
+    skip_whitespace(&mut pos);
+    if bytes.get(pos) != Some(&b';') {
+        return None;
+    }
+    pos += 1;
+    loop {
+        skip_whitespace(&mut pos);
+        let start = pos;
+        while bytes.get(pos).is_some_and(u8::is_ascii_alphabetic) {
+            pos += 1;
+        }
+        if start == pos {
+            // The next node or tree, the root has none
+            return None;
+        }
+        // FF[3] allowed lowercase letters in names, see `SgfCollection::parse`
+        let is_charset = bytes[start..pos]
+            .iter()
+            .filter(|b| b.is_ascii_uppercase())
+            .eq(b"CA");
+        let mut first = None;
+        loop {
+            skip_whitespace(&mut pos);
+            if bytes.get(pos) != Some(&b'[') {
+                break;
+            }
+            let value = pos + 1;
+            pos = value;
+            loop {
+                match bytes.get(pos)? {
+                    b'\\' => pos += 2,
+                    b']' => break,
+                    _ => pos += 1,
+                }
+            }
+            first.get_or_insert(value..pos);
+            pos += 1;
+        }
+        match first {
+            Some(value) if is_charset => {
+                return Some(String::from_utf8_lossy(&bytes[value]).trim().to_owned())
+            }
+            Some(_) => {}
+            None => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charset_of_the_root_node() {
+        assert_eq!(
+            charset(b"(;GM[1]FF[4]CA[ UTF-8 ]SZ[19];B[aa])").as_deref(),
+            Some("UTF-8")
+        );
+        assert_eq!(
+            charset(b"(;GM[1]\n  AB[aa] [bb]\n  CA[Latin1])").as_deref(),
+            Some("Latin1")
+        );
+        // Only in a comment, with an escaped bracket before it
+        assert_eq!(charset(br"(;GM[1]C[see \] CA[GB2312]])"), None);
+        // Only in a later node
+        assert_eq!(charset(b"(;GM[1];B[aa]CA[GB2312])"), None);
+        assert_eq!(charset(b"(;GM[1]C[unterminated"), None);
+    }
+}
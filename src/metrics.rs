@@ -0,0 +1,287 @@
+// What the client measures about the queries it sends. Latency is from writing a query to its
+// last final result, so time spent waiting for a batch slot in the client isn't included.
+// Throughput counts the visits every result added to its turn's search, interim ones included.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::{KataAction, KataResponse, QueryId};
+
+// Four buckets per doubling from a millisecond up to about nine hours, so percentiles are within
+// about 20% of the real latency
+const BUCKETS_PER_DOUBLING: f64 = 4.0;
+const BUCKETS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        self.buckets[bucket(latency)] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total / self.count as u32)
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    // The latency `percentile` of queries finished within, e.g. 0.99 for the p99. Rounded up to
+    // the bucket's bound.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((percentile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(upper_bound(index).min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+}
+
+fn bucket(latency: Duration) -> usize {
+    let millis = latency.as_secs_f64() * 1000.0;
+    if millis <= 1.0 {
+        return 0;
+    }
+    ((millis.log2() * BUCKETS_PER_DOUBLING).ceil() as usize).min(BUCKETS - 1)
+}
+
+fn upper_bound(bucket: usize) -> Duration {
+    Duration::from_secs_f64(2f64.powf(bucket as f64 / BUCKETS_PER_DOUBLING) / 1000.0)
+}
+
+// A query which took at least the `ClientBuilder::slow_query_log` threshold
+#[derive(Clone, Debug)]
+pub struct SlowQuery {
+    pub id: QueryId,
+    pub latency: Duration,
+    pub turns: usize,
+    // Summed over the final results of every turn
+    pub visits: u64,
+    pub board_x_size: u8,
+    pub board_y_size: u8,
+}
+
+impl fmt::Display for SlowQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "query {} took {:.1}s for {} turns with {} visits on {}x{}",
+            self.id,
+            self.latency.as_secs_f64(),
+            self.turns,
+            self.visits,
+            self.board_x_size,
+            self.board_y_size
+        )
+    }
+}
+
+// Visits searched while the engine had queries in flight. Snapshots only grow, the difference of
+// two is the throughput in between, e.g. to notice GPUs slowing down as they heat up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Throughput {
+    pub visits: u64,
+    pub busy: Duration,
+}
+
+impl Throughput {
+    pub fn visits_per_second(&self) -> Option<f64> {
+        (!self.busy.is_zero()).then(|| self.visits as f64 / self.busy.as_secs_f64())
+    }
+
+    // Of the time since the `earlier` snapshot of the same engine
+    pub fn since(&self, earlier: &Throughput) -> Throughput {
+        Throughput {
+            visits: self.visits.saturating_sub(earlier.visits),
+            busy: self.busy.saturating_sub(earlier.busy),
+        }
+    }
+}
+
+type SlowQueryCallback = Arc<dyn Fn(&SlowQuery) + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct SlowQueryLog {
+    pub(crate) threshold: Duration,
+    pub(crate) callback: SlowQueryCallback,
+}
+
+impl fmt::Debug for SlowQueryLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlowQueryLog")
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+// Kept with a query's routing state until its last final result, the engine counts as busy
+// while any is alive
+pub(crate) struct Timing {
+    metrics: Arc<Metrics>,
+    id: QueryId,
+    sent: Instant,
+    turns: usize,
+    // Summed over final results
+    visits: u64,
+    // The visits of each turn's last result
+    searched: HashMap<u32, u64>,
+    board_x_size: u8,
+    board_y_size: u8,
+}
+
+impl Timing {
+    // None for actions other than queries
+    pub(crate) fn start(action: &KataAction, metrics: &Arc<Metrics>) -> Option<Self> {
+        let KataAction::Query { inner } = action else {
+            return None;
+        };
+        let mut busy = metrics.busy.lock().unwrap();
+        busy.in_flight += 1;
+        busy.since.get_or_insert_with(Instant::now);
+        Some(Self {
+            metrics: metrics.clone(),
+            id: inner.id.clone(),
+            sent: Instant::now(),
+            turns: inner.turn_count(),
+            visits: 0,
+            searched: HashMap::new(),
+            board_x_size: inner.board_x_size,
+            board_y_size: inner.board_y_size,
+        })
+    }
+
+    pub(crate) fn observe(&mut self, response: &KataResponse) {
+        let KataResponse::Result {
+            is_during_search,
+            turn_number,
+            root_info,
+            ..
+        } = response
+        else {
+            return;
+        };
+        if !is_during_search {
+            self.visits += root_info.visits;
+        }
+        let searched = self.searched.entry(*turn_number).or_default();
+        let added = root_info.visits.saturating_sub(*searched);
+        *searched = (*searched).max(root_info.visits);
+        self.metrics.busy.lock().unwrap().visits += added;
+    }
+
+    // Called by the reader, so the slow query callback shouldn't block
+    pub(crate) fn finish(self) {
+        let latency = self.sent.elapsed();
+        self.metrics.latency.lock().unwrap().record(latency);
+        let Some(log) = &self.metrics.slow_query_log else {
+            return;
+        };
+        if latency >= log.threshold {
+            (log.callback)(&SlowQuery {
+                id: self.id.clone(),
+                latency,
+                turns: self.turns,
+                visits: self.visits,
+                board_x_size: self.board_x_size,
+                board_y_size: self.board_y_size,
+            });
+        }
+    }
+}
+
+impl Drop for Timing {
+    fn drop(&mut self) {
+        let mut busy = self.metrics.busy.lock().unwrap();
+        busy.in_flight -= 1;
+        if busy.in_flight == 0 {
+            if let Some(since) = busy.since.take() {
+                busy.total += since.elapsed();
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Busy {
+    in_flight: usize,
+    since: Option<Instant>,
+    total: Duration,
+    visits: u64,
+}
+
+pub(crate) struct Metrics {
+    latency: Mutex<LatencyHistogram>,
+    busy: Mutex<Busy>,
+    slow_query_log: Option<SlowQueryLog>,
+}
+
+impl Metrics {
+    pub(crate) fn new(slow_query_log: Option<SlowQueryLog>) -> Self {
+        Self {
+            latency: Mutex::new(LatencyHistogram::new()),
+            busy: Mutex::new(Busy::default()),
+            slow_query_log,
+        }
+    }
+
+    pub(crate) fn latency(&self) -> LatencyHistogram {
+        self.latency.lock().unwrap().clone()
+    }
+
+    pub(crate) fn throughput(&self) -> Throughput {
+        let busy = self.busy.lock().unwrap();
+        Throughput {
+            visits: busy.visits,
+            busy: busy.total + busy.since.map_or(Duration::ZERO, |since| since.elapsed()),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::sha256::Sha256;
+
+// Where release networks are published, `<name>.bin.gz` is appended
+pub const OFFICIAL_NETWORKS_URL: &str =
+    "https://media.katagotraining.org/uploaded/networks/models/kata1/";
+
+const MODEL_EXTENSIONS: &[&str] = &[".bin.gz", ".bin", ".txt.gz", ".onnx"];
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    ChecksumMismatch { expected: String, actual: String },
+    Download(String),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::Io(err) => write!(f, "model file i/o failed: {err}"),
+            ModelError::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "model checksum mismatch: expected sha256 {expected}, got {actual}"
+            ),
+            ModelError::Download(err) => write!(f, "model download failed: {err}"),
+        }
+    }
+}
+
+impl Error for ModelError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ModelError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ModelError {
+    fn from(err: io::Error) -> Self {
+        ModelError::Io(err)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ModelFile {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+// `$KPAE_MODEL_DIR` if set, otherwise a `kpae/models` directory in the platform's cache directory
+pub fn default_model_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("KPAE_MODEL_DIR") {
+        return Some(dir.into());
+    }
+    let cache = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+    cache.map(|cache| cache.join("kpae").join("models"))
+}
+
+// Networks found directly in `dir`, sorted by name. A missing directory has no models.
+pub fn list_models(dir: impl AsRef<Path>) -> io::Result<Vec<ModelFile>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut models = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+            continue;
+        };
+        if metadata.is_file() && MODEL_EXTENSIONS.iter().any(|ext| name.ends_with(ext)) {
+            models.push(ModelFile {
+                name,
+                path: entry.path(),
+                size: metadata.len(),
+            });
+        }
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+// Lowercase hex digest
+pub fn sha256_file(path: impl AsRef<Path>) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break,
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+    Ok(hasher.finish_hex())
+}
+
+pub fn verify_sha256(path: impl AsRef<Path>, expected: &str) -> Result<(), ModelError> {
+    let actual = sha256_file(path)?;
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        Ok(())
+    } else {
+        Err(ModelError::ChecksumMismatch {
+            expected: expected.trim().to_lowercase(),
+            actual,
+        })
+    }
+}
+
+pub fn official_network_url(name: &str) -> String {
+    format!("{OFFICIAL_NETWORKS_URL}{name}.bin.gz")
+}
+
+// Downloads an official release network, e.g. `kata1-b18c384nbt-s9996604416-d4316597426`, into
+// `dir` unless it's already there. Uses the system `curl`, so no HTTP stack is pulled in.
+#[cfg(feature = "download")]
+pub async fn download_official_network(
+    name: &str,
+    dir: impl AsRef<Path>,
+    sha256: Option<&str>,
+) -> Result<PathBuf, ModelError> {
+    download(
+        &official_network_url(name),
+        &format!("{name}.bin.gz"),
+        dir,
+        sha256,
+    )
+    .await
+}
+
+// Skips the download if `dir/file_name` exists and matches the checksum. The file is downloaded
+// under a temporary name first, so an interrupted download never looks like a model.
+#[cfg(feature = "download")]
+pub async fn download(
+    url: &str,
+    file_name: &str,
+    dir: impl AsRef<Path>,
+    sha256: Option<&str>,
+) -> Result<PathBuf, ModelError> {
+    let dir = dir.as_ref();
+    let path = dir.join(file_name);
+    if path.is_file() {
+        match sha256 {
+            Some(sha256) if verify_sha256(&path, sha256).is_err() => {}
+            _ => return Ok(path),
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    let partial = dir.join(format!("{file_name}.part"));
+    let output = tokio::process::Command::new("curl")
+        .args([
+            "--fail",
+            "--location",
+            "--silent",
+            "--show-error",
+            "--output",
+        ])
+        .arg(&partial)
+        .arg(url)
+        .output()
+        .await
+        .map_err(|err| ModelError::Download(format!("failed to run curl: {err}")))?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&partial);
+        return Err(ModelError::Download(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    if let Some(sha256) = sha256 {
+        if let Err(err) = verify_sha256(&partial, sha256) {
+            let _ = fs::remove_file(&partial);
+            return Err(err);
+        }
+    }
+    fs::rename(&partial, &path)?;
+    Ok(path)
+}
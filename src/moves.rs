@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+// GTP skips `I`, so it can't be confused with `J`
+const COLUMNS: &str = "ABCDEFGHJKLMNOPQRSTUVWXYZ";
+const KANJI_DIGITS: [char; 10] = ['〇', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+// A move in katago's GTP notation, e.g. `Q16` or `pass`. Points are counted from the bottom left
+// corner starting at 0, so `Q16` is `Point { x: 15, y: 15 }`. Only the 25 columns GTP has letters
+// for count as being on the board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Move {
+    Point { x: u8, y: u8 },
+    Pass,
+}
+
+impl Move {
+    pub fn point(x: u8, y: u8) -> Self {
+        Move::Point { x, y }
+    }
+
+    pub fn coordinates(&self) -> Option<(u8, u8)> {
+        match *self {
+            Move::Point { x, y } => Some((x, y)),
+            Move::Pass => None,
+        }
+    }
+
+    // Most GUIs count rows from the top instead
+    pub fn from_top_left(x: u8, y: u8, board_y_size: u8) -> Option<Self> {
+        (y < board_y_size).then(|| Move::point(x, board_y_size - 1 - y))
+    }
+
+    pub fn to_top_left(&self, board_y_size: u8) -> Option<(u8, u8)> {
+        match *self {
+            Move::Point { x, y } if y < board_y_size => Some((x, board_y_size - 1 - y)),
+            _ => None,
+        }
+    }
+
+    pub fn is_on_board(&self, board_x_size: u8, board_y_size: u8) -> bool {
+        match *self {
+            Move::Point { x, y } => x < board_x_size && y < board_y_size && x < COLUMNS.len() as u8,
+            Move::Pass => true,
+        }
+    }
+
+    // katago's ownership and policy arrays go row by row from the top left corner, policy has
+    // the pass after all points
+    pub fn index(&self, board_x_size: u8, board_y_size: u8) -> Option<usize> {
+        match *self {
+            Move::Point { x, y } if x < board_x_size && y < board_y_size => {
+                Some((board_y_size - 1 - y) as usize * board_x_size as usize + x as usize)
+            }
+            _ => None,
+        }
+    }
+
+    // Passes are empty or, on boards up to 19x19, `tt`
+    pub fn from_sgf(sgf: &str, board_x_size: u8, board_y_size: u8) -> Option<Self> {
+        let coordinate = |byte: u8| match byte {
+            b'a'..=b'z' => Some(byte - b'a'),
+            b'A'..=b'Z' => Some(byte - b'A' + 26),
+            _ => None,
+        };
+        match sgf.trim().as_bytes() {
+            [] => Some(Move::Pass),
+            b"tt" if board_x_size <= 19 && board_y_size <= 19 => Some(Move::Pass),
+            [x, y] => {
+                let (x, y) = (coordinate(*x)?, coordinate(*y)?);
+                (x < board_x_size && y < board_y_size).then(|| Move::point(x, board_y_size - 1 - y))
+            }
+            _ => None,
+        }
+    }
+
+    // Japanese notation counts columns from the right in digits and rows from the top in kanji,
+    // so `Q16` is `4-四` on 19x19. Passes are `パス`.
+    pub fn to_japanese(&self, board_x_size: u8, board_y_size: u8) -> String {
+        match *self {
+            Move::Point { x, y } if x < board_x_size && y < board_y_size => {
+                format!("{}-{}", board_x_size - x, kanji(board_y_size - y))
+            }
+            _ => "パス".to_owned(),
+        }
+    }
+
+    // Also takes full width digits and no separator, e.g. `４四`
+    pub fn from_japanese(japanese: &str, board_x_size: u8, board_y_size: u8) -> Option<Self> {
+        let japanese = japanese.trim();
+        if japanese == "パス" {
+            return Some(Move::Pass);
+        }
+        let split = japanese.find(|c: char| KANJI_DIGITS.contains(&c) || c == '十')?;
+        let (column, row) = japanese.split_at(split);
+        let column = column
+            .trim_end_matches(['-', 'ー', '－', ' '])
+            .chars()
+            .map(|c| match c {
+                '０'..='９' => char::from_u32(c as u32 - '０' as u32 + '0' as u32),
+                c => Some(c),
+            })
+            .collect::<Option<String>>()?
+            .parse::<u8>()
+            .ok()?;
+        let row = parse_kanji(row)?;
+        if !(1..=board_x_size).contains(&column) || !(1..=board_y_size).contains(&row) {
+            return None;
+        }
+        Some(Move::point(board_x_size - column, board_y_size - row))
+    }
+
+    // SGF counts rows from the top, so `Q16` is `pd` on 19x19. Passes are empty.
+    pub fn to_sgf(&self, board_y_size: u8) -> String {
+        match *self {
+            Move::Point { x, y } if y < board_y_size => [x, board_y_size - 1 - y]
+                .iter()
+                .map(|index| (b'a' + index) as char)
+                .collect(),
+            _ => String::new(),
+        }
+    }
+}
+
+// Up to 99, `十九` is 19 and `二十` is 20
+fn kanji(n: u8) -> String {
+    let (tens, ones) = ((n / 10) as usize, (n % 10) as usize);
+    let mut kanji = String::new();
+    if tens > 1 {
+        kanji.push(KANJI_DIGITS[tens]);
+    }
+    if tens > 0 {
+        kanji.push('十');
+    }
+    if ones > 0 || tens == 0 {
+        kanji.push(KANJI_DIGITS[ones]);
+    }
+    kanji
+}
+
+fn parse_kanji(kanji: &str) -> Option<u8> {
+    let digit = |c: char| KANJI_DIGITS.iter().position(|digit| *digit == c);
+    let mut chars = kanji.chars().peekable();
+    let mut n = 0;
+    if chars.peek().is_some_and(|c| *c != '十') {
+        n = digit(chars.next()?)?;
+    }
+    if chars.peek() == Some(&'十') {
+        chars.next();
+        n = n.max(1) * 10 + chars.next().map_or(Some(0), digit)?;
+    }
+    (chars.next().is_none() && n > 0).then_some(n as u8)
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Move::Point { x, y } => {
+                let column = COLUMNS
+                    .as_bytes()
+                    .get(x as usize)
+                    .map_or('?', |c| *c as char);
+                write!(f, "{column}{}", y as u16 + 1)
+            }
+            Move::Pass => f.write_str("pass"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParseMoveError(String);
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid move {:?}", self.0)
+    }
+}
+
+impl Error for ParseMoveError {}
+
+impl FromStr for Move {
+    type Err = ParseMoveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("pass") {
+            return Ok(Move::Pass);
+        }
+        let invalid = || ParseMoveError(s.to_owned());
+        let mut chars = s.chars();
+        let x = chars
+            .next()
+            .and_then(|column| COLUMNS.find(column.to_ascii_uppercase()))
+            .ok_or_else(invalid)?;
+        let y = chars
+            .as_str()
+            .parse::<u8>()
+            .ok()
+            .filter(|row| *row >= 1)
+            .ok_or_else(invalid)?;
+        Ok(Move::Point {
+            x: x as u8,
+            y: y - 1,
+        })
+    }
+}
+
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5 columns, 3 rows
+    const X: u8 = 5;
+    const Y: u8 = 3;
+
+    #[test]
+    fn index_on_non_square_boards() {
+        assert_eq!(Move::point(0, 2).index(X, Y), Some(0));
+        assert_eq!(Move::point(4, 2).index(X, Y), Some(4));
+        assert_eq!(Move::point(0, 1).index(X, Y), Some(5));
+        assert_eq!(Move::point(4, 0).index(X, Y), Some(14));
+        assert_eq!(Move::point(5, 0).index(X, Y), None);
+        assert_eq!(Move::point(0, 3).index(X, Y), None);
+        assert_eq!(Move::Pass.index(X, Y), None);
+    }
+
+    #[test]
+    fn top_left_on_non_square_boards() {
+        assert_eq!(Move::from_top_left(4, 0, Y), Some(Move::point(4, 2)));
+        assert_eq!(Move::from_top_left(0, 2, Y), Some(Move::point(0, 0)));
+        assert_eq!(Move::from_top_left(0, 3, Y), None);
+        assert_eq!(Move::point(4, 2).to_top_left(Y), Some((4, 0)));
+        assert_eq!(Move::point(0, 3).to_top_left(Y), None);
+        for index in 0..X as usize * Y as usize {
+            let (x, y) = ((index % X as usize) as u8, (index / X as usize) as u8);
+            let point = Move::from_top_left(x, y, Y).unwrap();
+            assert_eq!(point.index(X, Y), Some(index));
+        }
+    }
+
+    #[test]
+    fn sgf_on_non_square_boards() {
+        assert_eq!(Move::point(4, 2).to_sgf(Y), "ea");
+        assert_eq!(Move::point(0, 0).to_sgf(Y), "ac");
+        assert_eq!(Move::Pass.to_sgf(Y), "");
+        assert_eq!(Move::from_sgf("ea", X, Y), Some(Move::point(4, 2)));
+        assert_eq!(Move::from_sgf("ac", X, Y), Some(Move::point(0, 0)));
+        assert_eq!(Move::from_sgf("fa", X, Y), None);
+        assert_eq!(Move::from_sgf("ad", X, Y), None);
+        assert_eq!(Move::from_sgf("", X, Y), Some(Move::Pass));
+        assert_eq!(Move::from_sgf("tt", X, Y), Some(Move::Pass));
+        // `tt` is a point on boards larger than 19x19
+        assert_eq!(Move::from_sgf("tt", 25, 21), Some(Move::point(19, 1)));
+        for x in 0..X {
+            for y in 0..Y {
+                let point = Move::point(x, y);
+                assert_eq!(Move::from_sgf(&point.to_sgf(Y), X, Y), Some(point));
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+// Minimal MessagePack, for storing queries and results in about half the space JSON takes. Values
+// go through `serde_json::Value`, so they come out exactly like JSON would deserialize them. Most
+// of the size is in ownership and policy arrays, whose numbers are f32s and stored as such.
+
+use std::error::Error;
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug)]
+pub enum MsgpackError {
+    Truncated,
+    // A type MessagePack has but JSON doesn't, e.g. binary or extension types
+    Unsupported(u8),
+    NonStringKey,
+    TrailingBytes,
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for MsgpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MsgpackError::Truncated => f.write_str("MessagePack data ends early"),
+            MsgpackError::Unsupported(marker) => {
+                write!(f, "unsupported MessagePack type 0x{marker:02x}")
+            }
+            MsgpackError::NonStringKey => f.write_str("MessagePack map key isn't a string"),
+            MsgpackError::TrailingBytes => f.write_str("bytes after the MessagePack value"),
+            MsgpackError::Serde(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for MsgpackError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MsgpackError::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub fn to_msgpack<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, MsgpackError> {
+    let value = serde_json::to_value(value).map_err(MsgpackError::Serde)?;
+    let mut out = Vec::new();
+    encode(&value, &mut out);
+    Ok(out)
+}
+
+pub fn from_msgpack<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, MsgpackError> {
+    let mut input = bytes;
+    let value = decode(&mut input)?;
+    if !input.is_empty() {
+        return Err(MsgpackError::TrailingBytes);
+    }
+    serde_json::from_value(value).map_err(MsgpackError::Serde)
+}
+
+fn encode(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(false) => out.push(0xc2),
+        Value::Bool(true) => out.push(0xc3),
+        Value::Number(number) => encode_number(number, out),
+        Value::String(string) => {
+            let len = string.len();
+            match len {
+                0..=31 => out.push(0xa0 | len as u8),
+                32..=0xff => out.extend([0xd9, len as u8]),
+                0x100..=0xffff => {
+                    out.push(0xda);
+                    out.extend((len as u16).to_be_bytes());
+                }
+                _ => {
+                    out.push(0xdb);
+                    out.extend((len as u32).to_be_bytes());
+                }
+            }
+            out.extend(string.as_bytes());
+        }
+        Value::Array(values) => {
+            encode_len(values.len(), [0x90, 0xdc, 0xdd], out);
+            for value in values {
+                encode(value, out);
+            }
+        }
+        Value::Object(entries) => {
+            encode_len(entries.len(), [0x80, 0xde, 0xdf], out);
+            for (key, value) in entries {
+                encode(&Value::String(key.clone()), out);
+                encode(value, out);
+            }
+        }
+    }
+}
+
+// Arrays and maps up to 15 entries have their length in the marker
+fn encode_len(len: usize, [fixed, short, long]: [u8; 3], out: &mut Vec<u8>) {
+    match len {
+        0..=15 => out.push(fixed | len as u8),
+        16..=0xffff => {
+            out.push(short);
+            out.extend((len as u16).to_be_bytes());
+        }
+        _ => {
+            out.push(long);
+            out.extend((len as u32).to_be_bytes());
+        }
+    }
+}
+
+fn encode_number(number: &Number, out: &mut Vec<u8>) {
+    if let Some(n) = number.as_u64() {
+        match n {
+            0..=0x7f => out.push(n as u8),
+            0x80..=0xff => out.extend([0xcc, n as u8]),
+            0x100..=0xffff => {
+                out.push(0xcd);
+                out.extend((n as u16).to_be_bytes());
+            }
+            0x1_0000..=0xffff_ffff => {
+                out.push(0xce);
+                out.extend((n as u32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xcf);
+                out.extend(n.to_be_bytes());
+            }
+        }
+    } else if let Some(n) = number.as_i64() {
+        // Only negative numbers are left
+        match n {
+            -32..=-1 => out.push(n as i8 as u8),
+            -0x80..=-33 => out.extend([0xd0, n as i8 as u8]),
+            -0x8000..=-0x81 => {
+                out.push(0xd1);
+                out.extend((n as i16).to_be_bytes());
+            }
+            -0x8000_0000..=-0x8001 => {
+                out.push(0xd2);
+                out.extend((n as i32).to_be_bytes());
+            }
+            _ => {
+                out.push(0xd3);
+                out.extend(n.to_be_bytes());
+            }
+        }
+    } else {
+        let n = number.as_f64().unwrap_or_default();
+        // f32s widened to f64 by serde narrow back losslessly
+        if (n as f32) as f64 == n {
+            out.push(0xca);
+            out.extend((n as f32).to_be_bytes());
+        } else {
+            out.push(0xcb);
+            out.extend(n.to_be_bytes());
+        }
+    }
+}
+
+fn decode(input: &mut &[u8]) -> Result<Value, MsgpackError> {
+    let marker = take::<1>(input)?[0];
+    Ok(match marker {
+        0x00..=0x7f => Value::from(marker),
+        0x80..=0x8f => decode_map((marker & 0x0f) as usize, input)?,
+        0x90..=0x9f => decode_array((marker & 0x0f) as usize, input)?,
+        0xa0..=0xbf => decode_str((marker & 0x1f) as usize, input)?,
+        0xc0 => Value::Null,
+        0xc2 => Value::Bool(false),
+        0xc3 => Value::Bool(true),
+        0xca => Value::from(f32::from_be_bytes(take(input)?) as f64),
+        0xcb => Value::from(f64::from_be_bytes(take(input)?)),
+        0xcc => Value::from(take::<1>(input)?[0]),
+        0xcd => Value::from(u16::from_be_bytes(take(input)?)),
+        0xce => Value::from(u32::from_be_bytes(take(input)?)),
+        0xcf => Value::from(u64::from_be_bytes(take(input)?)),
+        0xd0 => Value::from(i8::from_be_bytes(take(input)?)),
+        0xd1 => Value::from(i16::from_be_bytes(take(input)?)),
+        0xd2 => Value::from(i32::from_be_bytes(take(input)?)),
+        0xd3 => Value::from(i64::from_be_bytes(take(input)?)),
+        0xd9 => {
+            let len = take::<1>(input)?[0] as usize;
+            decode_str(len, input)?
+        }
+        0xda => {
+            let len = u16::from_be_bytes(take(input)?) as usize;
+            decode_str(len, input)?
+        }
+        0xdb => {
+            let len = u32::from_be_bytes(take(input)?) as usize;
+            decode_str(len, input)?
+        }
+        0xdc => {
+            let len = u16::from_be_bytes(take(input)?) as usize;
+            decode_array(len, input)?
+        }
+        0xdd => {
+            let len = u32::from_be_bytes(take(input)?) as usize;
+            decode_array(len, input)?
+        }
+        0xde => {
+            let len = u16::from_be_bytes(take(input)?) as usize;
+            decode_map(len, input)?
+        }
+        0xdf => {
+            let len = u32::from_be_bytes(take(input)?) as usize;
+            decode_map(len, input)?
+        }
+        0xe0..=0xff => Value::from(marker as i8),
+        marker => return Err(MsgpackError::Unsupported(marker)),
+    })
+}
+
+fn decode_str(len: usize, input: &mut &[u8]) -> Result<Value, MsgpackError> {
+    if input.len() < len {
+        return Err(MsgpackError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    let string = std::str::from_utf8(bytes)
+        .map_err(|err| MsgpackError::Serde(serde::de::Error::custom(err)))?;
+    Ok(Value::String(string.to_owned()))
+}
+
+// Lengths come from the data, so nothing is reserved upfront for them
+fn decode_array(len: usize, input: &mut &[u8]) -> Result<Value, MsgpackError> {
+    let mut values = Vec::new();
+    for _ in 0..len {
+        values.push(decode(input)?);
+    }
+    Ok(Value::Array(values))
+}
+
+fn decode_map(len: usize, input: &mut &[u8]) -> Result<Value, MsgpackError> {
+    let mut entries = Map::new();
+    for _ in 0..len {
+        let Value::String(key) = decode(input)? else {
+            return Err(MsgpackError::NonStringKey);
+        };
+        entries.insert(key, decode(input)?);
+    }
+    Ok(Value::Object(entries))
+}
+
+fn take<const N: usize>(input: &mut &[u8]) -> Result<[u8; N], MsgpackError> {
+    if input.len() < N {
+        return Err(MsgpackError::Truncated);
+    }
+    let (bytes, rest) = input.split_at(N);
+    *input = rest;
+    Ok(bytes.try_into().unwrap())
+}
@@ -0,0 +1,559 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::task::AtomicWaker;
+use futures_util::{SinkExt, StreamExt};
+use tokio::sync::mpsc;
+use tokio::time::{Instant, Sleep};
+use tokio_util::sync::PollSender;
+
+use crate::{KataAction, KataResponse, QueryId};
+
+const PREFIX_SEPARATOR: char = '/';
+
+// Outgoing actions are buffered per connection up to this many before `poll_ready` waits
+const CONNECTION_BUFFER: usize = 64;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+type Routes = Arc<Mutex<HashMap<String, Route>>>;
+
+struct Route {
+    tx: mpsc::UnboundedSender<KataResponse>,
+    usage: Arc<Mutex<Usage>>,
+}
+
+// Limits for a single connection. Queries over the concurrency or rate limit wait in the
+// connection's queue, queries asking for more visits than allowed are rejected like katago rejects
+// invalid queries, and queries without a visit limit get the allowed maximum.
+#[derive(Clone, Debug, Default)]
+pub struct MuxQuota {
+    max_concurrent: Option<usize>,
+    max_visits: Option<u64>,
+    max_queries_per_minute: Option<usize>,
+}
+
+impl MuxQuota {
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = Some(max_concurrent.max(1));
+        self
+    }
+
+    pub fn max_visits(mut self, max_visits: u64) -> Self {
+        self.max_visits = Some(max_visits);
+        self
+    }
+
+    pub fn max_queries_per_minute(mut self, max_queries_per_minute: usize) -> Self {
+        self.max_queries_per_minute = Some(max_queries_per_minute.max(1));
+        self
+    }
+}
+
+#[derive(Default)]
+struct Usage {
+    // Final responses still expected, by prefixed query id
+    in_flight: HashMap<QueryId, usize>,
+    // When the queries of the last `RATE_WINDOW` were written
+    written: VecDeque<Instant>,
+}
+
+// A connection's queue as seen by the scheduler
+struct Lane {
+    actions: mpsc::Receiver<KataAction>,
+    // Taken from the queue but held back by the quota, in order. Other actions pass them, so a
+    // connection at its limit can still terminate its queries to make room.
+    held: VecDeque<KataAction>,
+    quota: MuxQuota,
+    usage: Arc<Mutex<Usage>>,
+}
+
+enum Admission {
+    Now,
+    // When the rate limit allows the next query, None if waiting on in flight queries
+    Later(Option<Instant>),
+}
+
+impl Lane {
+    // The next action which may be written now, reading past held queries for other actions
+    fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+        now: Instant,
+        retry_at: &mut Option<Instant>,
+    ) -> Poll<Option<KataAction>> {
+        loop {
+            // Queries the quota allows keep their place in line
+            if !self.held.is_empty() {
+                match self.admit(now) {
+                    Admission::Now => return Poll::Ready(self.held.pop_front()),
+                    Admission::Later(Some(at)) => {
+                        *retry_at = Some(retry_at.map_or(at, |retry_at| retry_at.min(at)))
+                    }
+                    Admission::Later(None) => {}
+                }
+            }
+            if self.held.len() >= CONNECTION_BUFFER {
+                return Poll::Pending;
+            }
+            match self.actions.poll_recv(cx) {
+                Poll::Ready(Some(action @ KataAction::Query { .. })) => self.held.push_back(action),
+                Poll::Ready(Some(action)) => {
+                    // A held query terminated whole never reaches the engine, katago acks the
+                    // terminate all the same
+                    if let KataAction::Terminate {
+                        terminate_id,
+                        turn_numbers: None,
+                        ..
+                    } = &action
+                    {
+                        self.held.retain(|held| held.id() != terminate_id);
+                    }
+                    return Poll::Ready(Some(action));
+                }
+                Poll::Ready(None) if self.held.is_empty() => return Poll::Ready(None),
+                Poll::Ready(None) | Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn admit(&mut self, now: Instant) -> Admission {
+        let Some(KataAction::Query { inner }) = self.held.front() else {
+            return Admission::Now;
+        };
+        let mut usage = self.usage.lock().unwrap();
+        if let Some(max_concurrent) = self.quota.max_concurrent {
+            if usage.in_flight.len() >= max_concurrent {
+                return Admission::Later(None);
+            }
+        }
+        if let Some(max_queries_per_minute) = self.quota.max_queries_per_minute {
+            while usage
+                .written
+                .front()
+                .is_some_and(|written| *written + RATE_WINDOW <= now)
+            {
+                usage.written.pop_front();
+            }
+            if usage.written.len() >= max_queries_per_minute {
+                return Admission::Later(Some(usage.written[0] + RATE_WINDOW));
+            }
+            usage.written.push_back(now);
+        }
+        usage
+            .in_flight
+            .insert(inner.id().clone(), inner.turn_count());
+        Admission::Now
+    }
+}
+
+// Picks the next action to write, taking turns between connections so a connection with a long
+// queue can't starve the others
+struct Scheduler {
+    new_lanes: mpsc::UnboundedReceiver<Lane>,
+    lanes: Vec<Lane>,
+    next_lane: usize,
+    wake: Arc<AtomicWaker>,
+    rate_limit: Pin<Box<Sleep>>,
+}
+
+impl Scheduler {
+    fn poll_next_action(&mut self, cx: &mut Context<'_>) -> Poll<Option<KataAction>> {
+        self.wake.register(cx.waker());
+        loop {
+            let mut new_lanes_closed = false;
+            loop {
+                match self.new_lanes.poll_recv(cx) {
+                    Poll::Ready(Some(lane)) => self.lanes.push(lane),
+                    Poll::Ready(None) => {
+                        new_lanes_closed = true;
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+
+            let now = Instant::now();
+            let mut retry_at: Option<Instant> = None;
+            let mut closed = Vec::new();
+            for k in 0..self.lanes.len() {
+                let i = (self.next_lane + k) % self.lanes.len();
+                match self.lanes[i].poll_next(cx, now, &mut retry_at) {
+                    Poll::Ready(Some(action)) => {
+                        self.next_lane = i + 1;
+                        return Poll::Ready(Some(action));
+                    }
+                    Poll::Ready(None) => closed.push(i),
+                    Poll::Pending => {}
+                }
+            }
+            closed.sort_unstable();
+            for i in closed.into_iter().rev() {
+                self.lanes.swap_remove(i);
+            }
+            if new_lanes_closed && self.lanes.is_empty() {
+                return Poll::Ready(None);
+            }
+
+            let Some(retry_at) = retry_at else {
+                return Poll::Pending;
+            };
+            self.rate_limit.as_mut().reset(retry_at);
+            if self.rate_limit.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MuxError {
+    InvalidPrefix(String),
+    PrefixInUse(String),
+    Unauthorized,
+    Closed,
+}
+
+impl fmt::Display for MuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MuxError::InvalidPrefix(prefix) => write!(
+                f,
+                "connection prefix {prefix:?} must be non-empty and must not contain {PREFIX_SEPARATOR:?}"
+            ),
+            MuxError::PrefixInUse(prefix) => {
+                write!(f, "connection prefix {prefix:?} is already in use")
+            }
+            MuxError::Unauthorized => f.write_str("invalid API key"),
+            MuxError::Closed => f.write_str("engine connection is closed"),
+        }
+    }
+}
+
+impl Error for MuxError {}
+
+// Shares one engine between independent components. Every connection gets its own prefix which is
+// prepended to the ids of outgoing actions (`<prefix>/<id>`) and stripped from incoming responses,
+// so each connection only ever sees its own traffic, with the ids it used.
+pub struct MuxClient {
+    lanes: mpsc::UnboundedSender<Lane>,
+    routes: Routes,
+}
+
+impl MuxClient {
+    pub fn new<Si, St>(sink: Si, stream: St) -> Self
+    where
+        Si: Sink<KataAction> + Send + 'static,
+        St: Stream<Item = KataResponse> + Send + 'static,
+    {
+        let (lanes, new_lanes) = mpsc::unbounded_channel();
+        let routes = Routes::default();
+        let wake = Arc::new(AtomicWaker::new());
+
+        let mut scheduler = Scheduler {
+            new_lanes,
+            lanes: Vec::new(),
+            next_lane: 0,
+            wake: wake.clone(),
+            rate_limit: Box::pin(tokio::time::sleep(Duration::ZERO)),
+        };
+        tokio::spawn(async move {
+            let mut sink = pin!(sink);
+            while let Some(action) =
+                futures_util::future::poll_fn(|cx| scheduler.poll_next_action(cx)).await
+            {
+                if sink.send(action).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let router_routes = routes.clone();
+        let router_wake = wake;
+        tokio::spawn(async move {
+            let mut stream = pin!(stream);
+            while let Some(response) = stream.next().await {
+                route(&router_routes, &router_wake, response);
+            }
+            // Dropping the senders ends every connection's stream
+            router_routes.lock().unwrap().clear();
+        });
+
+        Self { lanes, routes }
+    }
+
+    pub fn connect(&self, prefix: impl Into<String>) -> Result<MuxConnection, MuxError> {
+        self.connect_with_quota(prefix, MuxQuota::default())
+    }
+
+    pub fn connect_with_quota(
+        &self,
+        prefix: impl Into<String>,
+        quota: MuxQuota,
+    ) -> Result<MuxConnection, MuxError> {
+        let prefix = prefix.into();
+        if prefix.is_empty() || prefix.contains(PREFIX_SEPARATOR) {
+            return Err(MuxError::InvalidPrefix(prefix));
+        }
+        if self.lanes.is_closed() {
+            return Err(MuxError::Closed);
+        }
+
+        let (tx, responses) = mpsc::unbounded_channel();
+        let (actions, rx) = mpsc::channel(CONNECTION_BUFFER);
+        let usage = Arc::new(Mutex::new(Usage::default()));
+        let mut routes = self.routes.lock().unwrap();
+        if routes.contains_key(&prefix) {
+            return Err(MuxError::PrefixInUse(prefix));
+        }
+        let lane = Lane {
+            actions: rx,
+            held: VecDeque::new(),
+            quota: quota.clone(),
+            usage: usage.clone(),
+        };
+        if self.lanes.send(lane).is_err() {
+            return Err(MuxError::Closed);
+        }
+        routes.insert(prefix.clone(), Route { tx, usage });
+
+        Ok(MuxConnection {
+            prefix,
+            quota,
+            actions: PollSender::new(actions),
+            responses,
+            routes: self.routes.clone(),
+        })
+    }
+}
+
+fn route(routes: &Routes, wake: &AtomicWaker, mut response: KataResponse) {
+    let Some(id) = response.id().cloned() else {
+        return;
+    };
+    let Some((prefix, _)) = id.as_str().split_once(PREFIX_SEPARATOR) else {
+        return;
+    };
+    let prefix = prefix.to_owned();
+    if let Some(route) = routes.lock().unwrap().get(&prefix) {
+        if release(&mut route.usage.lock().unwrap(), &id, &response) {
+            wake.wake();
+        }
+    }
+    let strip = format!("{prefix}{PREFIX_SEPARATOR}");
+    response.for_each_id_mut(|id| {
+        if let Some(stripped) = id.as_str().strip_prefix(&strip) {
+            *id = QueryId::new(stripped);
+        }
+    });
+
+    let mut routes = routes.lock().unwrap();
+    if let Some(route) = routes.get(&prefix) {
+        if route.tx.send(response).is_err() {
+            routes.remove(&prefix);
+        }
+    }
+}
+
+// Whether the response ended a query counted against the quota. A terminated query ends with the
+// ack, katago reports nothing for the turns it hadn't started.
+fn release(usage: &mut Usage, id: &QueryId, response: &KataResponse) -> bool {
+    if let KataResponse::TerminateAck { terminate_id, .. } = response {
+        return usage.in_flight.remove(terminate_id).is_some();
+    }
+    let Some(remaining) = usage.in_flight.get_mut(id) else {
+        return false;
+    };
+    match response {
+        KataResponse::Result { .. } | KataResponse::Resultless { .. }
+            if !response.is_during_search() =>
+        {
+            *remaining -= 1
+        }
+        KataResponse::Error { .. } => *remaining = 0,
+        _ => {}
+    }
+    if *remaining > 0 {
+        return false;
+    }
+    usage.in_flight.remove(id);
+    true
+}
+
+// Behaves like the sink and stream pair returned by `start`
+pub struct MuxConnection {
+    prefix: String,
+    quota: MuxQuota,
+    actions: PollSender<KataAction>,
+    responses: mpsc::UnboundedReceiver<KataResponse>,
+    routes: Routes,
+}
+
+impl MuxConnection {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    // Rejects queries over the visit limit the way katago would, so the error reaches whoever
+    // reads this connection's responses instead of failing the sink
+    fn reject(&self, id: QueryId, error: String, field: &str) {
+        if let Some(route) = self.routes.lock().unwrap().get(&self.prefix) {
+            let _ = route.tx.send(KataResponse::Error {
+                id: Some(id),
+                error,
+                field: Some(field.to_owned()),
+            });
+        }
+    }
+}
+
+impl Drop for MuxConnection {
+    fn drop(&mut self) {
+        self.routes.lock().unwrap().remove(&self.prefix);
+    }
+}
+
+impl Sink<KataAction> for MuxConnection {
+    type Error = MuxError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.actions
+            .poll_ready_unpin(cx)
+            .map_err(|_| MuxError::Closed)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, mut item: KataAction) -> Result<(), Self::Error> {
+        if let (KataAction::Query { inner }, Some(max_visits)) = (&mut item, self.quota.max_visits)
+        {
+            match inner.max_visits {
+                Some(visits) if visits > max_visits => {
+                    let error = format!("maxVisits {visits} exceeds the allowed {max_visits}");
+                    self.reject(inner.id().clone(), error, "maxVisits");
+                    return Ok(());
+                }
+                Some(_) => {}
+                None => inner.max_visits = Some(max_visits),
+            }
+        }
+        let prefix = &self.prefix;
+        item.for_each_id_mut(|id| *id = QueryId::new(format!("{prefix}{PREFIX_SEPARATOR}{id}")));
+        self.actions
+            .start_send_unpin(item)
+            .map_err(|_| MuxError::Closed)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.actions
+            .poll_flush_unpin(cx)
+            .map_err(|_| MuxError::Closed)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.actions
+            .poll_close_unpin(cx)
+            .map_err(|_| MuxError::Closed)
+    }
+}
+
+impl Stream for MuxConnection {
+    type Item = KataResponse;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.responses.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::sync::mpsc;
+    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_util::sync::PollSender;
+
+    use super::*;
+    use crate::{ActionTerminate, KataQuery, Rules};
+
+    fn query(id: &str) -> KataAction {
+        let inner = KataQuery::builder()
+            .id(id)
+            .moves(Vec::new())
+            .rules(Rules::Japanese)
+            .board_x_size(19)
+            .board_y_size(19)
+            .build()
+            .unwrap();
+        KataAction::Query { inner }
+    }
+
+    async fn written(engine: &mut mpsc::Receiver<KataAction>) -> Option<KataAction> {
+        tokio::time::timeout(Duration::from_millis(100), engine.recv())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[test]
+    fn capped_connection_terminates_in_flight_query() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (sink, mut engine) = mpsc::channel(16);
+            let (responses, stream) = mpsc::unbounded_channel();
+            let mux = MuxClient::new(PollSender::new(sink), UnboundedReceiverStream::new(stream));
+            let mut connection = mux
+                .connect_with_quota("a", MuxQuota::default().max_concurrent(1))
+                .unwrap();
+
+            connection.send(query("first")).await.unwrap();
+            connection.send(query("second")).await.unwrap();
+            connection
+                .send(KataAction::Terminate {
+                    id: QueryId::new("stop"),
+                    action: ActionTerminate::ActionTerminate,
+                    terminate_id: QueryId::new("first"),
+                    turn_numbers: None,
+                })
+                .await
+                .unwrap();
+
+            // The terminate passes the held query
+            assert_eq!(written(&mut engine).await.unwrap().id().as_str(), "a/first");
+            let KataAction::Terminate { terminate_id, .. } = written(&mut engine).await.unwrap()
+            else {
+                panic!("expected the terminate");
+            };
+            assert_eq!(terminate_id.as_str(), "a/first");
+            assert!(written(&mut engine).await.is_none());
+
+            // The ack frees the slot
+            responses
+                .send(KataResponse::TerminateAck {
+                    id: QueryId::new("a/stop"),
+                    action: ActionTerminate::ActionTerminate,
+                    turn_number: None,
+                    terminate_id: QueryId::new("a/first"),
+                })
+                .unwrap();
+            assert_eq!(
+                written(&mut engine).await.unwrap().id().as_str(),
+                "a/second"
+            );
+            assert!(matches!(
+                connection.next().await,
+                Some(KataResponse::TerminateAck { .. })
+            ));
+        });
+    }
+}
@@ -0,0 +1,108 @@
+// The names katago uses for these values, e.g. `chinese`, `B` or `N-1`, so they can be read from
+// command lines, config files and SGF properties the same way they're written in queries
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Player, Rules, WhiteHandicapBonus};
+
+const RULES: [(Rules, &str); 11] = [
+    (Rules::TrompTaylor, "tromp-taylor"),
+    (Rules::Chinese, "chinese"),
+    (Rules::ChineseOgs, "chinese-ogs"),
+    (Rules::ChineseKgs, "chinese-kgs"),
+    (Rules::Japanese, "japanese"),
+    (Rules::Korean, "korean"),
+    (Rules::StoneScoring, "stone-scoring"),
+    (Rules::Aga, "aga"),
+    (Rules::Bga, "bga"),
+    (Rules::NewZealand, "new-zealand"),
+    (Rules::AgaButton, "aga-button"),
+];
+
+#[derive(Debug, Clone)]
+pub struct ParseNameError {
+    expected: &'static str,
+    name: String,
+}
+
+impl fmt::Display for ParseNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} isn't a valid {}", self.name, self.expected)
+    }
+}
+
+impl Error for ParseNameError {}
+
+fn invalid(expected: &'static str, name: &str) -> ParseNameError {
+    ParseNameError {
+        expected,
+        name: name.to_owned(),
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = RULES.iter().find(|(rules, _)| rules == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+// katago itself ignores case and accepts underscores
+impl FromStr for Rules {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_ascii_lowercase().replace('_', "-");
+        RULES
+            .iter()
+            .find(|(_, rules)| *rules == name)
+            .map(|(rules, _)| *rules)
+            .ok_or_else(|| invalid("ruleset", s))
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Player::Black => "B",
+            Player::White => "W",
+        })
+    }
+}
+
+impl FromStr for Player {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "b" | "black" => Ok(Player::Black),
+            "w" | "white" => Ok(Player::White),
+            _ => Err(invalid("player", s)),
+        }
+    }
+}
+
+impl fmt::Display for WhiteHandicapBonus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WhiteHandicapBonus::Zero => "0",
+            WhiteHandicapBonus::N => "N",
+            WhiteHandicapBonus::NMinusOne => "N-1",
+        })
+    }
+}
+
+impl FromStr for WhiteHandicapBonus {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "0" => Ok(WhiteHandicapBonus::Zero),
+            "N" | "n" => Ok(WhiteHandicapBonus::N),
+            "N-1" | "n-1" => Ok(WhiteHandicapBonus::NMinusOne),
+            _ => Err(invalid("white handicap bonus", s)),
+        }
+    }
+}
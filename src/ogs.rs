@@ -0,0 +1,218 @@
+// Export of analysis results in the JSON format online-go.com uses for AI reviews. Win rates and
+// scores are always from black's point of view there, moves are SGF coordinates, e.g. `dd`, with
+// an empty string for passes.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::{
+    Annotation, Annotator, KataResponse, Loss, Move, MoveInfo, Player, ReportAnalysisWinratesAs,
+    RootInfo,
+};
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReviewType {
+    Fast,
+    Full,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReviewBranch {
+    pub moves: String,
+    pub visits: u64,
+    pub win_rate: f32,
+    pub score: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ReviewMove {
+    pub move_number: u32,
+    // The move played from this position, empty for the last position of the game
+    #[serde(rename = "move")]
+    pub played: String,
+    pub win_rate: f32,
+    pub score: f32,
+    pub branches: Vec<ReviewBranch>,
+    // Set by `Review::annotate` for the played move
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation: Option<Annotation>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Review {
+    #[serde(rename = "type")]
+    pub review_type: ReviewType,
+    pub engine: String,
+    pub engine_version: String,
+    pub network: String,
+    // Keyed by move number
+    pub moves: BTreeMap<u32, ReviewMove>,
+    // Indexed by move number, positions without a result repeat the previous value
+    pub win_rates: Vec<f32>,
+    pub scores: Vec<f32>,
+}
+
+impl Review {
+    // `moves` are the game's moves as sent in the query, results are the final results of any of
+    // its turns. Results without a known perspective are taken as reported for black, katago's
+    // default. At most `max_branches` variations are kept per position.
+    pub fn from_results<'a>(
+        results: impl IntoIterator<Item = &'a KataResponse>,
+        moves: &[(Player, Move)],
+        board_y_size: u8,
+        max_branches: usize,
+    ) -> Self {
+        let mut review_moves = BTreeMap::new();
+        for result in results {
+            let KataResponse::Result {
+                turn_number,
+                move_infos,
+                root_info,
+                perspective,
+                ..
+            } = result
+            else {
+                continue;
+            };
+            let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+            let (win_rate, score) = root_for_black(root_info, reported_as);
+            let mut move_infos = move_infos.iter().collect::<Vec<_>>();
+            move_infos.sort_by_key(|move_info| move_info.order);
+            let branches = move_infos
+                .into_iter()
+                .take(max_branches)
+                .map(|move_info| branch(move_info, root_info, reported_as, board_y_size))
+                .collect();
+            let played = moves
+                .get(*turn_number as usize)
+                .map(|(_, played)| played.to_sgf(board_y_size))
+                .unwrap_or_default();
+            review_moves.insert(
+                *turn_number,
+                ReviewMove {
+                    move_number: *turn_number,
+                    played,
+                    win_rate,
+                    score,
+                    branches,
+                    annotation: None,
+                },
+            );
+        }
+
+        let last = review_moves.keys().next_back().copied();
+        let mut win_rates = Vec::new();
+        let mut scores = Vec::new();
+        let (mut win_rate, mut score) = (0.5, 0.0);
+        for move_number in last.map_or(0..0, |last| 0..last + 1) {
+            if let Some(review_move) = review_moves.get(&move_number) {
+                win_rate = review_move.win_rate;
+                score = review_move.score;
+            }
+            win_rates.push(win_rate);
+            scores.push(score);
+        }
+        let review_type = if review_moves.len() == moves.len() + 1 {
+            ReviewType::Full
+        } else {
+            ReviewType::Fast
+        };
+
+        Self {
+            review_type,
+            engine: "katago".to_owned(),
+            engine_version: String::new(),
+            network: String::new(),
+            moves: review_moves,
+            win_rates,
+            scores,
+        }
+    }
+
+    pub fn engine_version(mut self, engine_version: impl Into<String>) -> Self {
+        self.engine_version = engine_version.into();
+        self
+    }
+
+    pub fn network(mut self, network: impl Into<String>) -> Self {
+        self.network = network.into();
+        self
+    }
+
+    // What the move played from `move_number` by `player` lost, None unless both the position
+    // before and after it have results
+    pub fn loss(&self, move_number: u32, player: Player) -> Option<Loss> {
+        let before = self.moves.get(&move_number)?;
+        let after = self.moves.get(&(move_number + 1))?;
+        let sign = match player {
+            Player::Black => 1.0,
+            Player::White => -1.0,
+        };
+        Some(Loss::new(
+            sign * (before.score - after.score),
+            sign * (before.win_rate - after.win_rate),
+        ))
+    }
+
+    // Marks each played move by comparing the positions before and after it, moves without
+    // results for both are left unmarked
+    pub fn annotate(mut self, moves: &[(Player, Move)], annotator: &Annotator) -> Self {
+        for (move_number, (player, _)) in moves.iter().enumerate() {
+            let move_number = move_number as u32;
+            let Some(loss) = self.loss(move_number, *player) else {
+                continue;
+            };
+            if let Some(before) = self.moves.get_mut(&move_number) {
+                before.annotation = annotator.annotate(loss.points, loss.winrate);
+            }
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn root_for_black(root_info: &RootInfo, reported_as: ReportAnalysisWinratesAs) -> (f32, f32) {
+    (
+        root_info
+            .winrate_for(Player::Black, reported_as)
+            .unwrap_or(root_info.winrate),
+        root_info
+            .score_lead_for(Player::Black, reported_as)
+            .unwrap_or(root_info.score_lead),
+    )
+}
+
+fn branch(
+    move_info: &MoveInfo,
+    root_info: &RootInfo,
+    reported_as: ReportAnalysisWinratesAs,
+    board_y_size: u8,
+) -> ReviewBranch {
+    let current_player = root_info.current_player;
+    ReviewBranch {
+        moves: move_info
+            .pv
+            .iter()
+            .map(|pv_move| sgf_coordinate(pv_move, board_y_size))
+            .collect(),
+        visits: move_info.visits,
+        win_rate: move_info
+            .winrate_for(Player::Black, reported_as, current_player)
+            .unwrap_or(move_info.winrate),
+        score: move_info
+            .score_lead_for(Player::Black, reported_as, current_player)
+            .unwrap_or(move_info.score_lead),
+    }
+}
+
+// Anything which isn't a board point, e.g. `pass`, is a pass
+fn sgf_coordinate(gtp: &str, board_y_size: u8) -> String {
+    gtp.parse::<Move>()
+        .map(|point| point.to_sgf(board_y_size))
+        .unwrap_or_default()
+}
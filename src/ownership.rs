@@ -0,0 +1,140 @@
+// How a move changed who owns the board, the ownership after it minus the ownership before. A swing
+// of 1 is a point going from undecided to owned, 2 is a point changing hands, so the sum over a
+// region is roughly what the move was worth there.
+
+use crate::{Board, KataResponse, Move, Player};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnershipDelta {
+    board_x_size: u8,
+    board_y_size: u8,
+    // Katago's layout, positive for points `player` gained
+    deltas: Vec<f32>,
+    player: Player,
+}
+
+// Neighbouring points which swung the same way
+#[derive(Clone, Debug, PartialEq)]
+pub struct OwnershipRegion {
+    pub points: Vec<Move>,
+    pub total: f32,
+}
+
+impl OwnershipDelta {
+    // Both from `player`'s point of view and in katago's layout. None unless both fit the board.
+    pub fn new(
+        before: &[f32],
+        after: &[f32],
+        board_x_size: u8,
+        board_y_size: u8,
+        player: Player,
+    ) -> Option<Self> {
+        let points = board_x_size as usize * board_y_size as usize;
+        if before.len() != points || after.len() != points {
+            return None;
+        }
+        Some(Self {
+            board_x_size,
+            board_y_size,
+            deltas: after.iter().zip(before).map(|(a, b)| a - b).collect(),
+            player,
+        })
+    }
+
+    // Between the results of consecutive turns, e.g. before and after the move at turn N. None
+    // unless both include ownership and their perspective is known.
+    pub fn between(
+        before: &KataResponse,
+        after: &KataResponse,
+        board_x_size: u8,
+        board_y_size: u8,
+        player: Player,
+    ) -> Option<Self> {
+        Self::new(
+            &before.ownership_for(player)?,
+            &after.ownership_for(player)?,
+            board_x_size,
+            board_y_size,
+            player,
+        )
+    }
+
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    pub fn as_slice(&self) -> &[f32] {
+        &self.deltas
+    }
+
+    pub fn at(&self, point: Move) -> Option<f32> {
+        Some(self.deltas[point.index(self.board_x_size, self.board_y_size)?])
+    }
+
+    // Net points swung to the player over the whole board
+    pub fn total(&self) -> f32 {
+        self.deltas.iter().sum()
+    }
+
+    // Points which swung by at least `threshold` towards the player, biggest first
+    pub fn gained(&self, threshold: f32) -> Vec<(Move, f32)> {
+        self.swings(|delta| delta >= threshold)
+    }
+
+    // Points which swung by at least `threshold` away from the player, biggest first
+    pub fn lost(&self, threshold: f32) -> Vec<(Move, f32)> {
+        self.swings(|delta| delta <= -threshold)
+    }
+
+    // Groups of adjacent points which each swung by at least `threshold` in the same direction,
+    // biggest swing first, e.g. the corner a move took and the side it gave up
+    pub fn regions(&self, threshold: f32) -> Vec<OwnershipRegion> {
+        let board = self.board();
+        let mut seen = vec![false; self.deltas.len()];
+        let mut regions = Vec::new();
+        for start in 0..self.deltas.len() {
+            let positive = self.deltas[start] > 0.0;
+            if seen[start] || self.deltas[start].abs() < threshold {
+                continue;
+            }
+            let mut region = OwnershipRegion {
+                points: Vec::new(),
+                total: 0.0,
+            };
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(index) = stack.pop() {
+                region.points.push(board.point(index));
+                region.total += self.deltas[index];
+                for neighbour in board.neighbours(index) {
+                    let delta = self.deltas[neighbour];
+                    if !seen[neighbour] && delta.abs() >= threshold && (delta > 0.0) == positive {
+                        seen[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+            regions.push(region);
+        }
+        regions.sort_by(|a, b| b.total.abs().total_cmp(&a.total.abs()));
+        regions
+    }
+
+    fn swings(&self, keep: impl Fn(f32) -> bool) -> Vec<(Move, f32)> {
+        let board = self.board();
+        let mut swings = self
+            .deltas
+            .iter()
+            .enumerate()
+            .filter(|(_, delta)| keep(**delta))
+            .map(|(index, delta)| (board.point(index), *delta))
+            .collect::<Vec<_>>();
+        swings.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+        swings
+    }
+
+    // Only for its geometry
+    fn board(&self) -> Board {
+        Board::new(self.board_x_size, self.board_y_size)
+    }
+}
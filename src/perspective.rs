@@ -0,0 +1,143 @@
+use crate::{KataResponse, MoveInfo, Player, ReportAnalysisWinratesAs, RootInfo};
+
+impl Player {
+    pub fn opponent(self) -> Player {
+        match self {
+            Player::Black => Player::White,
+            Player::White => Player::Black,
+        }
+    }
+}
+
+impl ReportAnalysisWinratesAs {
+    // The player whose point of view values are reported from, None when that depends on the side
+    // to move and it isn't known
+    pub fn player(self, current_player: Option<Player>) -> Option<Player> {
+        match self {
+            ReportAnalysisWinratesAs::Black => Some(Player::Black),
+            ReportAnalysisWinratesAs::White => Some(Player::White),
+            ReportAnalysisWinratesAs::SideToMove => current_player,
+        }
+    }
+}
+
+fn winrate_for(winrate: f32, reported_for: Player, player: Player) -> f32 {
+    if reported_for == player {
+        winrate
+    } else {
+        1.0 - winrate
+    }
+}
+
+fn score_for(score: f32, reported_for: Player, player: Player) -> f32 {
+    if reported_for == player {
+        score
+    } else {
+        -score
+    }
+}
+
+impl RootInfo {
+    pub fn winrate_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+    ) -> Option<f32> {
+        let reported_for = reported_as.player(self.current_player)?;
+        Some(winrate_for(self.winrate, reported_for, player))
+    }
+
+    pub fn score_lead_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+    ) -> Option<f32> {
+        let reported_for = reported_as.player(self.current_player)?;
+        Some(score_for(self.score_lead, reported_for, player))
+    }
+}
+
+impl MoveInfo {
+    // Move infos are reported from the same point of view as the root, so `current_player` is the
+    // player to move at the root
+    pub fn winrate_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+        current_player: Option<Player>,
+    ) -> Option<f32> {
+        let reported_for = reported_as.player(current_player)?;
+        Some(winrate_for(self.winrate, reported_for, player))
+    }
+
+    pub fn score_lead_for(
+        &self,
+        player: Player,
+        reported_as: ReportAnalysisWinratesAs,
+        current_player: Option<Player>,
+    ) -> Option<f32> {
+        let reported_for = reported_as.player(current_player)?;
+        Some(score_for(self.score_lead, reported_for, player))
+    }
+}
+
+impl KataResponse {
+    // How the engine reported values in this result, if the engine or client producing it knew
+    pub fn perspective(&self) -> Option<ReportAnalysisWinratesAs> {
+        match self {
+            KataResponse::Result { perspective, .. } => *perspective,
+            _ => None,
+        }
+    }
+
+    pub(crate) fn tag_perspective(&mut self, reported_as: ReportAnalysisWinratesAs) {
+        if let KataResponse::Result { perspective, .. } = self {
+            perspective.get_or_insert(reported_as);
+        }
+    }
+
+    // Root winrate from `player`'s point of view, None for responses without results or when the
+    // perspective isn't known
+    pub fn winrate_for(&self, player: Player) -> Option<f32> {
+        match self {
+            KataResponse::Result {
+                root_info,
+                perspective,
+                ..
+            } => root_info.winrate_for(player, (*perspective)?),
+            _ => None,
+        }
+    }
+
+    pub fn score_lead_for(&self, player: Player) -> Option<f32> {
+        match self {
+            KataResponse::Result {
+                root_info,
+                perspective,
+                ..
+            } => root_info.score_lead_for(player, (*perspective)?),
+            _ => None,
+        }
+    }
+
+    // Ownership from `player`'s point of view, positive for points they'll own. None without
+    // ownership or a known perspective.
+    pub fn ownership_for(&self, player: Player) -> Option<Vec<f32>> {
+        let KataResponse::Result {
+            root_info,
+            ownership: Some(ownership),
+            perspective,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let reported_for = (*perspective)?.player(root_info.current_player)?;
+        Some(
+            ownership
+                .iter()
+                .map(|owner| score_for(*owner, reported_for, player))
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,75 @@
+// Shared notion of where a game is, for statistics and time management
+
+use std::fmt;
+
+// Ownership at least this sure counts as settled, below `UNSETTLED` as still open
+const SETTLED: f32 = 0.9;
+const UNSETTLED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GamePhase {
+    Opening,
+    Middlegame,
+    Endgame,
+}
+
+impl GamePhase {
+    // By move number alone, scaled to the board: on 19x19 the opening is the first 51 moves and
+    // the endgame starts at move 144
+    pub fn from_move_number(move_number: u32, board_x_size: u8, board_y_size: u8) -> Self {
+        let points = board_x_size as u32 * board_y_size as u32;
+        if move_number < points / 7 {
+            GamePhase::Opening
+        } else if move_number < points * 2 / 5 {
+            GamePhase::Middlegame
+        } else {
+            GamePhase::Endgame
+        }
+    }
+
+    // Also looks at katago's ownership, any point of view will do. The opening lasts until its
+    // move number bound or until a third of the board is settled. The endgame starts once less
+    // than a sixth of the board is open, or a third past the move number bound, so big fights
+    // late in the game stay middlegame. Ownership of the wrong length is ignored.
+    pub fn classify(
+        move_number: u32,
+        board_x_size: u8,
+        board_y_size: u8,
+        ownership: Option<&[f32]>,
+    ) -> Self {
+        let by_move_number = Self::from_move_number(move_number, board_x_size, board_y_size);
+        let points = board_x_size as usize * board_y_size as usize;
+        let Some(ownership) = ownership.filter(|ownership| ownership.len() == points) else {
+            return by_move_number;
+        };
+        let settled = ownership
+            .iter()
+            .filter(|owner| owner.abs() >= SETTLED)
+            .count();
+        let unsettled = ownership
+            .iter()
+            .filter(|owner| owner.abs() < UNSETTLED)
+            .count();
+        let open_limit = match by_move_number {
+            GamePhase::Endgame => points / 3,
+            _ => points / 6,
+        };
+        if unsettled < open_limit {
+            GamePhase::Endgame
+        } else if by_move_number == GamePhase::Opening && settled * 3 < points {
+            GamePhase::Opening
+        } else {
+            GamePhase::Middlegame
+        }
+    }
+}
+
+impl fmt::Display for GamePhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            GamePhase::Opening => "opening",
+            GamePhase::Middlegame => "middlegame",
+            GamePhase::Endgame => "endgame",
+        })
+    }
+}
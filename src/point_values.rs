@@ -0,0 +1,186 @@
+// Rough values of the candidate moves of a position, for "biggest areas" overlays. A move's value
+// is how much better it is for the player to move than passing: the difference of their score
+// leads, averaged with the ownership it swings when katago reported both moves' ownership. Passing
+// is compared against as searched, or failing that the worst well searched move stands in for it.
+// Moves the search didn't look at get a share of the biggest value by their policy, which only
+// says where big moves might be, not how big they are.
+
+use crate::{Board, KataResponse, Move, MoveInfo, ReportAnalysisWinratesAs};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueSource {
+    Search,
+    Policy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointValue {
+    pub points: f32,
+    pub source: ValueSource,
+}
+
+// One value per point in katago's layout, None for points which aren't candidates
+#[derive(Clone, Debug, PartialEq)]
+pub struct PointValues {
+    board_x_size: u8,
+    board_y_size: u8,
+    values: Vec<Option<PointValue>>,
+}
+
+impl PointValues {
+    pub fn get(&self, point: Move) -> Option<PointValue> {
+        self.values[point.index(self.board_x_size, self.board_y_size)?]
+    }
+
+    pub fn as_slice(&self) -> &[Option<PointValue>] {
+        &self.values
+    }
+
+    // The `n` biggest, searched moves before policy estimates of the same value
+    pub fn biggest(&self, n: usize) -> Vec<(Move, PointValue)> {
+        let board = Board::new(self.board_x_size, self.board_y_size);
+        let mut values = self
+            .values
+            .iter()
+            .enumerate()
+            .filter_map(|(index, value)| Some((board.point(index), (*value)?)))
+            .collect::<Vec<_>>();
+        values.sort_by(|(_, a), (_, b)| {
+            b.points
+                .total_cmp(&a.points)
+                .then((a.source == ValueSource::Policy).cmp(&(b.source == ValueSource::Policy)))
+        });
+        values.truncate(n);
+        values
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PointValueEstimator {
+    min_visits: u64,
+    min_policy: f32,
+}
+
+impl Default for PointValueEstimator {
+    fn default() -> Self {
+        Self {
+            min_visits: 10,
+            min_policy: 0.01,
+        }
+    }
+}
+
+impl PointValueEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Moves with fewer visits are valued by their policy, their score leads are mostly noise
+    pub fn min_visits(mut self, visits: u64) -> Self {
+        self.min_visits = visits;
+        self
+    }
+
+    // Unsearched points with less policy get no value
+    pub fn min_policy(mut self, policy: f32) -> Self {
+        self.min_policy = policy;
+        self
+    }
+
+    // None for responses without results, when the side to move isn't known or nothing was
+    // searched enough. Results without a known perspective are taken as reported for black.
+    pub fn estimate(
+        &self,
+        result: &KataResponse,
+        board_x_size: u8,
+        board_y_size: u8,
+    ) -> Option<PointValues> {
+        let KataResponse::Result {
+            move_infos,
+            root_info,
+            policy,
+            perspective,
+            ..
+        } = result
+        else {
+            return None;
+        };
+        let mover = root_info.current_player?;
+        let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+        let points = board_x_size as usize * board_y_size as usize;
+        let lead = |move_info: &MoveInfo| move_info.score_lead_for(mover, reported_as, Some(mover));
+        let ownership_for_mover = |move_info: &MoveInfo| {
+            let ownership = move_info.ownership.as_ref()?;
+            let sign = if reported_as.player(Some(mover))? == mover {
+                1.0
+            } else {
+                -1.0
+            };
+            (ownership.len() == points)
+                .then(|| ownership.iter().map(|owner| sign * owner).sum::<f32>())
+        };
+
+        let searched = move_infos
+            .iter()
+            .filter(|move_info| move_info.visits >= self.min_visits)
+            .collect::<Vec<_>>();
+        let pass = searched
+            .iter()
+            .find(|move_info| move_info.r#move.parse::<Move>().ok() == Some(Move::Pass));
+        let reference_lead = match pass {
+            Some(pass) => lead(pass)?,
+            None => searched
+                .iter()
+                .filter_map(|move_info| lead(move_info))
+                .min_by(f32::total_cmp)?,
+        };
+        let reference_ownership = pass.and_then(|pass| ownership_for_mover(pass));
+
+        let mut values = vec![None; points];
+        let mut biggest: Option<(f32, f32)> = None;
+        for move_info in &searched {
+            let Ok(point) = move_info.r#move.parse::<Move>() else {
+                continue;
+            };
+            let (Some(index), Some(move_lead)) =
+                (point.index(board_x_size, board_y_size), lead(move_info))
+            else {
+                continue;
+            };
+            let mut value = move_lead - reference_lead;
+            if let (Some(swung), Some(reference)) =
+                (ownership_for_mover(move_info), reference_ownership)
+            {
+                value = (value + swung - reference) / 2.0;
+            }
+            let value = value.max(0.0);
+            values[index] = Some(PointValue {
+                points: value,
+                source: ValueSource::Search,
+            });
+            if biggest.is_none_or(|(points, _)| value > points) {
+                biggest = Some((value, move_info.prior));
+            }
+        }
+
+        // Shares of the biggest searched move's value, never more than it
+        if let (Some(policy), Some((biggest, biggest_policy))) = (policy, biggest) {
+            if policy.len() >= points && biggest_policy > 0.0 {
+                for (value, policy) in values.iter_mut().zip(policy) {
+                    if value.is_none() && *policy >= self.min_policy {
+                        *value = Some(PointValue {
+                            points: biggest * (policy / biggest_policy).min(1.0),
+                            source: ValueSource::Policy,
+                        });
+                    }
+                }
+            }
+        }
+
+        Some(PointValues {
+            board_x_size,
+            board_y_size,
+            values,
+        })
+    }
+}
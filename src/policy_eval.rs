@@ -0,0 +1,164 @@
+// The net's evaluation of a position without any search, for move prediction and rank estimation
+// where a full search would be wasted, or to compare the net with the search. A single visit only
+// evaluates the root, so the values are the net's own and the policy is reported as always.
+
+use crate::{Analysis, Client, ClientError, KataQuery, KataResponse, Move, Player, RawEvaluation};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyEvaluation {
+    // The side to move
+    pub player: Player,
+    pub board_x_size: u8,
+    pub board_y_size: u8,
+    // Katago's layout with the pass last, negative for illegal moves
+    pub policy: Vec<f32>,
+    // For positions with a `humanSLProfile`
+    pub human_policy: Option<Vec<f32>>,
+    // Black's, the raw evaluation's when katago reports it
+    winrate: f32,
+    score_lead: f32,
+}
+
+impl PolicyEvaluation {
+    pub fn winrate_for(&self, player: Player) -> f32 {
+        match player {
+            Player::Black => self.winrate,
+            Player::White => 1.0 - self.winrate,
+        }
+    }
+
+    pub fn score_lead_for(&self, player: Player) -> f32 {
+        match player {
+            Player::Black => self.score_lead,
+            Player::White => -self.score_lead,
+        }
+    }
+
+    // The net's chance of `point` being played, 0 for illegal moves
+    pub fn probability(&self, point: Move) -> f32 {
+        policy_probability(&self.policy, point, self.board_x_size, self.board_y_size)
+    }
+
+    // Most likely first, illegal moves left out
+    pub fn top_moves(&self, top: usize) -> Vec<(Move, f32)> {
+        top_moves(&self.policy, self.board_x_size, self.board_y_size, top)
+    }
+}
+
+impl Client {
+    // Evaluates the last turn of `position` with one visit
+    pub async fn policy_only(&self, position: &KataQuery) -> Result<PolicyEvaluation, ClientError> {
+        let query = self.net_query(position, true);
+        let result = self.submit(query)?.result().await?;
+        let analysis = Analysis::from_response(&result);
+        let (
+            Some(analysis),
+            KataResponse::Result {
+                policy,
+                human_policy,
+                ..
+            },
+        ) = (analysis, result)
+        else {
+            return Err(ClientError::Unsupported(
+                "katago reported no analysis for the position".to_owned(),
+            ));
+        };
+        let policy = policy.ok_or_else(|| {
+            ClientError::Unsupported("katago reported no policy for the position".to_owned())
+        })?;
+        let (board_x_size, board_y_size) = position.board_size();
+        let (winrate, score_lead) = match analysis.raw() {
+            Some(raw) => (
+                raw.winrate_for(Player::Black),
+                raw.score_lead_for(Player::Black),
+            ),
+            None => (
+                analysis.winrate_for(Player::Black),
+                analysis.score_lead_for(Player::Black),
+            ),
+        };
+        Ok(PolicyEvaluation {
+            player: analysis.player(),
+            board_x_size,
+            board_y_size,
+            policy,
+            human_policy,
+            winrate,
+            score_lead,
+        })
+    }
+
+    // The least katago needs to report the raw evaluation, one visit and no policy
+    pub async fn raw_evaluation(&self, position: &KataQuery) -> Result<RawEvaluation, ClientError> {
+        let query = self.net_query(position, false);
+        let analysis = self.submit(query)?.analysis().await?;
+        analysis.raw().cloned().ok_or_else(|| {
+            ClientError::Unsupported(
+                "this katago version doesn't report raw evaluations".to_owned(),
+            )
+        })
+    }
+
+    // The last turn of `position` with one visit, leaving out ownership and the other extras the
+    // position asks for
+    fn net_query(&self, position: &KataQuery, include_policy: bool) -> KataQuery {
+        let mut query = position.clone();
+        query.id = self.next_id();
+        query.analyze_turns = None;
+        query.max_visits = Some(1);
+        query.include_policy = include_policy.then_some(true);
+        query.include_ownership = None;
+        query.inlcude_ownership_stdev = None;
+        query.include_moves_ownership = None;
+        query.include_moves_ownership_stdev = None;
+        query.include_pv_visits = None;
+        query.report_during_search_every = None;
+        query.priorities = None;
+        query
+    }
+}
+
+pub(crate) fn policy_probability(
+    policy: &[f32],
+    point: Move,
+    board_x_size: u8,
+    board_y_size: u8,
+) -> f32 {
+    let index = match point {
+        Move::Pass => Some(board_x_size as usize * board_y_size as usize),
+        point => point.index(board_x_size, board_y_size),
+    };
+    index
+        .and_then(|index| policy.get(index))
+        .map_or(0.0, |probability| probability.max(0.0))
+}
+
+pub(crate) fn top_moves(
+    policy: &[f32],
+    board_x_size: u8,
+    board_y_size: u8,
+    top: usize,
+) -> Vec<(Move, f32)> {
+    let points = board_x_size as usize * board_y_size as usize;
+    let mut moves = policy
+        .iter()
+        .enumerate()
+        .filter(|(_, probability)| **probability >= 0.0)
+        .filter_map(|(index, probability)| {
+            let point = match index {
+                index if index == points => Move::Pass,
+                index if index < points => Move::from_top_left(
+                    (index % board_x_size as usize) as u8,
+                    (index / board_x_size as usize) as u8,
+                    board_y_size,
+                )?,
+                _ => return None,
+            };
+            Some((point, *probability))
+        })
+        .collect::<Vec<_>>();
+    moves.sort_by(|a, b| b.1.total_cmp(&a.1));
+    moves.truncate(top);
+    moves
+}
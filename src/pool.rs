@@ -0,0 +1,375 @@
+// Several katago processes behind one submit, growing while queries queue up and shrinking once
+// engines sit idle, within the configured bounds. New engines are warmed up before they get
+// queries, so the first query routed to them doesn't pay for backend initialization.
+//
+// Queries of one game or session can stick to one engine, so katago's NN cache holds the
+// positions they share.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use futures_util::StreamExt;
+use tokio::time::Instant;
+
+use crate::{
+    Client, ClientBuilder, ClientError, EngineBuilder, EngineError, GpuBudget, KataQuery,
+    LatencyHistogram, QueryHandle, Throughput,
+};
+
+const DEFAULT_QUERIES_PER_ENGINE: usize = 8;
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+const DEFAULT_CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug)]
+pub struct EnginePoolBuilder {
+    engine: EngineBuilder,
+    client: ClientBuilder,
+    min_engines: usize,
+    max_engines: usize,
+    queries_per_engine: usize,
+    idle_timeout: Duration,
+    check_every: Duration,
+    gpu_budget: Option<GpuBudget>,
+}
+
+impl EnginePoolBuilder {
+    // Every engine's client is built from this, `engine` is filled in for each
+    pub fn client(mut self, client: ClientBuilder) -> Self {
+        self.client = client;
+        self
+    }
+
+    // Never fewer, even when idle. At least 1.
+    pub fn min_engines(mut self, min_engines: usize) -> Self {
+        self.min_engines = min_engines.max(1);
+        self.max_engines = self.max_engines.max(self.min_engines);
+        self
+    }
+
+    pub fn max_engines(mut self, max_engines: usize) -> Self {
+        self.max_engines = max_engines.max(1);
+        self.min_engines = self.min_engines.min(self.max_engines);
+        self
+    }
+
+    // Another engine is started once the engines average this many queries in flight
+    pub fn queries_per_engine(mut self, queries: usize) -> Self {
+        self.queries_per_engine = queries.max(1);
+        self
+    }
+
+    // Engines above the minimum are shut down after having nothing to do for this long
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    // How often the load is looked at
+    pub fn check_every(mut self, check_every: Duration) -> Self {
+        self.check_every = check_every;
+        self
+    }
+
+    // The engines share one GPU. Its memory is split between `max_engines` of them, replacing the
+    // engine builder's batch size and thread counts, and building fails with a `GpuBudgetError`
+    // as the io error's source if that many don't fit.
+    pub fn gpu_budget(mut self, gpu_budget: GpuBudget) -> Self {
+        self.gpu_budget = Some(gpu_budget);
+        self
+    }
+
+    // Starts and warms up the minimum number of engines, then keeps scaling in the background.
+    // Must be called from within a tokio runtime.
+    pub async fn build(mut self) -> Result<EnginePool, EngineError> {
+        if let Some(gpu_budget) = &self.gpu_budget {
+            let limits = gpu_budget
+                .limits(self.max_engines)
+                .map_err(|err| EngineError::Io(io::Error::new(io::ErrorKind::InvalidInput, err)))?;
+            self.engine = limits.apply(self.engine);
+        }
+        let mut members = Vec::new();
+        for id in 0..self.min_engines {
+            members.push(Member::new(id as u64, start(&self).await?));
+        }
+        let shared = Arc::new(PoolShared {
+            next_member_id: AtomicU64::new(members.len() as u64),
+            members: Mutex::new(members),
+            sessions: Mutex::new(HashMap::new()),
+            starting: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+            config: self,
+        });
+        tokio::spawn(autoscale(Arc::downgrade(&shared)));
+        Ok(EnginePool { shared })
+    }
+}
+
+struct Member {
+    // Stays the same while engines come and go, unlike the position in `members`
+    id: u64,
+    client: Client,
+    idle_since: Option<Instant>,
+}
+
+impl Member {
+    fn new(id: u64, client: Client) -> Self {
+        Self {
+            id,
+            client,
+            idle_since: None,
+        }
+    }
+}
+
+struct PoolShared {
+    config: EnginePoolBuilder,
+    members: Mutex<Vec<Member>>,
+    next_member_id: AtomicU64,
+    // The engine each session sticks to
+    sessions: Mutex<HashMap<String, u64>>,
+    // Engines spawned but still warming up
+    starting: AtomicUsize,
+    draining: AtomicBool,
+}
+
+// Cloning is cheap and all clones share the engines
+#[derive(Clone)]
+pub struct EnginePool {
+    shared: Arc<PoolShared>,
+}
+
+impl EnginePool {
+    pub fn builder(engine: EngineBuilder) -> EnginePoolBuilder {
+        EnginePoolBuilder {
+            engine,
+            client: ClientBuilder::default(),
+            min_engines: 1,
+            max_engines: 1,
+            queries_per_engine: DEFAULT_QUERIES_PER_ENGINE,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            check_every: DEFAULT_CHECK_EVERY,
+            gpu_budget: None,
+        }
+    }
+
+    // Goes to the engine with the fewest queries in flight
+    pub fn submit(&self, query: KataQuery) -> Result<QueryHandle, ClientError> {
+        if self.shared.draining.load(Ordering::Relaxed) {
+            return Err(ClientError::Draining);
+        }
+        let client = self
+            .shared
+            .members
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|member| !member.client.is_closed())
+            .min_by_key(|member| member.client.in_flight())
+            .map(|member| member.client.clone())
+            .ok_or(ClientError::Closed)?;
+        client.submit(query)
+    }
+
+    // Goes to the engine the session's earlier queries went to. A new session, or one whose
+    // engine is gone, gets the engine its key hashes to, and while the engine is saturated queries
+    // go elsewhere for the time being.
+    pub fn submit_for(&self, session: &str, query: KataQuery) -> Result<QueryHandle, ClientError> {
+        if self.shared.draining.load(Ordering::Relaxed) {
+            return Err(ClientError::Draining);
+        }
+        let client = self.pick_for(session)?;
+        client.submit(query)
+    }
+
+    // Picked under the locks, submitted to once they're released
+    fn pick_for(&self, session: &str) -> Result<Client, ClientError> {
+        let queries_per_engine = self.shared.config.queries_per_engine;
+        let members = self.shared.members.lock().unwrap();
+        let mut sessions = self.shared.sessions.lock().unwrap();
+        let open = members
+            .iter()
+            .filter(|member| !member.client.is_closed())
+            .collect::<Vec<_>>();
+        let sticky = sessions
+            .get(session)
+            .and_then(|id| open.iter().find(|member| member.id == *id));
+        let sticky = match sticky {
+            Some(member) => member,
+            None => {
+                let member = open
+                    .iter()
+                    .max_by_key(|member| rendezvous(session, member.id))
+                    .ok_or(ClientError::Closed)?;
+                sessions.insert(session.to_owned(), member.id);
+                member
+            }
+        };
+        let member = if sticky.client.in_flight() < queries_per_engine {
+            sticky
+        } else {
+            // Consistent, so a busy session's overflow keeps hitting the same caches too
+            open.iter()
+                .filter(|member| member.client.in_flight() < queries_per_engine)
+                .max_by_key(|member| rendezvous(session, member.id))
+                .or_else(|| open.iter().min_by_key(|member| member.client.in_flight()))
+                .unwrap_or(sticky)
+        };
+        Ok(member.client.clone())
+    }
+
+    // Lets the session's next query go anywhere, e.g. once its game ended
+    pub fn end_session(&self, session: &str) {
+        self.shared.sessions.lock().unwrap().remove(session);
+    }
+
+    // Merged over the running engines
+    pub fn latency(&self) -> LatencyHistogram {
+        let mut latency = LatencyHistogram::new();
+        for member in self.shared.members.lock().unwrap().iter() {
+            latency.merge(&member.client.latency());
+        }
+        latency
+    }
+
+    // One per running engine, in no particular order
+    pub fn throughput(&self) -> Vec<Throughput> {
+        self.shared
+            .members
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|member| member.client.throughput())
+            .collect()
+    }
+
+    // Running engines, not counting ones still warming up
+    pub fn engines(&self) -> usize {
+        self.shared.members.lock().unwrap().len()
+    }
+
+    // Stops scaling and drains every engine at once, see `Client::drain`, so `deadline` is for
+    // the whole pool rather than each engine
+    pub async fn drain(&self, deadline: Option<Duration>) -> Result<(), ClientError> {
+        self.shared.draining.store(true, Ordering::Relaxed);
+        self.shared.sessions.lock().unwrap().clear();
+        let clients = std::mem::take(&mut *self.shared.members.lock().unwrap())
+            .into_iter()
+            .map(|member| member.client)
+            .collect::<Vec<_>>();
+        join_all(clients.iter().map(|client| client.drain(deadline)))
+            .await
+            .into_iter()
+            .collect()
+    }
+}
+
+async fn start(config: &EnginePoolBuilder) -> Result<Client, EngineError> {
+    let mut engine = config.engine.spawn().map_err(EngineError::Io)?;
+    engine.warm_up().await?;
+    let handle = engine.handle();
+    let (sink, stream) = engine.split();
+    Ok(config.client.clone().engine(&handle).build(sink, stream))
+}
+
+async fn autoscale(shared: Weak<PoolShared>) {
+    loop {
+        let Some(check_every) = shared.upgrade().map(|shared| shared.config.check_every) else {
+            return;
+        };
+        tokio::time::sleep(check_every).await;
+        let Some(shared) = shared.upgrade() else {
+            return;
+        };
+        if shared.draining.load(Ordering::Relaxed) {
+            return;
+        }
+        scale(&shared);
+    }
+}
+
+fn scale(shared: &Arc<PoolShared>) {
+    let config = &shared.config;
+    let now = Instant::now();
+    let mut members = shared.members.lock().unwrap();
+    members.retain(|member| !member.client.is_closed());
+    shared
+        .sessions
+        .lock()
+        .unwrap()
+        .retain(|_, id| members.iter().any(|member| member.id == *id));
+    let mut in_flight = 0;
+    for member in members.iter_mut() {
+        let member_in_flight = member.client.in_flight();
+        in_flight += member_in_flight;
+        if member_in_flight == 0 {
+            member.idle_since.get_or_insert(now);
+        } else {
+            member.idle_since = None;
+        }
+    }
+
+    let starting = shared.starting.load(Ordering::Relaxed);
+    let engines = members.len() + starting;
+    // An idle engine takes the next submissions, queries already sent can't move to a new one.
+    // Engines still warming up count as capacity, otherwise a burst arriving during katago's
+    // startup would start another engine on every check.
+    let busy = in_flight >= engines * config.queries_per_engine
+        && members.iter().all(|member| member.idle_since.is_none());
+    if engines < config.min_engines || (busy && engines < config.max_engines) {
+        shared.starting.fetch_add(1, Ordering::Relaxed);
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            // Failed starts are retried on a later check
+            let started = start(&shared.config).await;
+            shared.starting.fetch_sub(1, Ordering::Relaxed);
+            let Ok(client) = started else {
+                return;
+            };
+            // Checked under the lock, so `drain` either sees the engine or the engine sees it
+            let rejected = {
+                let mut members = shared.members.lock().unwrap();
+                if shared.draining.load(Ordering::Relaxed) {
+                    Some(client)
+                } else {
+                    let id = shared.next_member_id.fetch_add(1, Ordering::Relaxed);
+                    members.push(Member::new(id, client));
+                    None
+                }
+            };
+            if let Some(client) = rejected {
+                let _ = client.drain(None).await;
+            }
+        });
+        return;
+    }
+
+    // One engine at a time, so a short lull doesn't shut down everything at once
+    if members.len() > config.min_engines {
+        let idle = members.iter().position(|member| {
+            member
+                .idle_since
+                .is_some_and(|idle_since| now - idle_since >= config.idle_timeout)
+        });
+        if let Some(idle) = idle {
+            let member = members.remove(idle);
+            tokio::spawn(async move {
+                let _ = member.client.drain(None).await;
+            });
+        }
+    }
+}
+
+// Rendezvous hashing: every session ranks the engines its own way, and a session only moves
+// when its top engine goes away
+fn rendezvous(session: &str, member: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    session.hash(&mut hasher);
+    member.hash(&mut hasher);
+    hasher.finish()
+}
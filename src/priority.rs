@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+// katago searches queries with a higher priority first, whatever order they arrived in. Queries
+// with equal priorities are searched in order. Values in between the constants are fine, only the
+// order matters.
+#[derive(
+    Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(transparent)]
+pub struct Priority(pub i32);
+
+impl Priority {
+    pub const LOW: Priority = Priority(-100);
+    pub const NORMAL: Priority = Priority(0);
+    pub const HIGH: Priority = Priority(100);
+}
+
+impl From<i32> for Priority {
+    fn from(priority: i32) -> Self {
+        Priority(priority)
+    }
+}
+
+// Where a query comes from, see `Client::submit_to`. Interactive queries, e.g. the position a user
+// is looking at, are searched before batch work like full game reviews.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QueryLane {
+    Interactive,
+    Batch,
+}
+
+impl QueryLane {
+    pub(crate) fn default_priority(self) -> Priority {
+        match self {
+            QueryLane::Interactive => Priority::HIGH,
+            QueryLane::Batch => Priority::LOW,
+        }
+    }
+}
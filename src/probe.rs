@@ -0,0 +1,193 @@
+// Evaluating one particular move, whether or not the search would have looked at it. The search is
+// restricted to the move with `allowMoves` at the first ply only, so the replies to it are searched
+// as usual and the whole visit budget goes to it.
+
+use std::fmt;
+
+use futures_util::future::try_join_all;
+
+use crate::{
+    Client, ClientError, KataQuery, KataResponse, Move, MoveGroup, Player, QueryLane,
+    ReportAnalysisWinratesAs,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Probe {
+    pub point: Move,
+    // Who plays it, the side to move in the position
+    pub player: Player,
+    pub visits: u64,
+    // From `player`'s point of view
+    pub winrate: f32,
+    pub score_lead: f32,
+    pub score_stdev: f32,
+    // Starting with the probed move
+    pub pv: Vec<Move>,
+}
+
+impl Probe {
+    // None unless `result` has a move info for `point` and its perspective is known or taken as
+    // black
+    pub(crate) fn from_result(result: &KataResponse, point: Move, player: Player) -> Option<Self> {
+        let KataResponse::Result {
+            move_infos,
+            perspective,
+            ..
+        } = result
+        else {
+            return None;
+        };
+        let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+        let move_info = move_infos
+            .iter()
+            .find(|move_info| move_info.r#move.parse::<Move>().ok() == Some(point))?;
+        Some(Self {
+            point,
+            player,
+            visits: move_info.visits,
+            winrate: move_info.winrate_for(player, reported_as, Some(player))?,
+            score_lead: move_info.score_lead_for(player, reported_as, Some(player))?,
+            score_stdev: move_info.score_stdev,
+            pv: move_info
+                .pv
+                .iter()
+                .filter_map(|played| played.parse().ok())
+                .collect(),
+        })
+    }
+}
+
+// Candidates searched with the same visits each, best first by winrate, then score lead
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub player: Player,
+    pub rows: Vec<ComparedMove>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ComparedMove {
+    pub probe: Probe,
+    // How far behind the best candidate it is, 0 for the best
+    pub winrate_loss: f32,
+    pub score_loss: f32,
+}
+
+impl Comparison {
+    fn new(player: Player, mut probes: Vec<Probe>) -> Self {
+        probes.sort_by(|a, b| {
+            b.winrate
+                .total_cmp(&a.winrate)
+                .then(b.score_lead.total_cmp(&a.score_lead))
+        });
+        let best = probes
+            .first()
+            .map_or((0.0, 0.0), |best| (best.winrate, best.score_lead));
+        let rows = probes
+            .into_iter()
+            .map(|probe| ComparedMove {
+                winrate_loss: best.0 - probe.winrate,
+                score_loss: best.1 - probe.score_lead,
+                probe,
+            })
+            .collect();
+        Self { player, rows }
+    }
+
+    pub fn best(&self) -> Option<&ComparedMove> {
+        self.rows.first()
+    }
+
+    pub fn get(&self, point: Move) -> Option<&ComparedMove> {
+        self.rows.iter().find(|row| row.probe.point == point)
+    }
+}
+
+// A plain text table, one candidate per line
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>4} {:>5} {:>7} {:>7} {:>7} {:>6}",
+            "rank", "move", "winrate", "lead", "loss", "visits"
+        )?;
+        for (rank, row) in self.rows.iter().enumerate() {
+            writeln!(
+                f,
+                "{:>4} {:>5} {:>6.1}% {:>+7.1} {:>7.1} {:>6}",
+                rank + 1,
+                row.probe.point.to_string(),
+                row.probe.winrate * 100.0,
+                row.probe.score_lead,
+                row.score_loss,
+                row.probe.visits
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    // Searches `position`, its last turn, with only `point` allowed for the side to move. Moves
+    // katago considers illegal are rejected like any other bad query.
+    pub async fn probe(
+        &self,
+        position: &KataQuery,
+        point: Move,
+        visits: u64,
+    ) -> Result<Probe, ClientError> {
+        self.probe_in(None, position, point, visits).await
+    }
+
+    // Probes every candidate with `visits` each, so moves the default search wouldn't spend visits
+    // on get compared fairly. Repeated candidates are probed once, any failed probe fails the
+    // comparison.
+    pub async fn compare(
+        &self,
+        position: &KataQuery,
+        candidates: &[Move],
+        visits: u64,
+    ) -> Result<Comparison, ClientError> {
+        let mut unique = Vec::new();
+        for candidate in candidates {
+            if !unique.contains(candidate) {
+                unique.push(*candidate);
+            }
+        }
+        let probes = try_join_all(
+            unique
+                .into_iter()
+                .map(|candidate| self.probe(position, candidate, visits)),
+        )
+        .await?;
+        Ok(Comparison::new(position.player_to_move(), probes))
+    }
+
+    // With `lane`'s priority rather than the position's
+    pub(crate) async fn probe_in(
+        &self,
+        lane: Option<QueryLane>,
+        position: &KataQuery,
+        point: Move,
+        visits: u64,
+    ) -> Result<Probe, ClientError> {
+        let player = position.player_to_move();
+        let mut probe = position.clone();
+        probe.id = self.next_id();
+        probe.analyze_turns = None;
+        probe.max_visits = Some(visits);
+        probe.report_during_search_every = None;
+        probe.priorities = None;
+        probe.allow_moves = Some([MoveGroup::new(player, [point], 1)]);
+        let handle = match lane {
+            Some(lane) => {
+                probe.priority = None;
+                self.submit_to(lane, probe)?
+            }
+            None => self.submit(probe)?,
+        };
+        let result = handle.result().await?;
+        Probe::from_result(&result, point, player).ok_or_else(|| {
+            ClientError::Unsupported(format!("katago didn't report on the probed move {point}"))
+        })
+    }
+}
@@ -0,0 +1,142 @@
+// OS scheduling of the katago process, so analysis running next to a game client doesn't starve
+// its UI. Both are set before katago starts its threads, which inherit them: on unix in the forked
+// child before exec, on Windows through the creation flags. Windows' affinity can only be set
+// once the process exists, threads katago started by then keep running on any CPU.
+
+use std::io;
+
+#[cfg(windows)]
+use tokio::process::Child;
+#[cfg(unix)]
+use tokio::process::Command;
+
+#[cfg(windows)]
+const IDLE_PRIORITY_CLASS: u32 = 0x0000_0040;
+#[cfg(windows)]
+const BELOW_NORMAL_PRIORITY_CLASS: u32 = 0x0000_4000;
+#[cfg(windows)]
+const NORMAL_PRIORITY_CLASS: u32 = 0x0000_0020;
+#[cfg(windows)]
+const ABOVE_NORMAL_PRIORITY_CLASS: u32 = 0x0000_8000;
+
+#[cfg(windows)]
+extern "system" {
+    fn SetProcessAffinityMask(process: *mut std::ffi::c_void, mask: usize) -> i32;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessPriority {
+    // Niceness 10 on unix, the below normal priority class on Windows
+    BelowNormal,
+    // Only runs when nothing else wants the CPU, niceness 19 or the idle priority class
+    Idle,
+    // From -20 to 19, negative values need privileges. Mapped to the closest priority class on
+    // Windows.
+    Nice(i8),
+}
+
+impl ProcessPriority {
+    #[cfg(unix)]
+    fn niceness(self) -> libc::c_int {
+        match self {
+            ProcessPriority::BelowNormal => 10,
+            ProcessPriority::Idle => 19,
+            ProcessPriority::Nice(nice) => nice.clamp(-20, 19) as libc::c_int,
+        }
+    }
+
+    #[cfg(windows)]
+    pub(crate) fn priority_class(self) -> u32 {
+        match self {
+            ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+            ProcessPriority::Nice(nice) if nice >= 15 => IDLE_PRIORITY_CLASS,
+            ProcessPriority::Nice(nice) if nice > 0 => BELOW_NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Nice(0) => NORMAL_PRIORITY_CLASS,
+            ProcessPriority::Nice(_) => ABOVE_NORMAL_PRIORITY_CLASS,
+        }
+    }
+}
+
+// Spawning fails with the error if the priority or affinity can't be set, e.g. for CPUs the
+// machine doesn't have
+#[cfg(unix)]
+pub(crate) fn apply(cmd: &mut Command, priority: Option<ProcessPriority>, cpus: Option<&[usize]>) {
+    if priority.is_none() && cpus.is_none() {
+        return;
+    }
+    let niceness = priority.map(ProcessPriority::niceness);
+    let affinity = cpus.map(cpu_set);
+    // SAFETY: the closure only makes system calls, which are async-signal-safe, and allocates
+    // nothing
+    unsafe {
+        cmd.pre_exec(move || {
+            if let Some(niceness) = niceness {
+                if libc::setpriority(libc::PRIO_PROCESS, 0, niceness) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            match &affinity {
+                Some(Ok(set)) => set_affinity(set),
+                Some(Err(kind)) => Err(io::Error::from(*kind)),
+                None => Ok(()),
+            }
+        });
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn cpu_set(cpus: &[usize]) -> Result<libc::cpu_set_t, io::ErrorKind> {
+    // SAFETY: cpu_set_t is a plain bit set, all zeroes is the empty set
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize {
+            return Err(io::ErrorKind::InvalidInput);
+        }
+        // SAFETY: the CPU was checked to fit in the set
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    Ok(set)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_affinity(set: &libc::cpu_set_t) -> io::Result<()> {
+    // SAFETY: the set is valid for its whole size
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), set) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// Other unixes, e.g. macOS, have no way to pin processes to CPUs
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn cpu_set(_cpus: &[usize]) -> Result<(), io::ErrorKind> {
+    Err(io::ErrorKind::Unsupported)
+}
+
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn set_affinity(_set: &()) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// CPUs past the 64th can't be given, Windows schedules those in processor groups
+#[cfg(windows)]
+pub(crate) fn set_affinity_after_spawn(child: &Child, cpus: &[usize]) -> io::Result<()> {
+    let mut mask = 0usize;
+    for &cpu in cpus {
+        if cpu >= usize::BITS as usize {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        mask |= 1 << cpu;
+    }
+    let handle = child
+        .raw_handle()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "engine already exited"))?;
+    // SAFETY: the handle belongs to the child, which wasn't reaped yet
+    if unsafe { SetProcessAffinityMask(handle, mask) } != 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
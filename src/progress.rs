@@ -0,0 +1,125 @@
+// How far along a search is, for progress bars. Katago reuses the search tree of positions it has
+// seen, e.g. the previous move's search when stepping through a game, so the first interim result
+// can already have most of the visits and later ones jump ahead. The fraction is only ever
+// allowed to grow, and the visit rate behind the ETA is measured from the first result on, so
+// the reused visits don't make it look faster than it is.
+
+use std::time::{Duration, Instant};
+
+use crate::{KataQuery, KataResponse};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    // From 0 to 1
+    pub fraction: f32,
+    pub visits: u64,
+    // None while neither the visit rate nor a time limit is known
+    pub eta: Option<Duration>,
+}
+
+impl KataResponse {
+    // Visits so far out of `max_visits`, None for responses without results
+    pub fn progress(&self, max_visits: u64) -> Option<f32> {
+        let KataResponse::Result { root_info, .. } = self else {
+            return None;
+        };
+        Some((root_info.visits as f32 / max_visits.max(1) as f32).min(1.0))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchProgress {
+    max_visits: Option<u64>,
+    max_time: Option<Duration>,
+    // Of the turn being searched
+    turn: Option<u32>,
+    started: Instant,
+    first: Option<(Instant, u64)>,
+    fraction: f32,
+}
+
+impl SearchProgress {
+    pub fn new(max_visits: u64) -> Self {
+        Self::with_limits(Some(max_visits), None)
+    }
+
+    // Queries with neither limit run until the engine's configured ones, which aren't known here.
+    // Created when the query is submitted, its time limit counts from then.
+    pub fn for_query(query: &KataQuery) -> Option<Self> {
+        let (max_visits, max_time) = (query.max_visits, query.max_time());
+        (max_visits.is_some() || max_time.is_some())
+            .then(|| Self::with_limits(max_visits, max_time))
+    }
+
+    fn with_limits(max_visits: Option<u64>, max_time: Option<Duration>) -> Self {
+        Self {
+            max_visits,
+            max_time,
+            turn: None,
+            started: Instant::now(),
+            first: None,
+            fraction: 0.0,
+        }
+    }
+
+    // Final results finish the turn. Queries analyzing several turns start over with each.
+    pub fn observe(&mut self, response: &KataResponse) -> Option<Progress> {
+        let KataResponse::Result { root_info, .. } = response else {
+            return None;
+        };
+        let now = Instant::now();
+        let turn = response.turn_number();
+        if self.turn != turn {
+            if self.turn.is_some() {
+                self.started = now;
+            }
+            self.turn = turn;
+            self.first = None;
+            self.fraction = 0.0;
+        }
+        let visits = root_info.visits;
+        let &mut (first_at, first_visits) = self.first.get_or_insert((now, visits));
+
+        let by_visits = self
+            .max_visits
+            .map(|max_visits| visits as f32 / max_visits.max(1) as f32);
+        let by_time = self.max_time.map(|max_time| {
+            now.duration_since(self.started).as_secs_f32()
+                / max_time.as_secs_f32().max(f32::EPSILON)
+        });
+        let fraction = match (by_visits, by_time) {
+            (Some(a), Some(b)) => a.max(b),
+            (a, b) => a.or(b).unwrap_or(0.0),
+        };
+        if !response.is_during_search() {
+            self.fraction = 1.0;
+        }
+        self.fraction = self.fraction.max(fraction.min(1.0));
+
+        let eta = if self.fraction >= 1.0 {
+            Some(Duration::ZERO)
+        } else {
+            let elapsed = now.duration_since(first_at).as_secs_f32();
+            let searched = visits.saturating_sub(first_visits);
+            let by_visits = self.max_visits.and_then(|max_visits| {
+                (searched > 0 && elapsed > 0.0).then(|| {
+                    let rate = searched as f32 / elapsed;
+                    max_visits.saturating_sub(visits) as f32 / rate
+                })
+            });
+            let by_time = self.max_time.map(|max_time| {
+                (max_time.as_secs_f32() - now.duration_since(self.started).as_secs_f32()).max(0.0)
+            });
+            match (by_visits, by_time) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            }
+            .map(Duration::from_secs_f32)
+        };
+        Some(Progress {
+            fraction: self.fraction,
+            visits,
+            eta,
+        })
+    }
+}
@@ -0,0 +1,142 @@
+#[cfg(feature = "process")]
+use std::fs::{self, File, OpenOptions};
+#[cfg(feature = "process")]
+use std::io::{self, LineWriter, Write};
+#[cfg(feature = "process")]
+use std::path::PathBuf;
+#[cfg(feature = "process")]
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "process")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "process")]
+const DEFAULT_MAX_BYTES: u64 = 64 << 20;
+#[cfg(feature = "process")]
+const DEFAULT_KEEP: usize = 3;
+
+// Outgoing lines are marked with `>`, incoming ones with `<`, e.g.
+// `1714557600.123 > {"id":"a","moves":[]...}`
+pub(crate) const OUTGOING: char = '>';
+pub(crate) const INCOMING: char = '<';
+
+// Splits an entry into its timestamp, direction and line
+pub(crate) fn parse_entry(entry: &str) -> Option<(Duration, char, &str)> {
+    let (timestamp, rest) = entry.split_once(' ')?;
+    let (direction, line) = rest.split_once(' ')?;
+    let direction = match direction {
+        ">" => OUTGOING,
+        "<" => INCOMING,
+        _ => return None,
+    };
+    let (secs, millis) = timestamp.split_once('.')?;
+    let timestamp =
+        Duration::from_secs(secs.parse().ok()?) + Duration::from_millis(millis.parse().ok()?);
+    Some((timestamp, direction, line))
+}
+
+#[cfg(feature = "process")]
+type WrapWriter = Arc<dyn Fn(File) -> io::Result<Box<dyn Write + Send>> + Send + Sync>;
+
+// Records every line exchanged with an engine verbatim, each prefixed with the seconds since the
+// unix epoch it was sent or received at. Once the file reaches `max_bytes` it's renamed to
+// `<path>.1`, older files shift to `<path>.2` and so on, keeping at most `keep` of them.
+#[cfg(feature = "process")]
+pub struct ProtocolLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    wrap: Option<WrapWriter>,
+    file: Box<dyn Write + Send>,
+    written: u64,
+}
+
+#[cfg(feature = "process")]
+impl ProtocolLog {
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        Self::open(path.into(), None)
+    }
+
+    // Writes every file through `wrap`, e.g. a zstd encoder, which then decides when lines reach
+    // the disk. Rotated files are dropped, so the writer has to finish its stream on drop, like
+    // zstd's `auto_finish`. `max_bytes` counts the lines before compression, and the existing
+    // file's size as it is on disk.
+    pub fn create_with<W: Write + Send + 'static>(
+        path: impl Into<PathBuf>,
+        wrap: impl Fn(File) -> io::Result<W> + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let wrap: WrapWriter =
+            Arc::new(move |file| Ok(Box::new(wrap(file)?) as Box<dyn Write + Send>));
+        Self::open(path.into(), Some(wrap))
+    }
+
+    fn open(path: PathBuf, wrap: Option<WrapWriter>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            file: writer(wrap.as_ref(), file)?,
+            path,
+            max_bytes: DEFAULT_MAX_BYTES,
+            keep: DEFAULT_KEEP,
+            wrap,
+            written,
+        })
+    }
+
+    pub fn rotate_at(mut self, max_bytes: u64, keep: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self.keep = keep;
+        self
+    }
+
+    // Logging must never break the engine, so failures only lose log lines
+    pub(crate) fn record(&mut self, direction: char, line: &str) {
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let entry = format!(
+            "{}.{:03} {direction} {line}\n",
+            now.as_secs(),
+            now.subsec_millis()
+        );
+        if self.written > 0 && self.written + entry.len() as u64 > self.max_bytes {
+            let _ = self.rotate();
+        }
+        if self.file.write_all(entry.as_bytes()).is_ok() {
+            self.written += entry.len() as u64;
+        }
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        // Finishes the old file's stream before it's renamed
+        self.file = Box::new(io::sink());
+        let rotated = |n: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{n}"));
+            PathBuf::from(path)
+        };
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated(self.keep));
+            for n in (1..self.keep).rev() {
+                let _ = fs::rename(rotated(n), rotated(n + 1));
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = writer(self.wrap.as_ref(), File::create(&self.path)?)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+// Unwrapped files get every line as it's recorded, so the log is complete if the process dies
+#[cfg(feature = "process")]
+fn writer(wrap: Option<&WrapWriter>, file: File) -> io::Result<Box<dyn Write + Send>> {
+    match wrap {
+        Some(wrap) => wrap(file),
+        None => Ok(Box::new(LineWriter::new(file))),
+    }
+}
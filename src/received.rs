@@ -0,0 +1,91 @@
+// Stamps responses as they come out of a stream, so they can still be put in order and timed
+// after being split by query, e.g. for profiling or replaying a session with its real pacing.
+// The stamp is taken when the stream is polled, which is when the line was read as long as the
+// consumer keeps up.
+
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::SystemTime;
+
+use futures_core::Stream;
+use tokio::time::Instant;
+
+use crate::KataResponse;
+
+#[derive(Clone, Debug)]
+pub struct ReceivedResponse<T = KataResponse> {
+    pub response: T,
+    // From 0, one up for every response of the stream
+    pub sequence: u64,
+    // Monotonic, for intervals between responses
+    pub received: Instant,
+    // Wall clock, for lining up with other logs
+    pub received_at: SystemTime,
+}
+
+impl<T> ReceivedResponse<T> {
+    pub fn into_inner(self) -> T {
+        self.response
+    }
+
+    // Keeps the stamp, e.g. for a response converted to an event
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> ReceivedResponse<U> {
+        ReceivedResponse {
+            response: f(self.response),
+            sequence: self.sequence,
+            received: self.received,
+            received_at: self.received_at,
+        }
+    }
+}
+
+impl AsRef<KataResponse> for ReceivedResponse<KataResponse> {
+    fn as_ref(&self) -> &KataResponse {
+        &self.response
+    }
+}
+
+impl AsRef<KataResponse> for KataResponse {
+    fn as_ref(&self) -> &KataResponse {
+        self
+    }
+}
+
+pub struct Stamped<St> {
+    stream: St,
+    sequence: u64,
+}
+
+pub fn stamp_received<St: Stream>(stream: St) -> Stamped<St> {
+    Stamped {
+        stream,
+        sequence: 0,
+    }
+}
+
+impl<St> Stamped<St> {
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St> Stream for Stamped<St>
+where
+    St: Stream + Unpin,
+{
+    type Item = ReceivedResponse<St::Item>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let Some(response) = ready!(Pin::new(&mut self.stream).poll_next(cx)) else {
+            return Poll::Ready(None);
+        };
+        let sequence = self.sequence;
+        self.sequence += 1;
+        Poll::Ready(Some(ReceivedResponse {
+            response,
+            sequence,
+            received: Instant::now(),
+            received_at: SystemTime::now(),
+        }))
+    }
+}
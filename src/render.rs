@@ -0,0 +1,129 @@
+// Text diagrams of a position for terminals and logs, e.g. `println!("{}", Diagram::new(&board))`.
+// Stones are `X` and `O`. Ownership marks the empty points, `x` and `o` in plain text or a gray
+// background with ANSI colors, and the top policy moves are numbered from 1.
+
+use std::fmt;
+
+use crate::{Board, Move, Player};
+
+// Weaker ownership is drawn as neutral
+const OWNERSHIP: f32 = 0.2;
+const STRONG_OWNERSHIP: f32 = 0.6;
+const BOARD_BACKGROUND: u8 = 179;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Diagram<'a> {
+    board: &'a Board,
+    ownership: Option<&'a [f32]>,
+    policy: Option<&'a [f32]>,
+    top_moves: usize,
+    ansi: bool,
+}
+
+impl<'a> Diagram<'a> {
+    pub fn new(board: &'a Board) -> Self {
+        Self {
+            board,
+            ownership: None,
+            policy: None,
+            top_moves: 0,
+            ansi: false,
+        }
+    }
+
+    // From black's point of view, in katago's layout. Arrays of the wrong length are ignored.
+    pub fn ownership(mut self, ownership: &'a [f32]) -> Self {
+        self.ownership = Some(ownership);
+        self
+    }
+
+    // Marks the `top_moves` points with the highest policy, at most 9. The pass at the end of
+    // katago's policy array is never marked.
+    pub fn policy(mut self, policy: &'a [f32], top_moves: usize) -> Self {
+        self.policy = Some(policy);
+        self.top_moves = top_moves.min(9);
+        self
+    }
+
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    fn points(&self) -> usize {
+        self.board.x_size() as usize * self.board.y_size() as usize
+    }
+
+    fn top_moves(&self) -> Vec<usize> {
+        let Some(policy) = self.policy.filter(|policy| policy.len() > self.points()) else {
+            return Vec::new();
+        };
+        // Illegal moves have a policy of -1
+        let mut indices = (0..self.points())
+            .filter(|index| policy[*index] >= 0.0)
+            .collect::<Vec<_>>();
+        indices.sort_by(|a, b| policy[*b].total_cmp(&policy[*a]));
+        indices.truncate(self.top_moves);
+        indices
+    }
+}
+
+impl fmt::Display for Diagram<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (x_size, y_size) = (self.board.x_size(), self.board.y_size());
+        let ownership = self
+            .ownership
+            .filter(|ownership| ownership.len() == self.points());
+        let top_moves = self.top_moves();
+
+        let columns = (0..x_size)
+            .map(|x| {
+                let column = Move::point(x, 0).to_string();
+                column.trim_end_matches('1').to_owned()
+            })
+            .collect::<Vec<_>>();
+        writeln!(f, "    {}", columns.join(" "))?;
+        for y in (0..y_size).rev() {
+            write!(f, "{:>3} ", y as u16 + 1)?;
+            for x in 0..x_size {
+                let point = Move::point(x, y);
+                let Some(index) = self.board.index(point) else {
+                    continue;
+                };
+                let owner = ownership.map_or(0.0, |ownership| ownership[index]);
+                let rank = top_moves.iter().position(|top| *top == index);
+                let stone = self.board.get(point);
+                let text = match (stone, rank) {
+                    (Some(Player::Black), _) => 'X',
+                    (Some(Player::White), _) => 'O',
+                    (None, Some(rank)) => char::from_digit(rank as u32 + 1, 10).unwrap_or('?'),
+                    (None, None) if !self.ansi && owner >= STRONG_OWNERSHIP => 'x',
+                    (None, None) if !self.ansi && owner <= -STRONG_OWNERSHIP => 'o',
+                    (None, None) => '.',
+                };
+                if self.ansi {
+                    let background = match owner {
+                        owner if owner >= STRONG_OWNERSHIP => 240,
+                        owner if owner >= OWNERSHIP => 244,
+                        owner if owner <= -STRONG_OWNERSHIP => 252,
+                        owner if owner <= -OWNERSHIP => 248,
+                        _ => BOARD_BACKGROUND,
+                    };
+                    let foreground = match (stone, rank) {
+                        (Some(Player::Black), _) => "38;5;16;1",
+                        (Some(Player::White), _) => "38;5;231;1",
+                        (None, Some(_)) => "38;5;196;1",
+                        (None, None) => "38;5;94",
+                    };
+                    write!(f, "\x1b[48;5;{background};{foreground}m{text} \x1b[0m")?;
+                } else if x + 1 < x_size {
+                    write!(f, "{text} ")?;
+                } else {
+                    write!(f, "{text}")?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_core::Stream;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+use tokio::time::Sleep;
+
+use crate::protocol_log::{parse_entry, INCOMING};
+use crate::{KataResponse, ReportAnalysisWinratesAs};
+
+// Reproduces the responses recorded by a `ProtocolLog`, so analysis can be consumed without an
+// engine. Outgoing lines are skipped. With `honor_timing` the gaps between the recorded entries
+// are slept through, which needs a runtime with the time driver enabled.
+pub struct Replay<R> {
+    lines: Lines<R>,
+    honor_timing: bool,
+    report_analysis_winrates_as: Option<ReportAnalysisWinratesAs>,
+    last: Option<Duration>,
+    delay: Option<(Pin<Box<Sleep>>, KataResponse)>,
+}
+
+impl Replay<Cursor<Vec<u8>>> {
+    // Reads the whole file upfront, logs are rotated before they get large
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(Cursor::new(std::fs::read(path)?)))
+    }
+
+    // Like `open`, reading the file through `wrap`, e.g. a zstd decoder for a log written with
+    // `ProtocolLog::create_with`. For streaming decompression pass an async decoder to `new`.
+    pub fn open_with<R: Read>(
+        path: impl AsRef<Path>,
+        wrap: impl FnOnce(File) -> io::Result<R>,
+    ) -> io::Result<Self> {
+        let mut log = Vec::new();
+        wrap(File::open(path)?)?.read_to_end(&mut log)?;
+        Ok(Self::new(Cursor::new(log)))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Replay<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            honor_timing: false,
+            report_analysis_winrates_as: None,
+            last: None,
+            delay: None,
+        }
+    }
+
+    pub fn honor_timing(mut self, honor_timing: bool) -> Self {
+        self.honor_timing = honor_timing;
+        self
+    }
+
+    // The log doesn't record how the engine was configured, see
+    // `EngineBuilder::report_analysis_winrates_as`
+    pub fn report_analysis_winrates_as(mut self, reported_as: ReportAnalysisWinratesAs) -> Self {
+        self.report_analysis_winrates_as = Some(reported_as);
+        self
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for Replay<R> {
+    type Item = io::Result<KataResponse>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some((delay, _)) = &mut self.delay {
+            ready!(delay.as_mut().poll(cx));
+            let (_, response) = self.delay.take().unwrap();
+            return Poll::Ready(Some(Ok(response)));
+        }
+        loop {
+            let entry = match ready!(Pin::new(&mut self.lines).poll_next_line(cx)) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => return Poll::Ready(None),
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            };
+            if entry.is_empty() {
+                continue;
+            }
+            let Some((timestamp, direction, line)) = parse_entry(&entry) else {
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("not a protocol log entry: {entry}"),
+                ))));
+            };
+            // The first entry starts the clock, usually it's the query the first response answers
+            if direction != INCOMING {
+                self.last.get_or_insert(timestamp);
+                continue;
+            }
+            let gap = self
+                .last
+                .replace(timestamp)
+                .and_then(|last| timestamp.checked_sub(last))
+                .filter(|gap| self.honor_timing && !gap.is_zero());
+            let mut response = match serde_json::from_str::<KataResponse>(line) {
+                Ok(response) => response,
+                Err(err) => {
+                    return Poll::Ready(Some(Err(io::Error::new(io::ErrorKind::InvalidData, err))))
+                }
+            };
+            if let Some(reported_as) = self.report_analysis_winrates_as {
+                response.tag_perspective(reported_as);
+            }
+            match gap {
+                Some(gap) => {
+                    self.delay = Some((Box::pin(tokio::time::sleep(gap)), response));
+                    return self.poll_next(cx);
+                }
+                None => return Poll::Ready(Some(Ok(response))),
+            }
+        }
+    }
+}
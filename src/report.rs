@@ -0,0 +1,270 @@
+// A self-contained HTML review of a game, to hand to students: an evaluation graph, the mistakes
+// with the engine's variations, and diagrams of the positions they were played in. Everything is
+// inline, the page needs no scripts, stylesheets or images from elsewhere.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::ogs::Review;
+use crate::sgf::SgfGame;
+use crate::{
+    Annotation, Annotator, Board, EvaluationPoint, EvaluationSeries, KataResponse, Move, Player,
+};
+
+const GRAPH_WIDTH: f32 = 800.0;
+const GRAPH_HEIGHT: f32 = 200.0;
+const CELL: u32 = 24;
+
+struct Mistake<'a> {
+    move_number: u32,
+    player: Player,
+    played: Move,
+    annotation: Annotation,
+    points_lost: f32,
+    best: Option<&'a str>,
+    variation: &'a [String],
+    diagram: Option<Board>,
+}
+
+// Results are the final results of any of the game's turns, results without a known perspective
+// are taken as reported for black. Moves the annotator marks as mistakes or blunders are listed.
+pub fn html_report<'a>(
+    game: &SgfGame,
+    results: impl IntoIterator<Item = &'a KataResponse>,
+    annotator: &Annotator,
+) -> String {
+    let results = results.into_iter().collect::<Vec<_>>();
+    let review = Review::from_results(results.iter().copied(), &game.moves, game.board_y_size, 1)
+        .annotate(&game.moves, annotator);
+    let mut by_turn = HashMap::new();
+    for result in &results {
+        if let KataResponse::Result { turn_number, .. } = result {
+            by_turn.insert(*turn_number, *result);
+        }
+    }
+
+    let mut board = Some(Board::new(game.board_x_size, game.board_y_size));
+    for (player, point) in &game.initial_stones {
+        board = board.and_then(|mut board| board.place(*player, *point).ok().map(|_| board));
+    }
+    let mut mistakes = Vec::new();
+    for (move_number, (player, played)) in game.moves.iter().enumerate() {
+        let move_number = move_number as u32;
+        let review_move = review.moves.get(&move_number);
+        let annotation = review_move.and_then(|review_move| review_move.annotation);
+        if let Some(annotation @ (Annotation::Mistake | Annotation::Blunder)) = annotation {
+            let best = by_turn.get(&move_number).and_then(|result| match result {
+                KataResponse::Result { move_infos, .. } => {
+                    move_infos.iter().min_by_key(|move_info| move_info.order)
+                }
+                _ => None,
+            });
+            mistakes.push(Mistake {
+                move_number,
+                player: *player,
+                played: *played,
+                annotation,
+                points_lost: review
+                    .loss(move_number, *player)
+                    .map_or(0.0, |loss| loss.points),
+                best: best.map(|best| best.r#move.as_str()),
+                variation: best.map_or(&[], |best| best.pv.as_slice()),
+                diagram: board.clone(),
+            });
+        }
+        // Diagrams stop at the first move the board can't follow
+        board = board.and_then(|mut board| board.play(*player, *played).ok().map(|_| board));
+    }
+
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    html.push_str("<title>Game review</title>\n<style>\n");
+    html.push_str("body { font-family: sans-serif; max-width: 860px; margin: auto; }\n");
+    html.push_str(".mistake { border-top: 1px solid #ccc; padding: 8px 0; }\n");
+    html.push_str("</style>\n</head>\n<body>\n<h1>Game review</h1>\n");
+    writeln!(
+        html,
+        "<p>{}x{}, {}, komi {}, {} moves</p>",
+        game.board_x_size,
+        game.board_y_size,
+        escape(game.ruleset.as_deref().unwrap_or("unknown rules")),
+        game.komi
+            .map_or("unknown".to_owned(), |komi| komi.to_string()),
+        game.moves.len()
+    )
+    .unwrap();
+
+    html.push_str("<h2>Evaluation</h2>\n");
+    html.push_str(&graph(&EvaluationSeries::from_review(
+        &review,
+        Player::Black,
+    )));
+
+    html.push_str("<h2>Mistakes</h2>\n");
+    if mistakes.is_empty() {
+        html.push_str("<p>None found.</p>\n");
+    }
+    for mistake in &mistakes {
+        let player = match mistake.player {
+            Player::Black => "Black",
+            Player::White => "White",
+        };
+        html.push_str("<div class=\"mistake\">\n");
+        writeln!(
+            html,
+            "<h3>Move {} ({player}) {}{}</h3>",
+            mistake.move_number + 1,
+            mistake.played,
+            mistake.annotation
+        )
+        .unwrap();
+        write!(html, "<p>Loses about {:.1} points.", mistake.points_lost).unwrap();
+        if let Some(best) = mistake.best {
+            write!(
+                html,
+                " Best was {} with {}.",
+                escape(best),
+                escape(&mistake.variation.join(" "))
+            )
+            .unwrap();
+        }
+        html.push_str("</p>\n");
+        if let Some(diagram) = &mistake.diagram {
+            let best = mistake.best.and_then(|best| best.parse().ok());
+            html.push_str(&diagram_svg(diagram, mistake.played, best));
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// Black's win rate as a solid line and the score lead as a dashed one, with gaps for turns without
+// results and the position after each mistake circled
+fn graph(series: &EvaluationSeries) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{GRAPH_WIDTH}\" height=\"{GRAPH_HEIGHT}\">\n"
+    );
+    writeln!(
+        svg,
+        "<rect width=\"{GRAPH_WIDTH}\" height=\"{GRAPH_HEIGHT}\" fill=\"#f8f8f8\"/>\n\
+         <line x1=\"0\" y1=\"{0}\" x2=\"{GRAPH_WIDTH}\" y2=\"{0}\" stroke=\"#bbb\"/>",
+        GRAPH_HEIGHT / 2.0
+    )
+    .unwrap();
+    let x = |move_number: u32| {
+        move_number as f32 * GRAPH_WIDTH / (series.points.len().max(2) - 1) as f32
+    };
+    let win_rate_y = |point: &EvaluationPoint| (1.0 - point.win_rate) * GRAPH_HEIGHT;
+    let score_y =
+        |point: &EvaluationPoint| (0.5 - point.score / series.score_range / 2.0) * GRAPH_HEIGHT;
+    for segment in series.segments() {
+        let points = |y: &dyn Fn(&EvaluationPoint) -> f32| {
+            segment
+                .iter()
+                .map(|point| format!("{:.1},{:.1}", x(point.move_number), y(point)))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"#888\" stroke-dasharray=\"4\" points=\"{}\"/>",
+            points(&score_y)
+        )
+        .unwrap();
+        writeln!(
+            svg,
+            "<polyline fill=\"none\" stroke=\"#000\" stroke-width=\"2\" points=\"{}\"/>",
+            points(&win_rate_y)
+        )
+        .unwrap();
+    }
+    for mistake in series.mistakes() {
+        let Some(Some(after)) = series.points.get(mistake.move_number as usize + 1) else {
+            continue;
+        };
+        writeln!(
+            svg,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"#d00\"><title>Move {}</title></circle>",
+            x(after.move_number),
+            win_rate_y(after),
+            after.move_number
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// The position before the mistake, with the played move ringed in red and the best one in blue
+fn diagram_svg(board: &Board, played: Move, best: Option<Move>) -> String {
+    let (x_size, y_size) = (board.x_size() as u32, board.y_size() as u32);
+    let center = |x: u32, y: u32| ((x + 1) * CELL, (y_size - y) * CELL);
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        (x_size + 1) * CELL,
+        (y_size + 1) * CELL
+    );
+    writeln!(
+        svg,
+        "<rect width=\"100%\" height=\"100%\" fill=\"#dcb35c\"/>"
+    )
+    .unwrap();
+    for x in 0..x_size {
+        let (x1, y1) = center(x, 0);
+        let (x2, y2) = center(x, y_size - 1);
+        writeln!(
+            svg,
+            "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"#000\"/>"
+        )
+        .unwrap();
+    }
+    for y in 0..y_size {
+        let (x1, y1) = center(0, y);
+        let (x2, y2) = center(x_size - 1, y);
+        writeln!(
+            svg,
+            "<line x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" stroke=\"#000\"/>"
+        )
+        .unwrap();
+    }
+    for y in 0..y_size {
+        for x in 0..x_size {
+            let Some(player) = board.get(Move::point(x as u8, y as u8)) else {
+                continue;
+            };
+            let (cx, cy) = center(x, y);
+            let fill = match player {
+                Player::Black => "#000",
+                Player::White => "#fff",
+            };
+            writeln!(
+                svg,
+                "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{}\" fill=\"{fill}\" stroke=\"#000\"/>",
+                CELL / 2 - 1
+            )
+            .unwrap();
+        }
+    }
+    for (point, color) in [(Some(played), "#d00"), (best, "#00d")] {
+        let Some((x, y)) = point.and_then(|point| point.coordinates()) else {
+            continue;
+        };
+        let (cx, cy) = center(x as u32, y as u32);
+        writeln!(
+            svg,
+            "<circle cx=\"{cx}\" cy=\"{cy}\" r=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"3\"/>",
+            CELL / 3
+        )
+        .unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
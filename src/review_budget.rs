@@ -0,0 +1,249 @@
+// Full game reviews under a total visit budget. A quick first pass over every turn shows which
+// positions are worth searching: close games with several plausible moves, or where the played
+// move swung the evaluation, get most of the budget, while decided positions and forced moves keep
+// their first pass results. Both passes go through the batch lane.
+
+use std::collections::BTreeMap;
+
+use futures_util::future::try_join_all;
+
+use crate::{Analysis, Client, ClientError, KataQuery, KataResponse, Player, QueryLane};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlannedTurn {
+    pub turn: u32,
+    // From 0 for settled positions, roughly up to 2
+    pub complexity: f32,
+    // Of the second pass, always more than the first pass had. 0 for turns which keep their first
+    // pass result.
+    pub visits: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct ReviewPlan {
+    // In turn order
+    pub turns: Vec<PlannedTurn>,
+    first_pass_visits: u64,
+    // Final first pass result of every turn
+    first_pass: BTreeMap<u32, KataResponse>,
+}
+
+impl ReviewPlan {
+    // Of both passes together
+    pub fn total_visits(&self) -> u64 {
+        let second_pass = self
+            .turns
+            .iter()
+            .filter(|turn| self.is_searched(turn))
+            .map(|turn| turn.visits)
+            .sum::<u64>();
+        self.turns.len() as u64 * self.first_pass_visits + second_pass
+    }
+
+    pub fn first_pass(&self, turn: u32) -> Option<&KataResponse> {
+        self.first_pass.get(&turn)
+    }
+
+    fn is_searched(&self, turn: &PlannedTurn) -> bool {
+        turn.visits > 0
+    }
+}
+
+pub struct ReviewBudget {
+    client: Client,
+    total_visits: u64,
+    first_pass_visits: u64,
+    max_visits: Option<u64>,
+}
+
+impl ReviewBudget {
+    // `total_visits` is for the whole game, both passes included
+    pub fn new(client: Client, total_visits: u64) -> Self {
+        Self {
+            client,
+            total_visits,
+            first_pass_visits: 32,
+            max_visits: None,
+        }
+    }
+
+    // Enough to tell settled positions from open ones
+    pub fn first_pass_visits(mut self, visits: u64) -> Self {
+        self.first_pass_visits = visits.max(1);
+        self
+    }
+
+    // Caps any one turn, so a single fight can't take the whole budget
+    pub fn max_visits(mut self, visits: u64) -> Self {
+        self.max_visits = Some(visits);
+        self
+    }
+
+    // Runs the first pass over the turns `game` analyzes, every turn of its moves without
+    // `analyzeTurns`, and splits what's left of the budget between them
+    pub async fn plan(&self, game: &KataQuery) -> Result<ReviewPlan, ClientError> {
+        let turns = match &game.analyze_turns {
+            Some(turns) => turns.clone(),
+            None => (0..=game.moves.len() as u32).collect(),
+        };
+        let mut query = self.query(game, self.first_pass_visits);
+        query.analyze_turns = Some(turns);
+        let results = self
+            .client
+            .submit_to(QueryLane::Batch, query)?
+            .results()
+            .await?;
+        let first_pass = results
+            .into_iter()
+            .filter_map(|result| Some((result.turn_number()?, result)))
+            .collect::<BTreeMap<_, _>>();
+
+        let analyses = first_pass
+            .iter()
+            .filter_map(|(turn, result)| Some((*turn, Analysis::from_response(result)?)))
+            .collect::<BTreeMap<_, _>>();
+        let complexities = first_pass
+            .keys()
+            .map(|turn| {
+                let next = analyses.get(&(turn + 1));
+                analyses
+                    .get(turn)
+                    .map_or(0.0, |analysis| complexity(analysis, next))
+            })
+            .collect::<Vec<_>>();
+        let spent = first_pass.len() as u64 * self.first_pass_visits;
+        let visits = allocate(
+            &complexities,
+            self.total_visits.saturating_sub(spent),
+            self.first_pass_visits,
+            self.max_visits.unwrap_or(u64::MAX),
+        );
+        let turns = first_pass
+            .keys()
+            .zip(complexities)
+            .zip(visits)
+            .map(|((turn, complexity), visits)| PlannedTurn {
+                turn: *turn,
+                complexity,
+                visits,
+            })
+            .collect();
+        Ok(ReviewPlan {
+            turns,
+            first_pass_visits: self.first_pass_visits,
+            first_pass,
+        })
+    }
+
+    // Searches the turns given second pass visits, the others keep their first pass result. Returns the final result of every turn in turn order.
+    pub async fn run(
+        &self,
+        game: &KataQuery,
+        plan: ReviewPlan,
+    ) -> Result<Vec<KataResponse>, ClientError> {
+        let searched = plan
+            .turns
+            .iter()
+            .filter(|turn| plan.is_searched(turn))
+            .map(|turn| {
+                let mut query = self.query(game, turn.visits);
+                query.analyze_turns = Some(vec![turn.turn]);
+                self.client.submit_to(QueryLane::Batch, query)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let searched = try_join_all(searched.into_iter().map(|handle| handle.result())).await?;
+        let mut results = plan.first_pass;
+        for result in searched {
+            if let Some(turn) = result.turn_number() {
+                results.insert(turn, result);
+            }
+        }
+        Ok(results.into_values().collect())
+    }
+
+    pub async fn review(&self, game: &KataQuery) -> Result<Vec<KataResponse>, ClientError> {
+        let plan = self.plan(game).await?;
+        self.run(game, plan).await
+    }
+
+    fn query(&self, game: &KataQuery, visits: u64) -> KataQuery {
+        let mut query = game.clone();
+        query.id = self.client.next_id();
+        query.max_visits = Some(visits);
+        query.report_during_search_every = None;
+        query.priority = None;
+        query.priorities = None;
+        query
+    }
+}
+
+// How much a deeper search could change: how spread the visits are over the candidates, plus how
+// far the played move moved the winrate, both scaled down as the game gets decided
+fn complexity(analysis: &Analysis, next: Option<&Analysis>) -> f32 {
+    let winrate = analysis.winrate_for(Player::Black);
+    let balance = 4.0 * winrate * (1.0 - winrate);
+    let uncertainty = match analysis.best() {
+        Some(best) if analysis.visits() > 0 => {
+            1.0 - (best.visits() as f32 / analysis.visits() as f32).min(1.0)
+        }
+        _ => 0.0,
+    };
+    let swing = next.map_or(0.0, |next| {
+        (next.winrate_for(Player::Black) - winrate).abs()
+    });
+    balance * (uncertainty + swing)
+}
+
+// Visits proportional to complexity, turns reaching `max` give the rest back to the others. A
+// second pass of no more than `first_pass` visits wouldn't improve on the first, so the least
+// complex turns are left out until every remaining one gets more, and the whole budget goes to
+// turns which are searched.
+fn allocate(complexities: &[f32], budget: u64, first_pass: u64, max: u64) -> Vec<u64> {
+    let mut open = (0..complexities.len())
+        .filter(|&i| complexities[i] > 0.0)
+        .collect::<Vec<_>>();
+    if open.is_empty() {
+        open = (0..complexities.len()).collect();
+    }
+    open.sort_by(|&a, &b| complexities[b].total_cmp(&complexities[a]));
+    while let Some(&least) = open.last() {
+        let visits = split(complexities, &open, budget, max);
+        if visits[least] > first_pass {
+            return visits;
+        }
+        open.pop();
+    }
+    vec![0; complexities.len()]
+}
+
+fn split(complexities: &[f32], turns: &[usize], budget: u64, max: u64) -> Vec<u64> {
+    let mut visits = vec![0; complexities.len()];
+    let mut open = turns.to_vec();
+    let mut left = budget;
+    while left > 0 && !open.is_empty() {
+        let total = open.iter().map(|&i| complexities[i] as f64).sum::<f64>();
+        let share = |i: usize| {
+            if total > 0.0 {
+                left as f64 * complexities[i] as f64 / total
+            } else {
+                left as f64 / open.len() as f64
+            }
+        };
+        let mut given = 0;
+        for &i in &open {
+            let add = (share(i) as u64).min(max - visits[i]);
+            visits[i] += add;
+            given += add;
+        }
+        open.retain(|&i| visits[i] < max);
+        if given == 0 {
+            // What rounding left over, one visit each to the most complex turns
+            for &i in open.iter().take(left as usize) {
+                visits[i] += 1;
+            }
+            break;
+        }
+        left -= given;
+    }
+    visits
+}
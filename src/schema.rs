@@ -0,0 +1,92 @@
+// Versioned storage of results, so data written by older releases of the crate stays readable
+// after `KataResponse` changes. Stored results are wrapped as
+// `{"schemaVersion":1,"results":[...]}`, older data is migrated step by step on reading. When the
+// serialized form of a response changes, bump `SCHEMA_VERSION` and append the step turning the
+// previous version into the new one to `MIGRATIONS`.
+
+use std::error::Error;
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use crate::KataResponse;
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+// `MIGRATIONS[n]` turns version n into version n + 1
+const MIGRATIONS: [fn(Value) -> Result<Value, SchemaError>; SCHEMA_VERSION as usize] = [wrap];
+
+#[derive(Debug)]
+pub enum SchemaError {
+    // Written by a later release of the crate, which this one can't read
+    Newer(u32),
+    Malformed(&'static str),
+    Serde(serde_json::Error),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaError::Newer(version) => write!(
+                f,
+                "schema version {version} is newer than the supported {SCHEMA_VERSION}"
+            ),
+            SchemaError::Malformed(reason) => write!(f, "malformed stored results: {reason}"),
+            SchemaError::Serde(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for SchemaError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SchemaError::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub fn stamp_results(results: &[KataResponse]) -> Result<Value, SchemaError> {
+    let results = serde_json::to_value(results).map_err(SchemaError::Serde)?;
+    Ok(json!({ "schemaVersion": SCHEMA_VERSION, "results": results }))
+}
+
+pub fn read_results(value: Value) -> Result<Vec<KataResponse>, SchemaError> {
+    let Value::Object(mut entries) = migrate_results(value)? else {
+        unreachable!("migrations end with the wrapped form");
+    };
+    let results = entries
+        .remove("results")
+        .ok_or(SchemaError::Malformed("no results"))?;
+    serde_json::from_value(results).map_err(SchemaError::Serde)
+}
+
+// Unversioned data, a bare array of results, is version 0
+pub fn schema_version(value: &Value) -> Result<u32, SchemaError> {
+    match value {
+        Value::Array(_) => Ok(0),
+        Value::Object(entries) => entries
+            .get("schemaVersion")
+            .and_then(Value::as_u64)
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or(SchemaError::Malformed("no schema version")),
+        _ => Err(SchemaError::Malformed("neither an array nor an object")),
+    }
+}
+
+// Brings stored data to `SCHEMA_VERSION`, data already at it is returned as is
+pub fn migrate_results(mut value: Value) -> Result<Value, SchemaError> {
+    let version = schema_version(&value)?;
+    if version > SCHEMA_VERSION {
+        return Err(SchemaError::Newer(version));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+// Version 0 to 1, results were stored without any wrapper before
+fn wrap(value: Value) -> Result<Value, SchemaError> {
+    Ok(json!({ "schemaVersion": 1, "results": value }))
+}
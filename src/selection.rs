@@ -0,0 +1,66 @@
+// Picking and ranking moves of a result the way katago does. Values are compared as reported, so
+// they have to be from the side to move's point of view, katago's `SIDETOMOVE` reporting. Ties
+// go to the move with more visits, then to katago's own order.
+
+use std::cmp::Ordering;
+
+use crate::MoveInfo;
+
+// katago's default minVisitPropForLCB: moves with fewer visits than this share of the most visited
+// move's aren't picked by their LCB, their bounds are mostly noise
+const MIN_VISIT_SHARE_FOR_LCB: f64 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Visits,
+    Winrate,
+    Lcb,
+    ScoreLead,
+}
+
+pub trait MoveInfos {
+    // The move katago would play, by utility LCB among the well visited moves
+    fn best_by_lcb(&self) -> Option<&MoveInfo>;
+    // Best first
+    fn sorted_by(&self, key: SortKey) -> Vec<&MoveInfo>;
+    fn with_min_visits(&self, min_visits: u64) -> Vec<&MoveInfo>;
+}
+
+impl MoveInfos for [MoveInfo] {
+    fn best_by_lcb(&self) -> Option<&MoveInfo> {
+        let max_visits = self.iter().map(|move_info| move_info.visits).max()?;
+        self.iter()
+            .filter(|move_info| {
+                move_info.visits as f64 >= max_visits as f64 * MIN_VISIT_SHARE_FOR_LCB
+            })
+            .min_by(|a, b| {
+                b.utility_lcb
+                    .total_cmp(&a.utility_lcb)
+                    .then_with(|| tie_break(a, b))
+            })
+    }
+
+    fn sorted_by(&self, key: SortKey) -> Vec<&MoveInfo> {
+        let mut sorted = self.iter().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| {
+            let by_key = match key {
+                SortKey::Visits => b.visits.cmp(&a.visits),
+                SortKey::Winrate => b.winrate.total_cmp(&a.winrate),
+                SortKey::Lcb => b.lcb.total_cmp(&a.lcb),
+                SortKey::ScoreLead => b.score_lead.total_cmp(&a.score_lead),
+            };
+            by_key.then_with(|| tie_break(a, b))
+        });
+        sorted
+    }
+
+    fn with_min_visits(&self, min_visits: u64) -> Vec<&MoveInfo> {
+        self.iter()
+            .filter(|move_info| move_info.visits >= min_visits)
+            .collect()
+    }
+}
+
+fn tie_break(a: &MoveInfo, b: &MoveInfo) -> Ordering {
+    b.visits.cmp(&a.visits).then(a.order.cmp(&b.order))
+}
@@ -0,0 +1,74 @@
+// Per-move win rate and score series of a reviewed game, shaped for plotting: values from one
+// player's point of view, gaps where turns weren't analyzed and a symmetric score axis.
+
+use crate::ogs::Review;
+use crate::{Annotation, Player};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EvaluationPoint {
+    pub move_number: u32,
+    pub win_rate: f32,
+    pub score: f32,
+    // Of the move played from this position
+    pub annotation: Option<Annotation>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EvaluationSeries {
+    pub perspective: Player,
+    // Indexed by move number, None for turns without a result
+    pub points: Vec<Option<EvaluationPoint>>,
+    // The score axis goes from minus this to this, in steps of 5 points
+    pub score_range: f32,
+}
+
+impl EvaluationSeries {
+    pub fn from_review(review: &Review, perspective: Player) -> Self {
+        let len = review
+            .moves
+            .keys()
+            .next_back()
+            .map_or(0, |last| *last as usize + 1);
+        let mut points = vec![None; len];
+        for review_move in review.moves.values() {
+            let (win_rate, score) = match perspective {
+                Player::Black => (review_move.win_rate, review_move.score),
+                Player::White => (1.0 - review_move.win_rate, -review_move.score),
+            };
+            points[review_move.move_number as usize] = Some(EvaluationPoint {
+                move_number: review_move.move_number,
+                win_rate,
+                score,
+                annotation: review_move.annotation,
+            });
+        }
+        let max_score = points
+            .iter()
+            .flatten()
+            .fold(0.0f32, |max, point| max.max(point.score.abs()));
+        Self {
+            perspective,
+            points,
+            score_range: ((max_score / 5.0).ceil() * 5.0).max(5.0),
+        }
+    }
+
+    // Runs of consecutive analyzed turns, to be drawn as separate lines
+    pub fn segments(&self) -> Vec<Vec<EvaluationPoint>> {
+        self.points
+            .split(Option::is_none)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| segment.iter().flatten().copied().collect())
+            .collect()
+    }
+
+    // Points whose move is a mistake or a blunder
+    pub fn mistakes(&self) -> impl Iterator<Item = &EvaluationPoint> {
+        self.points.iter().flatten().filter(|point| {
+            matches!(
+                point.annotation,
+                Some(Annotation::Mistake | Annotation::Blunder)
+            )
+        })
+    }
+}
@@ -0,0 +1,98 @@
+// Many games analyzed through one client, e.g. a bot playing 20 games at once. Each game gets its
+// own session with its own query defaults and priority, and the session keeps track of the
+// queries submitted through it, so a game's queries can be listed and cancelled together when it
+// ends or a player resigns.
+
+use std::sync::{Arc, Mutex};
+
+use futures_util::future::try_join_all;
+
+use crate::{Client, ClientError, KataQuery, KataQueryBuilder, Priority, QueryHandle, QueryId};
+
+#[derive(Default)]
+pub(crate) struct SessionState {
+    defaults: Option<KataQueryBuilder>,
+    priority: Option<Priority>,
+    queries: Vec<QueryId>,
+}
+
+// A handle to a session, clones refer to the same one
+#[derive(Clone)]
+pub struct GameSession {
+    client: Client,
+    name: String,
+    state: Arc<Mutex<SessionState>>,
+}
+
+impl GameSession {
+    pub(crate) fn new(client: Client, name: String, state: Arc<Mutex<SessionState>>) -> Self {
+        Self {
+            client,
+            name,
+            state,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    // What `query` starts from instead of the client's defaults
+    pub fn query_defaults(self, defaults: KataQueryBuilder) -> Self {
+        self.state.lock().unwrap().defaults = Some(defaults);
+        self
+    }
+
+    // Given to the session's queries which don't have their own
+    pub fn priority(self, priority: Priority) -> Self {
+        self.state.lock().unwrap().priority = Some(priority);
+        self
+    }
+
+    // A builder with the session's defaults, or the client's, and a fresh id
+    pub fn query(&self) -> KataQueryBuilder {
+        let defaults = self.state.lock().unwrap().defaults.clone();
+        match defaults {
+            Some(mut query) => {
+                query.id(self.client.next_id());
+                query
+            }
+            None => self.client.query(),
+        }
+    }
+
+    pub fn submit(&self, mut query: KataQuery) -> Result<QueryHandle, ClientError> {
+        let mut state = self.state.lock().unwrap();
+        if query.priority.is_none() {
+            query.priority = state.priority;
+        }
+        let id = query.id.clone();
+        let handle = self.client.submit(query)?;
+        state.queries.retain(|id| self.client.is_outstanding(id));
+        state.queries.push(id);
+        Ok(handle)
+    }
+
+    // The session's queries still in flight, oldest first
+    pub fn queries(&self) -> Vec<QueryId> {
+        let mut state = self.state.lock().unwrap();
+        state.queries.retain(|id| self.client.is_outstanding(id));
+        state.queries.clone()
+    }
+
+    // Terminates every query of the session still in flight and returns their ids. Their handles
+    // end without final results.
+    pub async fn cancel_all(&self) -> Result<Vec<QueryId>, ClientError> {
+        let mut queries = std::mem::take(&mut self.state.lock().unwrap().queries);
+        queries.retain(|id| self.client.is_outstanding(id));
+        try_join_all(queries.iter().map(|id| self.client.terminate(id, None))).await?;
+        Ok(queries)
+    }
+
+    // Cancels what's still in flight and forgets the session, other handles to it keep working
+    // but aren't listed by `Client::sessions` anymore
+    pub async fn close(self) -> Result<Vec<QueryId>, ClientError> {
+        self.client.forget_session(&self.name);
+        self.cancel_all().await
+    }
+}
@@ -0,0 +1,550 @@
+// Import of games from SGF. `SgfGame` reads one line of play, the main line unless a branch is
+// picked, and only keeps what a query needs: the board size, rules, komi, handicap, setup stones
+// and moves. `SgfCollection` keeps whole files, with every game tree and variation and all
+// properties, so annotations can be added and written back without losing anything.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{KataQuery, KataQueryBuilder, Move, Player, QueryId, Rules};
+
+// Trees are parsed recursively, so deeper nesting is rejected before it can overflow the stack,
+// even a 2 MiB one of a runtime worker in a debug build. Editors nest a tree per branch point, far
+// fewer than this along any one line.
+const MAX_NESTING: usize = 256;
+
+#[derive(Debug, Clone)]
+pub enum SgfError {
+    Syntax(usize),
+    InvalidProperty { property: String, value: String },
+    // Setup stones anywhere but the first node can't be expressed in a query
+    SetupAfterRoot,
+    // Variations nested deeper than `MAX_NESTING`, at the byte of the `(` too many
+    TooDeep(usize),
+    // A path picking a variation the node doesn't have
+    NoSuchVariation(Vec<usize>),
+}
+
+impl fmt::Display for SgfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SgfError::Syntax(offset) => write!(f, "SGF syntax error at byte {offset}"),
+            SgfError::InvalidProperty { property, value } => {
+                write!(f, "invalid SGF property {property}[{value}]")
+            }
+            SgfError::SetupAfterRoot => f.write_str("setup stones after the first node"),
+            SgfError::TooDeep(offset) => write!(
+                f,
+                "SGF variations nested deeper than {MAX_NESTING} at byte {offset}"
+            ),
+            SgfError::NoSuchVariation(path) => write!(f, "no SGF variation at {path:?}"),
+        }
+    }
+}
+
+impl Error for SgfError {}
+
+#[derive(Debug, Clone)]
+pub struct SgfGame {
+    pub board_x_size: u8,
+    pub board_y_size: u8,
+    // `None` when RU is missing or names rules katago doesn't have, `ruleset` keeps the original
+    pub rules: Option<Rules>,
+    pub ruleset: Option<String>,
+    pub komi: Option<f32>,
+    pub handicap: u8,
+    pub initial_stones: Vec<(Player, Move)>,
+    pub initial_player: Option<Player>,
+    pub moves: Vec<(Player, Move)>,
+}
+
+impl SgfGame {
+    // The main line of the first game in the file
+    pub fn parse(sgf: &str) -> Result<Self, SgfError> {
+        let collection = SgfCollection::parse(sgf)?;
+        let root = collection.games.first().ok_or(SgfError::Syntax(0))?;
+        root.game(&[])
+    }
+
+    fn from_line(nodes: &[&Node]) -> Result<Self, SgfError> {
+        let root = nodes.first().ok_or(SgfError::Syntax(0))?;
+
+        let (board_x_size, board_y_size) = match property(root, "SZ") {
+            Some(size) => parse_size(size).ok_or_else(|| invalid("SZ", size))?,
+            None => (19, 19),
+        };
+        let ruleset = property(root, "RU").map(str::to_owned);
+        let rules = ruleset.as_deref().and_then(parse_rules);
+        let komi = property(root, "KM")
+            .map(|komi| komi.trim().parse().map_err(|_| invalid("KM", komi)))
+            .transpose()?;
+        let handicap = property(root, "HA")
+            .map(|handicap| handicap.trim().parse().map_err(|_| invalid("HA", handicap)))
+            .transpose()?
+            .unwrap_or(0);
+        let initial_player = property(root, "PL")
+            .map(|player| player.parse().map_err(|_| invalid("PL", player)))
+            .transpose()?;
+
+        let mut initial_stones = Vec::new();
+        let mut moves = Vec::new();
+        for (index, node) in nodes.iter().enumerate() {
+            for (name, values) in node.iter() {
+                let player = match name.as_str() {
+                    "B" | "AB" => Player::Black,
+                    "W" | "AW" => Player::White,
+                    "AE" if index > 0 => return Err(SgfError::SetupAfterRoot),
+                    _ => continue,
+                };
+                if name.starts_with('A') {
+                    if index > 0 {
+                        return Err(SgfError::SetupAfterRoot);
+                    }
+                    for value in values {
+                        for point in parse_points(value, board_x_size, board_y_size)
+                            .ok_or_else(|| invalid(name, value))?
+                        {
+                            initial_stones.push((player, point));
+                        }
+                    }
+                } else {
+                    let value = values.first().map_or("", String::as_str);
+                    let played = Move::from_sgf(value, board_x_size, board_y_size)
+                        .ok_or_else(|| invalid(name, value))?;
+                    moves.push((player, played));
+                }
+            }
+        }
+
+        Ok(Self {
+            board_x_size,
+            board_y_size,
+            rules,
+            ruleset,
+            komi,
+            handicap,
+            initial_stones,
+            initial_player,
+            moves,
+        })
+    }
+
+    // Everything the game determines, the caller still has to set the rules if they weren't
+    // recognized. Handicap games get the ruleset's white handicap bonus, SGF has no way to say
+    // whether white was compensated.
+    pub fn query_builder(&self, id: impl Into<QueryId>) -> KataQueryBuilder {
+        let mut builder = KataQuery::builder();
+        builder
+            .id(id.into())
+            .board_x_size(self.board_x_size)
+            .board_y_size(self.board_y_size)
+            .moves(self.moves.clone());
+        if !self.initial_stones.is_empty() {
+            builder.initial_stones(self.initial_stones.clone());
+        }
+        if let Some(rules) = self.rules {
+            builder.rules(rules);
+            if self.handicap >= 2 {
+                builder.white_handicap_bonus(rules.white_handicap_bonus());
+            }
+        }
+        if let Some(komi) = self.komi {
+            builder.komi(komi);
+        }
+        // Without moves katago can't tell white is to play after the handicap stones
+        let initial_player = self
+            .initial_player
+            .or_else(|| (self.handicap >= 2 && self.moves.is_empty()).then_some(Player::White));
+        if let Some(initial_player) = initial_player {
+            builder.initial_player(initial_player);
+        }
+        builder
+    }
+}
+
+// Every game tree of a file, in order. Club records often hold a whole tournament in one file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SgfCollection {
+    pub games: Vec<SgfNode>,
+}
+
+// A node and the variations following it, the first child is the main line
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SgfNode {
+    properties: Node,
+    children: Vec<SgfNode>,
+}
+
+impl SgfCollection {
+    pub fn parse(sgf: &str) -> Result<Self, SgfError> {
+        let sgf = sgf.as_bytes();
+        let mut games = Vec::new();
+        let mut pos = 0;
+        loop {
+            while sgf.get(pos).is_some_and(u8::is_ascii_whitespace) {
+                pos += 1;
+            }
+            match sgf.get(pos) {
+                None if !games.is_empty() => return Ok(Self { games }),
+                Some(b'(') => {
+                    let (root, end) = parse_tree(sgf, pos + 1, 1)?;
+                    games.push(root);
+                    pos = end;
+                }
+                // Anything before the first tree is ignored, like a mail header
+                Some(_) if games.is_empty() => pos += 1,
+                _ => return Err(SgfError::Syntax(pos)),
+            }
+        }
+    }
+
+    // The line following `path` in the `index`th game, see `SgfNode::game`
+    pub fn game(&self, index: usize, path: &[usize]) -> Option<Result<SgfGame, SgfError>> {
+        Some(self.games.get(index)?.game(path))
+    }
+}
+
+// Writes the collection back, keeping every variation
+impl fmt::Display for SgfCollection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for game in &self.games {
+            writeln!(f, "({game})")?;
+        }
+        Ok(())
+    }
+}
+
+impl SgfNode {
+    pub fn property(&self, name: &str) -> Option<&str> {
+        property(&self.properties, name)
+    }
+
+    pub fn properties(&self) -> &[(String, Vec<String>)] {
+        &self.properties
+    }
+
+    // Replaces the property's values, or adds it
+    pub fn set_property(&mut self, name: impl Into<String>, values: Vec<String>) {
+        let name = name.into();
+        match self
+            .properties
+            .iter_mut()
+            .find(|(property, _)| *property == name)
+        {
+            Some((_, old)) => *old = values,
+            None => self.properties.push((name, values)),
+        }
+    }
+
+    pub fn remove_property(&mut self, name: &str) {
+        self.properties.retain(|(property, _)| property != name);
+    }
+
+    pub fn children(&self) -> &[SgfNode] {
+        &self.children
+    }
+
+    pub fn children_mut(&mut self) -> &mut [SgfNode] {
+        &mut self.children
+    }
+
+    // A path picks a child at every node with variations, from the root down. Branch points past
+    // its end follow the main line, so `[]` is the main line and `[1]` its first variation. None
+    // if it picks a variation that doesn't exist.
+    pub fn line(&self, path: &[usize]) -> Option<Vec<&SgfNode>> {
+        let mut line = vec![self];
+        let mut path = path.iter();
+        let mut node = self;
+        while !node.children.is_empty() {
+            let choice = if node.children.len() > 1 {
+                path.next().copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let child = node.children.get(choice)?;
+            line.push(child);
+            node = child;
+        }
+        Some(line)
+    }
+
+    // The node `depth` nodes down the line following `path`, the root is at 0. For attaching
+    // comments or move annotations to the move a result is for.
+    pub fn line_node_mut(&mut self, path: &[usize], depth: usize) -> Option<&mut SgfNode> {
+        let mut path = path.iter();
+        let mut node = self;
+        for _ in 0..depth {
+            let choice = if node.children.len() > 1 {
+                path.next().copied().unwrap_or(0)
+            } else {
+                0
+            };
+            node = node.children.get_mut(choice)?;
+        }
+        Some(node)
+    }
+
+    // The path to every leaf, main line first
+    pub fn paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = Vec::new();
+        let mut stack = vec![(self, Vec::new())];
+        while let Some((node, path)) = stack.pop() {
+            match node.children.len() {
+                0 => paths.push(path),
+                1 => stack.push((&node.children[0], path)),
+                _ => {
+                    for (choice, child) in node.children.iter().enumerate().rev() {
+                        let mut path = path.clone();
+                        path.push(choice);
+                        stack.push((child, path));
+                    }
+                }
+            }
+        }
+        paths
+    }
+
+    // The game along `path`, this node being its root
+    pub fn game(&self, path: &[usize]) -> Result<SgfGame, SgfError> {
+        let line = self
+            .line(path)
+            .ok_or_else(|| SgfError::NoSuchVariation(path.to_vec()))?
+            .into_iter()
+            .map(|node| &node.properties)
+            .collect::<Vec<_>>();
+        SgfGame::from_line(&line)
+    }
+}
+
+impl fmt::Display for SgfNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut node = self;
+        loop {
+            f.write_str(";")?;
+            for (name, values) in &node.properties {
+                f.write_str(name)?;
+                for value in values {
+                    write!(f, "[{}]", value.replace('\\', "\\\\").replace(']', "\\]"))?;
+                }
+            }
+            match &node.children[..] {
+                [] => return Ok(()),
+                [child] => node = child,
+                children => {
+                    for child in children {
+                        write!(f, "({child})")?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// Real-world RU values vary a lot between servers and editors, e.g. `jp`, `Japanese`, `chinese`
+// or `New Zealand`
+fn parse_rules(ruleset: &str) -> Option<Rules> {
+    let ruleset = ruleset.trim().to_ascii_lowercase().replace(['-', '_'], " ");
+    let rules = match ruleset.as_str() {
+        "japanese" | "jp" | "jpn" | "japan" => Rules::Japanese,
+        "chinese" | "cn" | "chn" | "china" => Rules::Chinese,
+        "korean" | "ko" | "kr" | "korea" => Rules::Korean,
+        "aga" | "american" => Rules::Aga,
+        "bga" | "british" => Rules::Bga,
+        "nz" | "new zealand" => Rules::NewZealand,
+        "tromp taylor" | "tt" => Rules::TrompTaylor,
+        "chinese ogs" => Rules::ChineseOgs,
+        "chinese kgs" => Rules::ChineseKgs,
+        "stone scoring" => Rules::StoneScoring,
+        "aga button" => Rules::AgaButton,
+        _ => return None,
+    };
+    Some(rules)
+}
+
+fn parse_size(size: &str) -> Option<(u8, u8)> {
+    let (x, y) = size.split_once(':').unwrap_or((size, size));
+    let (x, y) = (x.trim().parse().ok()?, y.trim().parse().ok()?);
+    (x > 0 && y > 0).then_some((x, y))
+}
+
+// Point lists can compress rectangles, `aa:cc` is all nine points from `aa` to `cc`
+fn parse_points(value: &str, board_x_size: u8, board_y_size: u8) -> Option<Vec<Move>> {
+    let point = |value: &str| match Move::from_sgf(value, board_x_size, board_y_size)? {
+        Move::Point { x, y } => Some((x, y)),
+        Move::Pass => None,
+    };
+    let (from, to) = match value.split_once(':') {
+        Some((from, to)) => (point(from)?, point(to)?),
+        None => (point(value)?, point(value)?),
+    };
+    let mut points = Vec::new();
+    for x in from.0.min(to.0)..=from.0.max(to.0) {
+        for y in from.1.min(to.1)..=from.1.max(to.1) {
+            points.push(Move::point(x, y));
+        }
+    }
+    Some(points)
+}
+
+fn invalid(property: &str, value: &str) -> SgfError {
+    SgfError::InvalidProperty {
+        property: property.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+type Node = Vec<(String, Vec<String>)>;
+
+fn property<'a>(node: &'a Node, name: &str) -> Option<&'a str> {
+    node.iter()
+        .find(|(property, _)| property == name)
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+// Parses the tree whose `(` is just before `pos`, returns its first node and the position after
+// its `)`. Nodes in sequence become each other's only child, nested trees the last one's children.
+// `depth` counts the trees it's nested in, itself included.
+fn parse_tree(sgf: &[u8], mut pos: usize, depth: usize) -> Result<(SgfNode, usize), SgfError> {
+    if depth > MAX_NESTING {
+        return Err(SgfError::TooDeep(pos - 1));
+    }
+    let mut sequence: Vec<SgfNode> = Vec::new();
+    let mut variations = Vec::new();
+    loop {
+        match sgf.get(pos) {
+            Some(b')') => {
+                pos += 1;
+                break;
+            }
+            Some(b'(') => {
+                let (variation, end) = parse_tree(sgf, pos + 1, depth + 1)?;
+                variations.push(variation);
+                pos = end;
+            }
+            Some(b';') if variations.is_empty() => {
+                pos += 1;
+                sequence.push(SgfNode::default());
+            }
+            Some(byte) if byte.is_ascii_whitespace() => pos += 1,
+            Some(byte) if byte.is_ascii_alphabetic() => {
+                let node = sequence.last_mut().ok_or(SgfError::Syntax(pos))?;
+                let start = pos;
+                while sgf.get(pos).is_some_and(u8::is_ascii_alphabetic) {
+                    pos += 1;
+                }
+                // FF[3] allowed lowercase letters in names, e.g. `AddBlack` for `AB`
+                let name = sgf[start..pos]
+                    .iter()
+                    .filter(|byte| byte.is_ascii_uppercase())
+                    .map(|byte| *byte as char)
+                    .collect();
+                let mut values = Vec::new();
+                loop {
+                    while sgf.get(pos).is_some_and(u8::is_ascii_whitespace) {
+                        pos += 1;
+                    }
+                    if sgf.get(pos) != Some(&b'[') {
+                        break;
+                    }
+                    let (value, end) = parse_value(sgf, pos + 1).ok_or(SgfError::Syntax(pos))?;
+                    values.push(value);
+                    pos = end;
+                }
+                if values.is_empty() {
+                    return Err(SgfError::Syntax(pos));
+                }
+                node.properties.push((name, values));
+            }
+            _ => return Err(SgfError::Syntax(pos)),
+        }
+    }
+    let mut node = sequence.pop().ok_or(SgfError::Syntax(pos))?;
+    node.children = variations;
+    while let Some(mut parent) = sequence.pop() {
+        parent.children = vec![node];
+        node = parent;
+    }
+    Ok((node, pos))
+}
+
+// Returns the unescaped value starting at `pos` and the position after its `]`
+fn parse_value(sgf: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut value = Vec::new();
+    loop {
+        match *sgf.get(pos)? {
+            b']' => return Some((String::from_utf8_lossy(&value).into_owned(), pos + 1)),
+            b'\\' => {
+                pos += 1;
+                match *sgf.get(pos)? {
+                    // Escaped line breaks are soft, they're removed
+                    b'\n' | b'\r' => {}
+                    byte => value.push(byte),
+                }
+            }
+            byte => value.push(byte),
+        }
+        pos += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_square_size() {
+        let game = SgfGame::parse("(;GM[1]SZ[5:3]AB[aa][ec];B[ea];W[ac];B[])").unwrap();
+        assert_eq!((game.board_x_size, game.board_y_size), (5, 3));
+        assert_eq!(
+            game.initial_stones,
+            vec![
+                (Player::Black, Move::point(0, 2)),
+                (Player::Black, Move::point(4, 0)),
+            ]
+        );
+        assert_eq!(
+            game.moves,
+            vec![
+                (Player::Black, Move::point(4, 2)),
+                (Player::White, Move::point(0, 0)),
+                (Player::Black, Move::Pass),
+            ]
+        );
+    }
+
+    #[test]
+    fn square_and_invalid_sizes() {
+        let game = SgfGame::parse("(;GM[1]SZ[13])").unwrap();
+        assert_eq!((game.board_x_size, game.board_y_size), (13, 13));
+        assert!(SgfGame::parse("(;GM[1]SZ[0:3])").is_err());
+        assert!(SgfGame::parse("(;GM[1]SZ[5:x])").is_err());
+        // Off the 5x3 board
+        assert!(SgfGame::parse("(;GM[1]SZ[5:3];B[ad])").is_err());
+    }
+
+    #[test]
+    fn variation_paths() {
+        let collection = SgfCollection::parse("(;GM[1]SZ[9];B[aa](;W[bb])(;W[cc];B[dd]))").unwrap();
+        let moves = |path: &[usize]| {
+            collection
+                .game(0, path)
+                .unwrap()
+                .map(|game| game.moves.len())
+        };
+        assert_eq!(moves(&[]).unwrap(), 2);
+        assert_eq!(moves(&[1]).unwrap(), 3);
+        assert!(matches!(moves(&[2]), Err(SgfError::NoSuchVariation(_))));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| format!("{}{}", "(;C[x]".repeat(depth), ")".repeat(depth));
+        assert!(SgfCollection::parse(&nested(MAX_NESTING)).is_ok());
+        assert!(matches!(
+            SgfCollection::parse(&nested(MAX_NESTING + 1)),
+            Err(SgfError::TooDeep(_))
+        ));
+    }
+}
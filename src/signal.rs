@@ -0,0 +1,40 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use crate::Client;
+
+// Completes on the first Ctrl-C, or SIGTERM on unix. Handlers are installed right away, which
+// replaces the default behaviour of exiting the process.
+pub fn shutdown_signal() -> io::Result<impl Future<Output = ()> + Send + 'static> {
+    #[cfg(unix)]
+    {
+        use std::pin::pin;
+
+        use futures_util::future::select;
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        Ok(async move {
+            select(pin!(interrupt.recv()), pin!(terminate.recv())).await;
+        })
+    }
+    #[cfg(not(unix))]
+    {
+        Ok(async {
+            if tokio::signal::ctrl_c().await.is_err() {
+                futures_util::future::pending::<()>().await;
+            }
+        })
+    }
+}
+
+impl Client {
+    // Drains the client on Ctrl-C or SIGTERM, so running queries deliver their (partial) results
+    // and the engine is reaped instead of being left running
+    pub fn shutdown_on_signal(&self, deadline: Option<Duration>) -> io::Result<()> {
+        self.shutdown_on(shutdown_signal()?, deadline);
+        Ok(())
+    }
+}
@@ -0,0 +1,397 @@
+// Analysis as applications see it, kept stable across katago releases. `KataResponse` and friends
+// mirror katago's JSON and change whenever it does, these are built from them and only grow new
+// accessors. Values are kept from black's point of view whatever the engine reported them as, so
+// they can be compared across turns without knowing how the engine was configured.
+
+use crate::{
+    ClientError, KataQuery, KataQueryBuilder, KataResponse, KomiValue, Move, MoveInfo, Player,
+    QueryHandle, QueryId, ReportAnalysisWinratesAs, RootInfo, Rules,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Position {
+    board_x_size: u8,
+    board_y_size: u8,
+    rules: Rules,
+    komi: Option<KomiValue>,
+    initial_stones: Vec<(Player, Move)>,
+    initial_player: Option<Player>,
+    moves: Vec<(Player, Move)>,
+}
+
+impl Position {
+    pub fn new(board_x_size: u8, board_y_size: u8, rules: Rules) -> Self {
+        Self {
+            board_x_size,
+            board_y_size,
+            rules,
+            komi: None,
+            initial_stones: Vec::new(),
+            initial_player: None,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn komi(mut self, komi: KomiValue) -> Self {
+        self.komi = Some(komi);
+        self
+    }
+
+    pub fn initial_stones(mut self, stones: impl Into<Vec<(Player, Move)>>) -> Self {
+        self.initial_stones = stones.into();
+        self
+    }
+
+    pub fn initial_player(mut self, player: Player) -> Self {
+        self.initial_player = Some(player);
+        self
+    }
+
+    pub fn moves(mut self, moves: impl Into<Vec<(Player, Move)>>) -> Self {
+        self.moves = moves.into();
+        self
+    }
+
+    pub fn board_size(&self) -> (u8, u8) {
+        (self.board_x_size, self.board_y_size)
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    // None leaves it to katago's default for the rules
+    pub fn komi_value(&self) -> Option<KomiValue> {
+        self.komi
+    }
+
+    pub fn stones(&self) -> &[(Player, Move)] {
+        &self.initial_stones
+    }
+
+    pub fn played(&self) -> &[(Player, Move)] {
+        &self.moves
+    }
+
+    pub fn player_to_move(&self) -> Player {
+        self.moves
+            .last()
+            .map(|(last, _)| last.opponent())
+            .or(self.initial_player)
+            .unwrap_or(Player::Black)
+    }
+
+    // A builder for querying the position, everything else about the query can still be set
+    pub fn query(&self, id: impl Into<QueryId>) -> KataQueryBuilder {
+        let mut builder = KataQuery::builder();
+        builder
+            .id(id.into())
+            .rules(self.rules)
+            .board_x_size(self.board_x_size)
+            .board_y_size(self.board_y_size)
+            .moves(self.moves.clone())
+            .komi(self.komi.map(f32::from))
+            .initial_player(self.initial_player);
+        if !self.initial_stones.is_empty() {
+            builder.initial_stones(Some(self.initial_stones.clone()));
+        }
+        builder
+    }
+}
+
+// Komi a query couldn't have been sent with is left out
+impl From<&KataQuery> for Position {
+    fn from(query: &KataQuery) -> Self {
+        Self {
+            board_x_size: query.board_x_size,
+            board_y_size: query.board_y_size,
+            rules: query.rules,
+            komi: query.komi.and_then(|komi| KomiValue::new(komi).ok()),
+            initial_stones: query.initial_stones.clone().unwrap_or_default(),
+            initial_player: query.initial_player,
+            moves: query.moves.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CandidateMove {
+    point: Move,
+    visits: u64,
+    // Black's
+    winrate: f32,
+    score_lead: f32,
+    prior: f32,
+    order: u16,
+    pv: Vec<Move>,
+}
+
+impl CandidateMove {
+    pub fn point(&self) -> Move {
+        self.point
+    }
+
+    pub fn visits(&self) -> u64 {
+        self.visits
+    }
+
+    pub fn winrate_for(&self, player: Player) -> f32 {
+        for_player(self.winrate, player, |winrate| 1.0 - winrate)
+    }
+
+    pub fn score_lead_for(&self, player: Player) -> f32 {
+        for_player(self.score_lead, player, |lead| -lead)
+    }
+
+    // The raw policy for the move
+    pub fn prior(&self) -> f32 {
+        self.prior
+    }
+
+    // Katago's ranking, 0 for its best move
+    pub fn order(&self) -> u16 {
+        self.order
+    }
+
+    pub fn pv(&self) -> &[Move] {
+        &self.pv
+    }
+}
+
+// The net's evaluation of the root before any search, as opposed to the searched values of
+// `Analysis`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawEvaluation {
+    // Black's
+    winrate: f32,
+    score_lead: f32,
+    score_selfplay: Option<f32>,
+    score_stdev: Option<f32>,
+    no_result_prob: Option<f32>,
+    short_term_winrate_error: Option<f32>,
+    short_term_score_error: Option<f32>,
+}
+
+impl RawEvaluation {
+    pub fn winrate_for(&self, player: Player) -> f32 {
+        for_player(self.winrate, player, |winrate| 1.0 - winrate)
+    }
+
+    pub fn score_lead_for(&self, player: Player) -> f32 {
+        for_player(self.score_lead, player, |lead| -lead)
+    }
+
+    pub fn score_selfplay_for(&self, player: Player) -> Option<f32> {
+        Some(for_player(self.score_selfplay?, player, |score| -score))
+    }
+
+    pub fn score_stdev(&self) -> Option<f32> {
+        self.score_stdev
+    }
+
+    // Of the game ending without a result, only under rules with no result outcomes
+    pub fn no_result_prob(&self) -> Option<f32> {
+        self.no_result_prob
+    }
+
+    // The net's estimate of how far a search would move its winrate and score lead, high in
+    // unsettled positions
+    pub fn short_term_winrate_error(&self) -> Option<f32> {
+        self.short_term_winrate_error
+    }
+
+    pub fn short_term_score_error(&self) -> Option<f32> {
+        self.short_term_score_error
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    id: QueryId,
+    turn: u32,
+    is_final: bool,
+    player: Player,
+    visits: u64,
+    // Black's
+    winrate: f32,
+    score_lead: f32,
+    score_stdev: Option<f32>,
+    candidates: Vec<CandidateMove>,
+    ownership: Option<Vec<f32>>,
+    policy: Option<Vec<f32>>,
+    human_policy: Option<Vec<f32>>,
+    raw: Option<RawEvaluation>,
+}
+
+impl Analysis {
+    // None for responses without results, or when the side to move isn't known but values were
+    // reported for it. Results without a known perspective are taken as reported for black.
+    pub fn from_response(response: &KataResponse) -> Option<Self> {
+        let KataResponse::Result {
+            id,
+            is_during_search,
+            turn_number,
+            move_infos,
+            root_info,
+            ownership,
+            policy,
+            human_policy,
+            perspective,
+            ..
+        } = response
+        else {
+            return None;
+        };
+        let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+        let reported_for = reported_as.player(root_info.current_player)?;
+        let player = root_info.current_player.unwrap_or(reported_for);
+        let black = |value: f32, flip: fn(f32) -> f32| match reported_for {
+            Player::Black => value,
+            Player::White => flip(value),
+        };
+        let candidates = move_infos
+            .iter()
+            .filter_map(|move_info| candidate(move_info, black))
+            .collect();
+        Some(Self {
+            id: id.clone(),
+            turn: *turn_number,
+            is_final: !is_during_search,
+            player,
+            visits: root_info.visits,
+            winrate: black(root_info.winrate, |winrate| 1.0 - winrate),
+            score_lead: black(root_info.score_lead, |lead| -lead),
+            score_stdev: root_info.score_stdev,
+            candidates,
+            ownership: ownership.as_ref().map(|ownership| {
+                ownership
+                    .iter()
+                    .map(|owner| black(*owner, |owner| -owner))
+                    .collect()
+            }),
+            policy: policy.clone(),
+            human_policy: human_policy.clone(),
+            raw: raw(root_info, black),
+        })
+    }
+
+    pub fn id(&self) -> &QueryId {
+        &self.id
+    }
+
+    pub fn turn(&self) -> u32 {
+        self.turn
+    }
+
+    // False for interim results
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+
+    // The side to move
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    pub fn visits(&self) -> u64 {
+        self.visits
+    }
+
+    pub fn winrate_for(&self, player: Player) -> f32 {
+        for_player(self.winrate, player, |winrate| 1.0 - winrate)
+    }
+
+    pub fn score_lead_for(&self, player: Player) -> f32 {
+        for_player(self.score_lead, player, |lead| -lead)
+    }
+
+    pub fn score_stdev(&self) -> Option<f32> {
+        self.score_stdev
+    }
+
+    // In katago's order, best first
+    pub fn candidates(&self) -> &[CandidateMove] {
+        &self.candidates
+    }
+
+    pub fn best(&self) -> Option<&CandidateMove> {
+        self.candidates.first()
+    }
+
+    pub fn candidate(&self, point: Move) -> Option<&CandidateMove> {
+        self.candidates
+            .iter()
+            .find(|candidate| candidate.point == point)
+    }
+
+    // Katago's layout, positive for black
+    pub fn ownership(&self) -> Option<&[f32]> {
+        self.ownership.as_deref()
+    }
+
+    // Katago's layout with the pass last, negative for illegal moves
+    pub fn policy(&self) -> Option<&[f32]> {
+        self.policy.as_deref()
+    }
+
+    // Laid out like `policy`, for queries with a `humanSLProfile`
+    pub fn human_policy(&self) -> Option<&[f32]> {
+        self.human_policy.as_deref()
+    }
+
+    // None from katago versions which don't report it
+    pub fn raw(&self) -> Option<&RawEvaluation> {
+        self.raw.as_ref()
+    }
+}
+
+impl QueryHandle {
+    // Like `result`, for queries whose position had results
+    pub async fn analysis(self) -> Result<Analysis, ClientError> {
+        let result = self.result().await?;
+        Analysis::from_response(&result).ok_or_else(|| {
+            ClientError::Unsupported("katago reported no analysis for the position".to_owned())
+        })
+    }
+}
+
+// Moves katago reports that don't parse are left out
+fn candidate(
+    move_info: &MoveInfo,
+    black: impl Fn(f32, fn(f32) -> f32) -> f32,
+) -> Option<CandidateMove> {
+    Some(CandidateMove {
+        point: move_info.r#move.parse().ok()?,
+        visits: move_info.visits,
+        winrate: black(move_info.winrate, |winrate| 1.0 - winrate),
+        score_lead: black(move_info.score_lead, |lead| -lead),
+        prior: move_info.prior,
+        order: move_info.order,
+        pv: move_info
+            .pv
+            .iter()
+            .filter_map(|played| played.parse().ok())
+            .collect(),
+    })
+}
+
+fn raw(root_info: &RootInfo, black: impl Fn(f32, fn(f32) -> f32) -> f32) -> Option<RawEvaluation> {
+    Some(RawEvaluation {
+        winrate: black(root_info.raw_winrate?, |winrate| 1.0 - winrate),
+        score_lead: black(root_info.raw_lead?, |lead| -lead),
+        score_selfplay: root_info
+            .raw_score_selfplay
+            .map(|score| black(score, |score| -score)),
+        score_stdev: root_info.raw_score_selfplay_stdev,
+        no_result_prob: root_info.raw_no_result_prob,
+        short_term_winrate_error: root_info.raw_st_wr_error,
+        short_term_score_error: root_info.raw_st_score_error,
+    })
+}
+
+fn for_player(black: f32, player: Player, flip: fn(f32) -> f32) -> f32 {
+    match player {
+        Player::Black => black,
+        Player::White => flip(black),
+    }
+}
@@ -0,0 +1,340 @@
+// Life and death statuses rulesets settle differently than katago's search would on its own. The
+// shapes are found on the board, and only count when katago's ownership of their stones stays
+// undecided across probes, so groups which are simply alive or dead aren't reported.
+//
+// Seki: groups of both colors sharing liberties, neither able to approach the other. Bent four
+// in the corner: a group whose only eye space is four points bent around the corner with enemy
+// stones inside, which the attacker can turn into a ko whenever they like. Moonshine life: a
+// group with one eye whose second depends on a ko the attacker can't be forced to resolve.
+
+use std::collections::HashSet;
+
+use crate::{
+    Board, Client, ClientError, KataQuery, Move, Player, QueryLane, ReportAnalysisWinratesAs, Rules,
+};
+
+// Stones owned less surely than this count as undecided
+const UNDECIDED: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SpecialKind {
+    Seki,
+    BentFourInTheCorner,
+    MoonshineLife,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpecialOutcome {
+    // Both sides live, the liberties they share count for nobody. Under territory scoring the
+    // eyes inside the seki don't count either.
+    Seki { eyes_count: bool },
+    // Dead without being played out, e.g. bent four under Japanese rules
+    DeadByRule,
+    // Has to be resolved on the board, usually as a ko
+    PlayedOut,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpecialPosition {
+    pub kind: SpecialKind,
+    pub outcome: SpecialOutcome,
+    // The stones involved, for seki those of both colors
+    pub stones: Vec<Move>,
+}
+
+struct Group {
+    player: Player,
+    stones: Vec<usize>,
+    liberties: HashSet<usize>,
+}
+
+// `samples` are ownership arrays from black's point of view, e.g. of the position before and
+// after a pass. Shapes are only reported when their stones are undecided in every sample, or for
+// the ko based shapes in any of them.
+pub fn find_special_positions(
+    board: &Board,
+    samples: &[&[f32]],
+    rules: Rules,
+) -> Vec<SpecialPosition> {
+    let points = board.x_size() as usize * board.y_size() as usize;
+    let samples = samples
+        .iter()
+        .filter(|sample| sample.len() == points)
+        .collect::<Vec<_>>();
+    if samples.is_empty() {
+        return Vec::new();
+    }
+    let always_undecided = |stones: &[usize]| {
+        stones.iter().all(|index| {
+            samples
+                .iter()
+                .all(|sample| sample[*index].abs() < UNDECIDED)
+        })
+    };
+    let ever_undecided = |stones: &[usize]| {
+        samples
+            .iter()
+            .any(|sample| stones.iter().all(|index| sample[*index].abs() < UNDECIDED))
+    };
+    let territory_scoring = matches!(rules, Rules::Japanese | Rules::Korean);
+    let by_rule = if territory_scoring {
+        SpecialOutcome::DeadByRule
+    } else {
+        SpecialOutcome::PlayedOut
+    };
+
+    let groups = groups(board);
+    let mut special = seki(board, &groups, &always_undecided)
+        .into_iter()
+        .map(|stones| SpecialPosition {
+            kind: SpecialKind::Seki,
+            outcome: SpecialOutcome::Seki {
+                eyes_count: !territory_scoring,
+            },
+            stones: stones.into_iter().map(|index| board.point(index)).collect(),
+        })
+        .collect::<Vec<_>>();
+    for group in &groups {
+        if !ever_undecided(&group.stones) {
+            continue;
+        }
+        let kind = if is_bent_four_in_the_corner(board, group) {
+            SpecialKind::BentFourInTheCorner
+        } else if is_moonshine_life(board, group) {
+            SpecialKind::MoonshineLife
+        } else {
+            continue;
+        };
+        special.push(SpecialPosition {
+            kind,
+            outcome: by_rule,
+            stones: group
+                .stones
+                .iter()
+                .map(|index| board.point(*index))
+                .collect(),
+        });
+    }
+    special
+}
+
+// Probes the position at the end of `query`'s moves and after the side to move passes, so a group
+// only looks undecided if it stays that way when its owner doesn't answer
+pub async fn probe_special_positions(
+    client: &Client,
+    query: &KataQuery,
+) -> Result<Vec<SpecialPosition>, ClientError> {
+    let board = replay(query);
+    let to_move = query.player_to_move();
+    let mut samples = Vec::new();
+    for pass in [None, Some((to_move, Move::Pass))] {
+        let mut probe = query.clone();
+        probe.id = client.next_id();
+        probe.moves.extend(pass);
+        probe.analyze_turns = None;
+        probe.include_ownership = Some(true);
+        probe.report_during_search_every = None;
+        probe.priorities = None;
+        probe.priority = None;
+        let mut result = client.submit_to(QueryLane::Batch, probe)?.result().await?;
+        // Katago's default, when the client wasn't told how the engine reports
+        result.tag_perspective(ReportAnalysisWinratesAs::Black);
+        samples.extend(result.ownership_for(Player::Black));
+    }
+    let samples = samples.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    Ok(find_special_positions(&board, &samples, query.rules))
+}
+
+// Moves which don't fit the board are skipped, katago would have rejected them anyway
+fn replay(query: &KataQuery) -> Board {
+    let mut board = Board::new(query.board_x_size, query.board_y_size);
+    for (player, stone) in query.initial_stones.iter().flatten() {
+        let _ = board.place(*player, *stone);
+    }
+    for (player, played) in &query.moves {
+        let _ = board.play(*player, *played);
+    }
+    board
+}
+
+fn groups(board: &Board) -> Vec<Group> {
+    let points = board.x_size() as usize * board.y_size() as usize;
+    let mut seen = vec![false; points];
+    let mut groups = Vec::new();
+    for index in 0..points {
+        let Some(player) = board.get(board.point(index)) else {
+            continue;
+        };
+        if seen[index] {
+            continue;
+        }
+        let (stones, _) = board.group(index);
+        let mut liberties = HashSet::new();
+        for stone in &stones {
+            seen[*stone] = true;
+            liberties.extend(
+                board
+                    .neighbours(*stone)
+                    .filter(|neighbour| board.get(board.point(*neighbour)).is_none()),
+            );
+        }
+        groups.push(Group {
+            player,
+            stones,
+            liberties,
+        });
+    }
+    groups
+}
+
+// Undecided groups joined by shared liberties, kept when both colors take part. Stones inside an
+// eye space which also has a point of its own for the surrounding group, like the one in a bent
+// four, are waiting to be captured rather than in seki.
+fn seki(board: &Board, groups: &[Group], undecided: &dyn Fn(&[usize]) -> bool) -> Vec<Vec<usize>> {
+    let mut captive = HashSet::new();
+    for group in groups {
+        for space in eye_spaces(board, group) {
+            let (empty, stones): (Vec<usize>, Vec<usize>) = space
+                .into_iter()
+                .partition(|index| board.get(board.point(*index)).is_none());
+            let private = empty.iter().any(|index| {
+                board
+                    .neighbours(*index)
+                    .all(|neighbour| !stones.contains(&neighbour))
+            });
+            if private {
+                captive.extend(stones);
+            }
+        }
+    }
+    let candidates = groups
+        .iter()
+        .filter(|group| undecided(&group.stones) && !captive.contains(&group.stones[0]))
+        .collect::<Vec<_>>();
+    let mut assigned = vec![false; candidates.len()];
+    let mut clusters = Vec::new();
+    for start in 0..candidates.len() {
+        if assigned[start] {
+            continue;
+        }
+        assigned[start] = true;
+        let mut cluster = vec![start];
+        let mut next = 0;
+        while let Some(&member) = cluster.get(next) {
+            next += 1;
+            for (other, group) in candidates.iter().enumerate() {
+                let shares = group.player != candidates[member].player
+                    && !group.liberties.is_disjoint(&candidates[member].liberties);
+                if !assigned[other] && shares {
+                    assigned[other] = true;
+                    cluster.push(other);
+                }
+            }
+        }
+        if cluster.len() > 1 {
+            clusters.push(
+                cluster
+                    .into_iter()
+                    .flat_map(|member| candidates[member].stones.iter().copied())
+                    .collect(),
+            );
+        }
+    }
+    clusters
+}
+
+// The group's eye spaces, the small regions of points around it surrounded by its stones alone.
+// Enemy stones inside count as part of them.
+fn eye_spaces(board: &Board, group: &Group) -> Vec<Vec<usize>> {
+    let points = board.x_size() as usize * board.y_size() as usize;
+    let own = group.stones.iter().copied().collect::<HashSet<_>>();
+    let mut seen = vec![false; points];
+    let mut regions = Vec::new();
+    for start in group.liberties.iter().copied() {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut region = vec![start];
+        let mut enclosed = true;
+        let mut next = 0;
+        while let Some(&index) = region.get(next) {
+            next += 1;
+            for neighbour in board.neighbours(index) {
+                if own.contains(&neighbour) || seen[neighbour] {
+                    continue;
+                }
+                if board.get(board.point(neighbour)) == Some(group.player) {
+                    // Another group of the same color borders it
+                    enclosed = false;
+                    continue;
+                }
+                seen[neighbour] = true;
+                region.push(neighbour);
+            }
+            // Regions reaching far are the outside, not eye space
+            if region.len() > 8 {
+                enclosed = false;
+            }
+        }
+        if enclosed {
+            regions.push(region);
+        }
+    }
+    regions
+}
+
+fn is_bent_four_in_the_corner(board: &Board, group: &Group) -> bool {
+    let [region] = &eye_spaces(board, group)[..] else {
+        return false;
+    };
+    let inside = |player| {
+        region
+            .iter()
+            .any(|index| board.get(board.point(*index)) == player)
+    };
+    inside(Some(group.player.opponent())) && inside(None) && is_bent_four(board, region)
+}
+
+// Four points in an L, three along one edge and one turning along the other, bent at the corner
+fn is_bent_four(board: &Board, region: &[usize]) -> bool {
+    if region.len() != 4 {
+        return false;
+    }
+    let (x_size, y_size) = (board.x_size(), board.y_size());
+    let coordinates = region
+        .iter()
+        .filter_map(|index| board.point(*index).coordinates())
+        .collect::<Vec<_>>();
+    let corner = |(x, y): (u8, u8)| (x == 0 || x == x_size - 1) && (y == 0 || y == y_size - 1);
+    let Some(&(corner_x, corner_y)) = coordinates.iter().find(|point| corner(**point)) else {
+        return false;
+    };
+    let along_x = coordinates.iter().filter(|(_, y)| *y == corner_y).count();
+    let along_y = coordinates.iter().filter(|(x, _)| *x == corner_x).count();
+    // Both counts include the corner
+    matches!((along_x, along_y), (3, 2) | (2, 3))
+}
+
+// One eye of a single point, and a ko on one of the group's outside liberties
+fn is_moonshine_life(board: &Board, group: &Group) -> bool {
+    let [eye] = &eye_spaces(board, group)[..] else {
+        return false;
+    };
+    if eye.len() != 1 || board.get(board.point(eye[0])).is_some() {
+        return false;
+    }
+    group
+        .liberties
+        .iter()
+        .filter(|liberty| **liberty != eye[0])
+        .any(|liberty| {
+            let point = board.point(*liberty);
+            [group.player, group.player.opponent()]
+                .into_iter()
+                .any(|player| {
+                    let mut board = board.clone();
+                    board.play(player, point).is_ok() && board.ko().is_some()
+                })
+        })
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+use crate::{KataResponse, QueryId};
+
+// Demultiplexes a response stream into one sub-stream per query, see `split_by_id`
+pub struct SplitById<St: Stream> {
+    stream: St,
+    streams: HashMap<QueryId, mpsc::UnboundedSender<St::Item>>,
+}
+
+// Responses for a single query. Ends after the final result, error or terminate ack for it,
+// warnings don't end it.
+pub struct QueryStream<T = KataResponse> {
+    rx: mpsc::UnboundedReceiver<T>,
+}
+
+// A new sub-stream is yielded the first time a query's id shows up, following responses with that
+// id go to it until it completes. Queries analyzing several turns therefore yield one sub-stream
+// per turn, all with the same id. Responses without an id are dropped, as are responses for
+// dropped sub-streams and terminate acks for queries without a sub-stream still open, which already
+// ended. Sub-streams only receive responses while the splitter itself is polled.
+// Stamped responses, see `stamp_received`, keep their stamps.
+pub fn split_by_id<St>(stream: St) -> SplitById<St>
+where
+    St: Stream + Unpin,
+    St::Item: AsRef<KataResponse>,
+{
+    SplitById {
+        stream,
+        streams: HashMap::new(),
+    }
+}
+
+impl<St: Stream> SplitById<St> {
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St> Stream for SplitById<St>
+where
+    St: Stream + Unpin,
+    St::Item: AsRef<KataResponse>,
+{
+    type Item = (QueryId, QueryStream<St::Item>);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let Some(response) = ready!(self.stream.poll_next_unpin(cx)) else {
+                // Ends every sub-stream still waiting for responses
+                self.streams.clear();
+                return Poll::Ready(None);
+            };
+            // Terminate acks belong to the query they terminated
+            let (id, last) = match response.as_ref() {
+                KataResponse::TerminateAck { terminate_id, .. } => {
+                    if !self.streams.contains_key(terminate_id) {
+                        continue;
+                    }
+                    (terminate_id.clone(), true)
+                }
+                KataResponse::Warning { id: Some(id), .. } => (id.clone(), false),
+                response => match response.id() {
+                    Some(id) => (id.clone(), !response.is_during_search()),
+                    None => continue,
+                },
+            };
+
+            if let Some(tx) = self.streams.get(&id) {
+                let _ = tx.send(response);
+                if last {
+                    self.streams.remove(&id);
+                }
+                continue;
+            }
+            let (tx, rx) = mpsc::unbounded_channel();
+            let _ = tx.send(response);
+            if !last {
+                self.streams.insert(id.clone(), tx);
+            }
+            return Poll::Ready(Some((id, QueryStream { rx })));
+        }
+    }
+}
+
+impl<T> Stream for QueryStream<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+
+    use super::*;
+
+    fn response(json: &str) -> KataResponse {
+        KataResponse::from_json(json).unwrap()
+    }
+
+    fn result(id: &str) -> KataResponse {
+        response(&format!(
+            r#"{{"id":"{id}","isDuringSearch":false,"turnNumber":0,"moveInfos":[],"rootInfo":{{"winrate":0.5,"scoreLead":0.0,"scoreSelfplay":0.0,"utility":0.0,"visits":1,"currentPlayer":"B"}}}}"#
+        ))
+    }
+
+    fn ack(id: &str, terminate_id: &str) -> KataResponse {
+        response(&format!(
+            r#"{{"id":"{id}","action":"terminate","terminateId":"{terminate_id}"}}"#
+        ))
+    }
+
+    fn split(responses: Vec<KataResponse>) -> Vec<(QueryId, Vec<KataResponse>)> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let streams = split_by_id(stream::iter(responses))
+                .collect::<Vec<_>>()
+                .await;
+            let mut split = Vec::new();
+            for (id, stream) in streams {
+                split.push((id, stream.collect().await));
+            }
+            split
+        })
+    }
+
+    #[test]
+    fn warnings_keep_the_stream_open() {
+        let split = split(vec![
+            response(r#"{"id":"q","warning":"Unknown field","field":"overrideSetting"}"#),
+            result("q"),
+        ]);
+        assert_eq!(split.len(), 1);
+        assert_eq!(split[0].0.as_str(), "q");
+        assert_eq!(split[0].1.len(), 2);
+    }
+
+    #[test]
+    fn errors_end_the_stream() {
+        let split = split(vec![
+            response(r#"{"id":"q","error":"Bad field","field":"moves"}"#),
+            result("q"),
+        ]);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].1.len(), 1);
+    }
+
+    #[test]
+    fn acks_after_the_end_are_dropped() {
+        let split = split(vec![
+            result("q"),
+            ack("t", "q"),
+            response(r#"{"id":"r","warning":"Unknown field"}"#),
+            ack("u", "r"),
+        ]);
+        assert_eq!(
+            split
+                .iter()
+                .map(|(id, responses)| (id.as_str(), responses.len()))
+                .collect::<Vec<_>>(),
+            [("q", 1), ("r", 2)]
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::KataResponse;
+
+// Server-sent events framing for responses, for relaying a query's progress to a web UI through
+// whatever HTTP server hosts it. Events are named `interim`, `final`, `warning` or `error` after
+// the response, and carry the response's JSON as data. The id is sent as the event id unless it
+// has line breaks.
+pub fn sse_event(response: &KataResponse) -> String {
+    let event = match response {
+        KataResponse::Error { .. } => "error",
+        KataResponse::Warning { .. } => "warning",
+        response if response.is_during_search() => "interim",
+        _ => "final",
+    };
+    // Serialized JSON never contains raw newlines, so the data fits on one line
+    let data = serde_json::to_string(response).unwrap();
+    // A line break in the id would end the field early and let the rest pose as other fields,
+    // browsers ignore ids with NUL in them anyway
+    let id = response
+        .id()
+        .map(|id| id.to_string())
+        .filter(|id| !id.contains(['\r', '\n', '\0']));
+    match id {
+        Some(id) => format!("event: {event}\nid: {id}\ndata: {data}\n\n"),
+        None => format!("event: {event}\ndata: {data}\n\n"),
+    }
+}
+
+// E.g. over a `QueryHandle`, which ends after the query's last final result
+pub fn sse_stream<St>(stream: St) -> impl Stream<Item = String>
+where
+    St: Stream<Item = KataResponse>,
+{
+    stream.map(|response| sse_event(&response))
+}
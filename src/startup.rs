@@ -0,0 +1,139 @@
+// What katago is doing before it's ready, read from its log lines, for a loading bar in the
+// 10 to 60 seconds startup takes. Only OpenCL's tuning, done on the first start with a model,
+// says how far along it is, the other phases are shown as they're reached.
+
+#[cfg(feature = "process")]
+use std::pin::Pin;
+#[cfg(feature = "process")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "process")]
+use futures_core::Stream;
+
+use crate::LogEvent;
+#[cfg(feature = "process")]
+use crate::LogEvents;
+
+// In the order katago goes through them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StartupPhase {
+    Starting,
+    LoadingModel,
+    // Finding GPUs, building TensorRT engines
+    InitializingBackend,
+    // OpenCL's parameter tuning
+    Tuning,
+    Ready,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartupProgress {
+    pub phase: StartupPhase,
+    // Only known while tuning, of the kernel being tuned, katago tunes several in turn
+    pub fraction: Option<f32>,
+    // The log line it was read from
+    pub message: String,
+}
+
+impl StartupProgress {
+    // None for lines which don't tell anything about startup
+    pub fn from_log(event: &LogEvent) -> Option<Self> {
+        let message = event.message.as_str();
+        let lowercase = message.to_lowercase();
+        let (phase, fraction) = if lowercase.contains("ready to begin handling requests")
+            || lowercase.contains("gtp ready")
+        {
+            (StartupPhase::Ready, None)
+        } else if lowercase.contains("done tuning") {
+            (StartupPhase::Tuning, Some(1.0))
+        } else if let Some(fraction) = tuning_fraction(message) {
+            (StartupPhase::Tuning, Some(fraction))
+        } else if lowercase.contains("tuning") {
+            (StartupPhase::Tuning, None)
+        } else if event.subsystem.is_some() || lowercase.contains("backend") {
+            (StartupPhase::InitializingBackend, None)
+        } else if lowercase.contains("nnmodelfile")
+            || lowercase.contains("neural net")
+            || lowercase.contains("loading model")
+            || lowercase.contains("loaded model")
+            || lowercase.starts_with("model name")
+        {
+            (StartupPhase::LoadingModel, None)
+        } else if lowercase.starts_with("katago v") || lowercase.starts_with("loaded config") {
+            (StartupPhase::Starting, None)
+        } else {
+            return None;
+        };
+        Some(Self {
+            phase,
+            fraction,
+            message: message.to_owned(),
+        })
+    }
+}
+
+// `Tuning 9/84 Calls/sec ...` lines count the configurations tried so far
+fn tuning_fraction(message: &str) -> Option<f32> {
+    let rest = message.split_once("Tuning ")?.1;
+    let (done, rest) = rest.split_once('/')?;
+    let total = rest.split_whitespace().next()?;
+    let (done, total) = (done.trim().parse::<f32>().ok()?, total.parse::<f32>().ok()?);
+    (total > 0.0).then(|| (done / total).min(1.0))
+}
+
+#[cfg(feature = "process")]
+impl LogEvents {
+    // Yields startup progress until the engine is ready, see `StartupEvents::into_inner` for the
+    // log lines after that
+    pub fn startup(self) -> StartupEvents {
+        StartupEvents {
+            logs: self,
+            phase: None,
+            done: false,
+        }
+    }
+}
+
+// Phases never go back, a log line of an earlier phase after a later one only updates the message
+#[cfg(feature = "process")]
+pub struct StartupEvents {
+    logs: LogEvents,
+    phase: Option<StartupPhase>,
+    done: bool,
+}
+
+#[cfg(feature = "process")]
+impl StartupEvents {
+    pub fn into_inner(self) -> LogEvents {
+        self.logs
+    }
+}
+
+#[cfg(feature = "process")]
+impl Stream for StartupEvents {
+    type Item = StartupProgress;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        loop {
+            let Some(event) = std::task::ready!(self.logs.rx.poll_recv(cx)) else {
+                self.done = true;
+                return Poll::Ready(None);
+            };
+            let Some(mut progress) = StartupProgress::from_log(&event) else {
+                continue;
+            };
+            match self.phase {
+                Some(phase) if phase > progress.phase => {
+                    progress.phase = phase;
+                    progress.fraction = None;
+                }
+                _ => self.phase = Some(progress.phase),
+            }
+            self.done = progress.phase == StartupPhase::Ready;
+            return Poll::Ready(Some(progress));
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// Rollups over many analyzed games, e.g. average points lost per rank or how often blunders
+// happen in the endgame. Statistics of separate runs can be merged, so databases can be reduced
+// in parallel.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ogs::Review;
+use crate::sgf::SgfGame;
+use crate::{Annotation, Annotator, GamePhase, KataResponse, Move, Player};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveStats {
+    // Moves with results before and after them
+    pub moves: u64,
+    // Gains are counted as nothing lost, they're mostly the engine's search being uncertain
+    pub points_lost: f64,
+    pub mistakes: u64,
+    pub blunders: u64,
+    // Moves with a result before them, and how many of those were the engine's top move
+    pub compared: u64,
+    pub top_move_matches: u64,
+}
+
+impl MoveStats {
+    pub fn average_points_lost(&self) -> Option<f64> {
+        (self.moves > 0).then(|| self.points_lost / self.moves as f64)
+    }
+
+    pub fn blunder_rate(&self) -> Option<f64> {
+        (self.moves > 0).then(|| self.blunders as f64 / self.moves as f64)
+    }
+
+    pub fn top_move_match_rate(&self) -> Option<f64> {
+        (self.compared > 0).then(|| self.top_move_matches as f64 / self.compared as f64)
+    }
+
+    pub fn merge(&mut self, other: &MoveStats) {
+        self.moves += other.moves;
+        self.points_lost += other.points_lost;
+        self.mistakes += other.mistakes;
+        self.blunders += other.blunders;
+        self.compared += other.compared;
+        self.top_move_matches += other.top_move_matches;
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DatasetStats {
+    pub games: u64,
+    // Keyed by the labels games were added with, e.g. player names or ranks
+    pub by_label: BTreeMap<String, MoveStats>,
+    pub by_phase: BTreeMap<GamePhase, MoveStats>,
+    annotator: Annotator,
+}
+
+impl DatasetStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Decides what counts as a mistake or a blunder
+    pub fn annotator(mut self, annotator: Annotator) -> Self {
+        self.annotator = annotator;
+        self
+    }
+
+    // Results are the final results of any of the game's turns. Each side's moves are counted
+    // under its label, and under the phase the game was in, which uses ownership when results
+    // include it.
+    pub fn add_game<'a>(
+        &mut self,
+        game: &SgfGame,
+        results: impl IntoIterator<Item = &'a KataResponse>,
+        black_label: &str,
+        white_label: &str,
+    ) {
+        let results = results.into_iter().collect::<Vec<_>>();
+        let review =
+            Review::from_results(results.iter().copied(), &game.moves, game.board_y_size, 0)
+                .annotate(&game.moves, &self.annotator);
+        let mut top_moves = HashMap::new();
+        let mut ownership = HashMap::new();
+        for result in results {
+            if let KataResponse::Result {
+                turn_number,
+                move_infos,
+                ownership: turn_ownership,
+                ..
+            } = result
+            {
+                if let Some(turn_ownership) = turn_ownership {
+                    ownership.insert(*turn_number, turn_ownership.as_slice());
+                }
+                if let Some(top) = move_infos.iter().min_by_key(|move_info| move_info.order) {
+                    top_moves.insert(*turn_number, top.r#move.parse::<Move>().ok());
+                }
+            }
+        }
+
+        self.games += 1;
+        for (move_number, (player, played)) in game.moves.iter().enumerate() {
+            let move_number = move_number as u32;
+            let mut stats = MoveStats::default();
+            if let Some(loss) = review.loss(move_number, *player) {
+                stats.moves = 1;
+                stats.points_lost = loss.points.max(0.0) as f64;
+                match review.moves[&move_number].annotation {
+                    Some(Annotation::Mistake) => stats.mistakes = 1,
+                    Some(Annotation::Blunder) => stats.blunders = 1,
+                    _ => {}
+                }
+            }
+            if let Some(top) = top_moves.get(&move_number) {
+                stats.compared = 1;
+                stats.top_move_matches = (*top == Some(*played)) as u64;
+            }
+            let label = match player {
+                Player::Black => black_label,
+                Player::White => white_label,
+            };
+            let phase = GamePhase::classify(
+                move_number,
+                game.board_x_size,
+                game.board_y_size,
+                ownership.get(&move_number).copied(),
+            );
+            self.by_label
+                .entry(label.to_owned())
+                .or_default()
+                .merge(&stats);
+            self.by_phase.entry(phase).or_default().merge(&stats);
+        }
+    }
+
+    pub fn merge(&mut self, other: &DatasetStats) {
+        self.games += other.games;
+        for (label, stats) in &other.by_label {
+            self.by_label.entry(label.clone()).or_default().merge(stats);
+        }
+        for (phase, stats) in &other.by_phase {
+            self.by_phase.entry(*phase).or_default().merge(stats);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+// One-line, human-readable descriptions of results for chat bots and the like, e.g.
+// `White is ahead by 3.5 points (72% winrate); best move R14, played move Q3 loses ~2.1 points`.
+// The wording comes from a `SummaryPhrases` implementation, so it can be translated.
+
+use crate::{KataResponse, Move, MoveInfo, Player, ReportAnalysisWinratesAs};
+
+// Leads smaller than this are reported as even
+const EVEN_LEAD: f32 = 0.05;
+
+pub trait SummaryPhrases {
+    // `winrate` is the leader's, from 0 to 1
+    fn ahead(&self, leader: Player, points: f32, winrate: f32) -> String;
+    // `winrate` is black's
+    fn even(&self, winrate: f32) -> String;
+    fn best_move(&self, best: &str) -> String;
+    // `loss` is None when the played move wasn't searched
+    fn played_move(&self, played: Move, loss: Option<f32>) -> String;
+}
+
+pub struct EnglishSummary;
+
+impl SummaryPhrases for EnglishSummary {
+    fn ahead(&self, leader: Player, points: f32, winrate: f32) -> String {
+        let leader = match leader {
+            Player::Black => "Black",
+            Player::White => "White",
+        };
+        format!(
+            "{leader} is ahead by {points:.1} points ({:.0}% winrate)",
+            winrate * 100.0
+        )
+    }
+
+    fn even(&self, winrate: f32) -> String {
+        format!(
+            "The game is even ({:.0}% winrate for Black)",
+            winrate * 100.0
+        )
+    }
+
+    fn best_move(&self, best: &str) -> String {
+        format!("best move {best}")
+    }
+
+    fn played_move(&self, played: Move, loss: Option<f32>) -> String {
+        match loss {
+            Some(loss) if loss >= EVEN_LEAD => {
+                format!("played move {played} loses ~{loss:.1} points")
+            }
+            Some(_) => format!("played move {played} is as good as the best"),
+            None => format!("played move {played} wasn't searched"),
+        }
+    }
+}
+
+// None for responses without results. `played` is the move played in the analyzed position, if
+// any. Results without a known perspective are taken as reported for black.
+pub fn summarize(result: &KataResponse, played: Option<Move>) -> Option<String> {
+    summarize_with(result, played, &EnglishSummary)
+}
+
+pub fn summarize_with(
+    result: &KataResponse,
+    played: Option<Move>,
+    phrases: &dyn SummaryPhrases,
+) -> Option<String> {
+    let KataResponse::Result {
+        move_infos,
+        root_info,
+        perspective,
+        ..
+    } = result
+    else {
+        return None;
+    };
+    let reported_as = perspective.unwrap_or(ReportAnalysisWinratesAs::Black);
+    let current_player = root_info.current_player;
+    let lead = root_info.score_lead_for(Player::Black, reported_as)?;
+    let winrate = root_info.winrate_for(Player::Black, reported_as)?;
+    let mut summary = if lead.abs() < EVEN_LEAD {
+        phrases.even(winrate)
+    } else if lead > 0.0 {
+        phrases.ahead(Player::Black, lead, winrate)
+    } else {
+        phrases.ahead(Player::White, -lead, 1.0 - winrate)
+    };
+
+    let best = move_infos.iter().min_by_key(|move_info| move_info.order);
+    if let Some(best) = best {
+        summary.push_str("; ");
+        summary.push_str(&phrases.best_move(&best.r#move));
+    }
+    if let Some(played) = played {
+        // Losses are from the point of view of the player who played the move
+        let loss = current_player.zip(best).and_then(|(mover, best)| {
+            let played = move_infos
+                .iter()
+                .find(|move_info| move_info.r#move.parse::<Move>().ok() == Some(played))?;
+            let score =
+                |move_info: &MoveInfo| move_info.score_lead_for(mover, reported_as, current_player);
+            Some(score(best)? - score(played)?)
+        });
+        summary.push_str(if best.is_some() { ", " } else { "; " });
+        summary.push_str(&phrases.played_move(played, loss));
+    }
+    Some(summary)
+}
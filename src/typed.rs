@@ -0,0 +1,73 @@
+// A query builder that tracks which required fields are set in its type, so a missing one is a
+// compile error instead of an error from `build()`:
+//
+//     KataQuery::typed_builder(id)
+//         .moves(moves)
+//         .rules(Rules::Japanese)
+//         .board_size(19, 19)
+//         .with(|query| {
+//             query.komi(6.5);
+//         })
+//         .build()
+
+use std::marker::PhantomData;
+
+use crate::{KataQuery, KataQueryBuilder, KataQueryBuilderError, Move, Player, QueryId, Rules};
+
+pub struct Unset;
+pub struct Set;
+
+pub struct QueryBuilder<HasMoves = Unset, HasRules = Unset, HasBoardSize = Unset> {
+    inner: KataQueryBuilder,
+    state: PhantomData<(HasMoves, HasRules, HasBoardSize)>,
+}
+
+impl KataQuery {
+    pub fn typed_builder(id: impl Into<QueryId>) -> QueryBuilder {
+        let mut inner = KataQuery::builder();
+        inner.id(id.into());
+        QueryBuilder {
+            inner,
+            state: PhantomData,
+        }
+    }
+}
+
+impl<M, R, S> QueryBuilder<M, R, S> {
+    fn state<M2, R2, S2>(self) -> QueryBuilder<M2, R2, S2> {
+        QueryBuilder {
+            inner: self.inner,
+            state: PhantomData,
+        }
+    }
+
+    pub fn moves(mut self, moves: impl Into<Vec<(Player, Move)>>) -> QueryBuilder<Set, R, S> {
+        self.inner.moves(moves);
+        self.state()
+    }
+
+    pub fn rules(mut self, rules: Rules) -> QueryBuilder<M, Set, S> {
+        self.inner.rules(rules);
+        self.state()
+    }
+
+    pub fn board_size(mut self, board_x_size: u8, board_y_size: u8) -> QueryBuilder<M, R, Set> {
+        self.inner
+            .board_x_size(board_x_size)
+            .board_y_size(board_y_size);
+        self.state()
+    }
+
+    // Optional fields are set on the untyped builder
+    pub fn with(mut self, f: impl FnOnce(&mut KataQueryBuilder)) -> Self {
+        f(&mut self.inner);
+        self
+    }
+}
+
+impl QueryBuilder<Set, Set, Set> {
+    // Can still fail on moves that don't fit the board
+    pub fn build(self) -> Result<KataQuery, KataQueryBuilderError> {
+        self.inner.build()
+    }
+}
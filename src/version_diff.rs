@@ -0,0 +1,156 @@
+// Comparing stored results of the same positions from two engine or model versions, e.g. before
+// upgrading the model of an analysis server. Positions are matched by query id and turn, only the
+// last final result of each is compared, interim ones are skipped. Values are black's, deltas are
+// the candidate's minus the baseline's.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{Analysis, KataResponse, Move, Player, QueryId};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PositionDiff {
+    pub id: QueryId,
+    pub turn: u32,
+    pub winrate_delta: f32,
+    pub score_delta: f32,
+    // None when a version reported no candidate moves
+    pub baseline_best: Option<Move>,
+    pub candidate_best: Option<Move>,
+}
+
+impl PositionDiff {
+    pub fn best_move_changed(&self) -> bool {
+        self.baseline_best != self.candidate_best
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VersionDiff {
+    // In id and turn order
+    pub positions: Vec<PositionDiff>,
+    // Positions only one of the versions has final results for
+    pub only_in_baseline: Vec<(QueryId, u32)>,
+    pub only_in_candidate: Vec<(QueryId, u32)>,
+}
+
+impl VersionDiff {
+    pub fn new<'a>(
+        baseline: impl IntoIterator<Item = &'a KataResponse>,
+        candidate: impl IntoIterator<Item = &'a KataResponse>,
+    ) -> Self {
+        let mut baseline = final_analyses(baseline);
+        let candidate = final_analyses(candidate);
+        let mut diff = Self::default();
+        for (key, after) in candidate {
+            let Some(before) = baseline.remove(&key) else {
+                diff.only_in_candidate.push(key);
+                continue;
+            };
+            diff.positions.push(PositionDiff {
+                id: key.0,
+                turn: key.1,
+                winrate_delta: after.winrate_for(Player::Black) - before.winrate_for(Player::Black),
+                score_delta: after.score_lead_for(Player::Black)
+                    - before.score_lead_for(Player::Black),
+                baseline_best: before.best().map(|best| best.point()),
+                candidate_best: after.best().map(|best| best.point()),
+            });
+        }
+        diff.only_in_baseline = baseline.into_keys().collect();
+        diff
+    }
+
+    pub fn changed_best_moves(&self) -> impl Iterator<Item = &PositionDiff> {
+        self.positions
+            .iter()
+            .filter(|position| position.best_move_changed())
+    }
+
+    // The `count` positions the versions disagree on most, by score and then winrate
+    pub fn largest_disagreements(&self, count: usize) -> Vec<&PositionDiff> {
+        let mut positions = self.positions.iter().collect::<Vec<_>>();
+        positions.sort_by(|a, b| {
+            b.score_delta
+                .abs()
+                .total_cmp(&a.score_delta.abs())
+                .then(b.winrate_delta.abs().total_cmp(&a.winrate_delta.abs()))
+        });
+        positions.truncate(count);
+        positions
+    }
+
+    pub fn mean_abs_winrate_delta(&self) -> Option<f32> {
+        self.mean(|position| position.winrate_delta.abs())
+    }
+
+    pub fn mean_abs_score_delta(&self) -> Option<f32> {
+        self.mean(|position| position.score_delta.abs())
+    }
+
+    // Of the compared positions
+    pub fn best_move_change_rate(&self) -> Option<f32> {
+        self.mean(|position| position.best_move_changed() as u8 as f32)
+    }
+
+    fn mean(&self, value: impl Fn(&PositionDiff) -> f32) -> Option<f32> {
+        (!self.positions.is_empty())
+            .then(|| self.positions.iter().map(value).sum::<f32>() / self.positions.len() as f32)
+    }
+}
+
+// A summary followed by the ten largest disagreements
+impl fmt::Display for VersionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} positions compared, {} only in baseline, {} only in candidate",
+            self.positions.len(),
+            self.only_in_baseline.len(),
+            self.only_in_candidate.len()
+        )?;
+        if let (Some(winrate), Some(score), Some(changed)) = (
+            self.mean_abs_winrate_delta(),
+            self.mean_abs_score_delta(),
+            self.best_move_change_rate(),
+        ) {
+            writeln!(
+                f,
+                "mean winrate delta {:.1}%, mean score delta {score:.2}, best move changed in {:.1}%",
+                winrate * 100.0,
+                changed * 100.0
+            )?;
+        }
+        writeln!(
+            f,
+            "{:>12} {:>4} {:>8} {:>7} {:>5} {:>5}",
+            "id", "turn", "winrate", "score", "was", "now"
+        )?;
+        let best =
+            |point: Option<Move>| point.map_or_else(|| "-".to_owned(), |point| point.to_string());
+        for position in self.largest_disagreements(10) {
+            writeln!(
+                f,
+                "{:>12} {:>4} {:>+7.1}% {:>+7.1} {:>5} {:>5}",
+                position.id.to_string(),
+                position.turn,
+                position.winrate_delta * 100.0,
+                position.score_delta,
+                best(position.baseline_best),
+                best(position.candidate_best)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn final_analyses<'a>(
+    results: impl IntoIterator<Item = &'a KataResponse>,
+) -> BTreeMap<(QueryId, u32), Analysis> {
+    results
+        .into_iter()
+        .filter(|result| !result.is_during_search())
+        .filter_map(Analysis::from_response)
+        .map(|analysis| ((analysis.id().clone(), analysis.turn()), analysis))
+        .collect()
+}